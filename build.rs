@@ -2,7 +2,7 @@ use std::process::Command;
 
 fn main() {
     Command::new("cargo")
-        .args(&["fmt", "--", "src/*.rs"])
+        .args(["fmt", "--", "src/*.rs"])
         .status()
         .expect("cargo fmt failed");
 }
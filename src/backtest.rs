@@ -0,0 +1,452 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::clock::{Clock, ManualClock};
+use crate::event::EventBus;
+use crate::grid::{self, GridContext, GridRegistry, StateStore};
+use crate::risk::RiskManager;
+use crate::trade::{
+    Account, Balance, BalanceGuard, Fill, Interval, Kline, KlineQuery, MarketService, Order,
+    Ticker24h, TradeService,
+};
+use crate::{Coin, RiskConfig, Symbol, TgError};
+
+/// 用历史 k 线回放一个币种的网格
+#[derive(Clone, Debug)]
+pub struct Backtest {
+    pub symbol: Symbol,
+    pub coin: Coin,
+    pub risk: RiskConfig,
+    /// 初始计价资产数量
+    pub quote: f64,
+    /// 初始基础资产数量
+    pub base: f64,
+    /// 手续费率，以计价资产收取
+    pub fee_rate: f64,
+}
+
+/// 回测结果
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct BacktestReport {
+    pub symbol: Symbol,
+    pub bars: usize,
+    pub buys: usize,
+    pub sells: usize,
+    pub fees: f64,
+    pub start_equity: f64,
+    pub end_equity: f64,
+    pub pnl: f64,
+    /// 收益率
+    pub return_ratio: f64,
+    /// 最大回撤比例
+    pub max_drawdown: f64,
+    /// 按 k 线收盘权益计算的年化夏普比率(无风险利率为0)
+    pub sharpe: f64,
+    pub quote: f64,
+    pub base: f64,
+}
+
+impl Backtest {
+    /// 以 k 线时间驱动的 `ManualClock` 回放 `bars`，网格中的等待只推进模拟时间
+    pub fn run(&self, registry: &GridRegistry, bars: &[Kline]) -> Result<BacktestReport> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(self.simulate(registry, bars))
+    }
+
+    async fn simulate(&self, registry: &GridRegistry, bars: &[Kline]) -> Result<BacktestReport> {
+        let first = bars
+            .first()
+            .ok_or_else(|| TgError::Internal("No k lines to backtest".to_string()))?;
+        let clock = Arc::new(ManualClock::new(bar_time(first)));
+        let sim = Arc::new(Mutex::new(SimState {
+            bars: bars.to_vec(),
+            cursor: 0,
+            price: first.open,
+            quote: self.quote,
+            base: self.base,
+            fee_rate: self.fee_rate,
+            ..SimState::default()
+        }));
+        let market: Arc<dyn MarketService> = Arc::new(SimMarket { sim: sim.clone() });
+        let trade: Arc<dyn TradeService> = Arc::new(SimTrade {
+            symbol: self.symbol.clone(),
+            sim: sim.clone(),
+            clock: clock.clone(),
+        });
+        let events = EventBus::default();
+        // 并行回测时用计数区分状态目录
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let state = std::env::temp_dir().join(format!(
+            "tgs-backtest-{}-{}-{}",
+            std::process::id(),
+            Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+            RUNS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let ctx = GridContext {
+            symbol: self.symbol.clone(),
+            market,
+            trade: trade.clone(),
+            events: events.clone(),
+            store: StateStore::new(&state),
+            risk: Arc::new(RiskManager::new(self.risk.clone(), events, clock.clone())),
+            balance: Arc::new(BalanceGuard::new(trade, BTreeMap::new(), clock.clone())),
+            clock: clock.clone(),
+        };
+        let mut grid = grid::factory(registry, ctx, &self.coin)?;
+
+        let start_equity = self.quote + self.base * first.open;
+        let (mut peak, mut max_drawdown) = (start_equity, 0.0_f64);
+        let mut equities = vec![start_equity];
+        for (i, bar) in bars.iter().enumerate() {
+            sim.lock().unwrap().cursor = i;
+            // 上一根 k 线内的等待可能已越过本根 k 线的开盘时间
+            clock.set(bar_time(bar));
+            for price in path(bar) {
+                sim.lock().unwrap().price = price;
+                if let Err(e) = grid.execute(price).await {
+                    warn!("Grid execute error: {}", e);
+                }
+            }
+            let equity = sim.lock().unwrap().equity(bar.close);
+            equities.push(equity);
+            peak = peak.max(equity);
+            if peak > 0.0 {
+                max_drawdown = max_drawdown.max((peak - equity) / peak);
+            }
+        }
+        let _ = std::fs::remove_dir_all(&state);
+
+        let sim = sim.lock().unwrap();
+        let end_equity = sim.equity(bars[bars.len() - 1].close);
+        Ok(BacktestReport {
+            symbol: self.symbol.clone(),
+            bars: bars.len(),
+            buys: sim.buys,
+            sells: sim.sells,
+            fees: sim.fees,
+            start_equity,
+            end_equity,
+            pnl: end_equity - start_equity,
+            return_ratio: (end_equity - start_equity) / start_equity,
+            max_drawdown,
+            sharpe: sharpe(&equities, bar_millis(bars)),
+            quote: sim.quote,
+            base: sim.base,
+        })
+    }
+}
+
+/// k 线的周期，k 线少于两根时按1小时计
+fn bar_millis(bars: &[Kline]) -> i64 {
+    match bars {
+        [first, second, ..] if second.open_time > first.open_time => {
+            second.open_time - first.open_time
+        }
+        _ => Interval::Hour1.millis(),
+    }
+}
+
+/// 每根 k 线收益率的均值除以标准差，再按一年的 k 线数量年化
+fn sharpe(equities: &[f64], bar_millis: i64) -> f64 {
+    let returns: Vec<f64> = equities
+        .windows(2)
+        .filter(|w| w[0] > 0.0)
+        .map(|w| w[1] / w[0] - 1.0)
+        .collect();
+    if returns.len() < 2 {
+        return 0.0;
+    }
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    if variance <= 0.0 {
+        return 0.0;
+    }
+    let year = 365.0 * Interval::Day1.millis() as f64;
+    mean / variance.sqrt() * (year / bar_millis as f64).sqrt()
+}
+
+fn bar_time(bar: &Kline) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(bar.open_time)
+        .single()
+        .unwrap_or_default()
+}
+
+/// 一根 k 线内的价格路径: 阳线先到最低价，阴线先到最高价
+fn path(bar: &Kline) -> [f64; 4] {
+    if bar.close >= bar.open {
+        [bar.open, bar.low, bar.high, bar.close]
+    } else {
+        [bar.open, bar.high, bar.low, bar.close]
+    }
+}
+
+/// 把 `bars` 合并为 `interval` 周期的 k 线
+fn aggregate(bars: &[Kline], interval: Interval) -> Vec<Kline> {
+    let millis = interval.millis();
+    let mut merged: Vec<Kline> = Vec::new();
+    for bar in bars {
+        let open_time = bar.open_time - bar.open_time.rem_euclid(millis);
+        match merged.last_mut() {
+            Some(last) if last.open_time == open_time => {
+                last.high = last.high.max(bar.high);
+                last.low = last.low.min(bar.low);
+                last.close = bar.close;
+                last.close_time = bar.close_time;
+                last.count += bar.count;
+            }
+            _ => merged.push(Kline {
+                open_time,
+                ..bar.clone()
+            }),
+        }
+    }
+    merged
+}
+
+#[derive(Default)]
+struct SimState {
+    bars: Vec<Kline>,
+    /// 当前 k 线的下标，之前的 k 线已收盘
+    cursor: usize,
+    price: f64,
+    quote: f64,
+    base: f64,
+    fee_rate: f64,
+    fees: f64,
+    buys: usize,
+    sells: usize,
+    next_order: usize,
+}
+
+impl SimState {
+    fn equity(&self, price: f64) -> f64 {
+        self.quote + self.base * price
+    }
+
+    fn fill(&mut self, quantity: f64, fee: f64, time: DateTime<Utc>) -> Fill {
+        self.next_order += 1;
+        self.fees += fee;
+        Fill {
+            price: self.price,
+            quantity,
+            order_id: Some(self.next_order),
+            fee,
+            fee_asset: Some("USDT".to_string()),
+            time,
+        }
+    }
+
+    fn buy(
+        &mut self,
+        quantity: f64,
+        spent: f64,
+        fee: f64,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Fill>> {
+        if spent + fee > self.quote + 1e-9 {
+            return Err(TgError::Internal(format!(
+                "Insufficient quote balance: {} < {}",
+                self.quote,
+                spent + fee
+            ))
+            .into());
+        }
+        self.quote -= spent + fee;
+        self.base += quantity;
+        self.buys += 1;
+        Ok(Some(self.fill(quantity, fee, time)))
+    }
+}
+
+struct SimMarket {
+    sim: Arc<Mutex<SimState>>,
+}
+
+#[async_trait]
+impl MarketService for SimMarket {
+    async fn ping(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn ticker_price(&self, _symbol: &Symbol) -> Result<f64> {
+        Ok(self.sim.lock().unwrap().price)
+    }
+
+    async fn ticker_24hr(&self, _symbol: &Symbol) -> Result<Ticker24h> {
+        Err(TgError::Internal("ticker_24hr is not supported in backtest".to_string()).into())
+    }
+
+    /// 只返回当前 k 线之前已收盘的 k 线，避免看到未来价格
+    async fn k_lines(&self, _symbol: &Symbol, query: &KlineQuery) -> Result<Vec<Kline>> {
+        let sim = self.sim.lock().unwrap();
+        let bars = aggregate(&sim.bars[..sim.cursor], query.interval);
+        Ok(query.select(&bars))
+    }
+}
+
+struct SimTrade {
+    symbol: Symbol,
+    sim: Arc<Mutex<SimState>>,
+    clock: Arc<ManualClock>,
+}
+
+#[async_trait]
+impl TradeService for SimTrade {
+    async fn get_order(&self, _symbol: &Symbol, _order_id: usize) -> Result<Order> {
+        Err(TgError::Internal("get_order is not supported in backtest".to_string()).into())
+    }
+
+    async fn buy_limit(&self, symbol: &Symbol, quantity: f64, _price: f64) -> Result<Option<Fill>> {
+        self.buy(symbol, quantity).await
+    }
+
+    async fn buy(&self, _symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        let mut sim = self.sim.lock().unwrap();
+        let spent = quantity * sim.price;
+        let fee = spent * sim.fee_rate;
+        sim.buy(quantity, spent, fee, self.clock.now())
+    }
+
+    async fn buy_quote(&self, _symbol: &Symbol, quote: f64) -> Result<Option<Fill>> {
+        let mut sim = self.sim.lock().unwrap();
+        let fee = quote * sim.fee_rate;
+        let quantity = (quote - fee) / sim.price;
+        sim.buy(quantity, quote - fee, fee, self.clock.now())
+    }
+
+    async fn sell_limit(
+        &self,
+        symbol: &Symbol,
+        quantity: f64,
+        _price: f64,
+    ) -> Result<Option<Fill>> {
+        self.sell(symbol, quantity).await
+    }
+
+    async fn sell(&self, _symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        let mut sim = self.sim.lock().unwrap();
+        if quantity > sim.base + 1e-12 {
+            return Err(TgError::Internal(format!(
+                "Insufficient base balance: {} < {}",
+                sim.base, quantity
+            ))
+            .into());
+        }
+        let proceeds = quantity * sim.price;
+        let fee = proceeds * sim.fee_rate;
+        sim.base -= quantity;
+        sim.quote += proceeds - fee;
+        sim.sells += 1;
+        Ok(Some(sim.fill(quantity, fee, self.clock.now())))
+    }
+
+    async fn account(&self) -> Result<Account> {
+        let sim = self.sim.lock().unwrap();
+        let balance = |asset: &str, free: f64| Balance {
+            asset: asset.to_string(),
+            free,
+            locked: 0.0,
+        };
+        Ok(Account {
+            can_trade: true,
+            balances: vec![
+                balance(self.symbol.quote_asset(), sim.quote),
+                balance(self.symbol.base_asset(), sim.base),
+            ],
+        })
+    }
+
+    async fn open_orders(&self, _symbol: &Symbol) -> Result<Vec<Order>> {
+        Ok(Vec::new())
+    }
+
+    async fn cancel_order(&self, _symbol: &Symbol, _order_id: Option<usize>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LevelGrid, Sizing, Spacing};
+
+    fn bar(hour: i64, open: f64, high: f64, low: f64, close: f64) -> Kline {
+        let open_time = hour * Interval::Hour1.millis();
+        Kline {
+            open_time,
+            open,
+            high,
+            low,
+            close,
+            close_time: open_time + Interval::Hour1.millis() - 1,
+            count: 1,
+        }
+    }
+
+    #[test]
+    fn bars_should_be_aggregated() {
+        let bars: Vec<Kline> = (0..6)
+            .map(|h| {
+                bar(
+                    h,
+                    h as f64,
+                    h as f64 + 10.0,
+                    h as f64 - 10.0,
+                    h as f64 + 1.0,
+                )
+            })
+            .collect();
+        let merged = aggregate(&bars, "4h".parse().unwrap());
+        assert_eq!(merged.len(), 2);
+        assert_eq!((merged[0].open, merged[0].close), (0.0, 4.0));
+        assert_eq!((merged[0].high, merged[0].low), (13.0, -10.0));
+        assert_eq!(merged[1].open_time, 4 * Interval::Hour1.millis());
+        assert_eq!(merged[1].count, 2);
+    }
+
+    #[test]
+    fn level_grid_should_profit_from_oscillation() {
+        let coin: Coin = toml::from_str(
+            r#"
+            strategy = 'level'
+            quantity = 1
+            [params]
+            lower_price = 90
+            upper_price = 110
+            levels = 3
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            coin.params::<LevelGrid>().unwrap().spacing,
+            Spacing::Arithmetic
+        );
+        assert_eq!(coin.sizing, Sizing::Base);
+        let backtest = Backtest {
+            symbol: Symbol::Eth,
+            coin,
+            risk: RiskConfig::default(),
+            quote: 1000.0,
+            base: 0.0,
+            fee_rate: 0.0,
+        };
+        let bars: Vec<Kline> = (0..4).map(|h| bar(h, 105.0, 111.0, 89.0, 105.0)).collect();
+        let report = backtest.run(&GridRegistry::default(), &bars).unwrap();
+        assert_eq!(report.bars, 4);
+        assert!(report.buys > 0);
+        assert!(report.sells > 0);
+        assert!(report.pnl > 0.0, "{:?}", report);
+        assert!(report.sharpe > 0.0, "{:?}", report);
+        assert_eq!(report.start_equity, 1000.0);
+    }
+}
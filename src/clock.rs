@@ -0,0 +1,94 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+/// 时间来源，网格的等待、缓存过期、风控统计及请求时间戳都通过它获取时间
+#[async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    async fn sleep(&self, duration: Duration);
+}
+
+/// 系统时间，`sleep` 真实等待
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// 手动推进的时间，`sleep` 立即返回并把时间向前推进，用于回测及测试
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// 设置当前时间，早于当前时间时忽略，保证时间不会倒退
+    pub fn set(&self, time: DateTime<Utc>) {
+        let mut now = self.now.lock().unwrap();
+        if time > *now {
+            *now = time;
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += chrono::Duration::from_std(duration).unwrap_or_else(|_| chrono::Duration::zero());
+    }
+}
+
+#[async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+        tokio::task::yield_now().await;
+    }
+}
+
+/// `since` 之后是否已经过了 `duration`
+pub fn elapsed(clock: &dyn Clock, since: DateTime<Utc>, duration: Duration) -> bool {
+    (clock.now() - since)
+        .to_std()
+        .is_ok_and(|elapsed| elapsed > duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn manual_clock_should_advance_on_sleep() {
+        let start = Utc.timestamp_millis_opt(0).unwrap();
+        let clock = ManualClock::new(start);
+        clock.sleep(Duration::from_secs(120)).await;
+        assert_eq!(clock.now(), start + chrono::Duration::seconds(120));
+        assert!(elapsed(&clock, start, Duration::from_secs(60)));
+        assert!(!elapsed(&clock, start, Duration::from_secs(120)));
+
+        clock.set(start);
+        assert_eq!(clock.now(), start + chrono::Duration::seconds(120));
+    }
+}
@@ -1,16 +1,34 @@
+use std::collections::BTreeMap;
 use std::ops::Div;
-use std::{fmt, fs};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use std::{env, fmt, fs};
 
+use serde::de::DeserializeOwned;
 use serde::de::{Unexpected, Visitor};
 use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::grid::FIXED_STRATEGY;
+use crate::trade::Interval;
 use crate::TgError;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct ServerConfig {
+    /// 默认账户，未指定 `account` 的币种使用
     pub trade: TradeConfig,
+    /// 其他账户，即 `[accounts.xxx]`，如子账户
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub accounts: BTreeMap<String, TradeConfig>,
     pub coin: CoinConfig,
     pub log: LogConfig,
+    #[serde(default)]
+    pub state: StateConfig,
+    #[serde(default)]
+    pub risk: RiskConfig,
+    /// 成交、停止及错误等事件的通知
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify: Vec<NotifyConfig>,
 }
 
 impl AsRef<TradeConfig> for TradeConfig {
@@ -27,10 +45,84 @@ impl AsRef<CoinConfig> for CoinConfig {
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct TradeConfig {
+    /// 交易所，决定 `url` 指向的 API 及签名方式
+    #[serde(default)]
+    pub exchange: Exchange,
     pub url: String,
     pub proxy: Option<String>,
-    pub key: String,
-    pub secret: String,
+    #[serde(default)]
+    pub key: Secret,
+    #[serde(default)]
+    pub secret: Secret,
+    /// 从文件读取 key，如 Docker secrets，优先于 `key`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<String>,
+    /// 从文件读取 secret，优先于 `secret`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_file: Option<String>,
+    /// 模拟下单: 订单只记录不发送，可被币种的 `dry_run` 覆盖
+    #[serde(default)]
+    pub dry_run: bool,
+    /// 录制全部行情及交易请求的文件，用于回放
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<String>,
+    /// 每种资产保留的数量，下单时不会使用
+    #[serde(default)]
+    pub reserve: BTreeMap<String, f64>,
+}
+
+/// 支持的交易所
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Exchange {
+    /// 币安现货
+    #[default]
+    Binance,
+    /// Bybit 现货(v5 API)
+    Bybit,
+}
+
+/// 密钥等敏感配置，Debug、Display 及序列化时隐藏真实值
+#[derive(Clone, Default, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    const REDACTED: &'static str = "******";
+
+    /// 真实值，仅在签名、请求头等必须使用的地方调用
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(s: &str) -> Self {
+        Self(s.to_string())
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(Self::REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(Self::REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(Self::REDACTED)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -40,13 +132,35 @@ pub struct CoinConfig {
     pub bnb: Option<Coin>,
 }
 
+impl CoinConfig {
+    /// 已配置的币种
+    pub fn coins(&self) -> Vec<(Symbol, &Coin)> {
+        [
+            (Symbol::Eth, self.eth.as_ref()),
+            (Symbol::Bnb, self.bnb.as_ref()),
+            (Symbol::Btc, self.btc.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(symbol, coin)| coin.map(|c| (symbol, c)))
+        .collect()
+    }
+
+    pub fn get(&self, symbol: &Symbol) -> Option<&Coin> {
+        match symbol {
+            Symbol::Eth => self.eth.as_ref(),
+            Symbol::Btc => self.btc.as_ref(),
+            Symbol::Bnb => self.bnb.as_ref(),
+        }
+    }
+}
+
 impl AsRef<Coin> for Coin {
     fn as_ref(&self) -> &Coin {
         self
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum Symbol {
     #[serde(rename = "ETHUSDT")]
     Eth,
@@ -56,21 +170,342 @@ pub enum Symbol {
     Bnb,
 }
 
+impl Symbol {
+    /// 基础资产，如 ETHUSDT 中的 ETH
+    pub fn base_asset(&self) -> &'static str {
+        match self {
+            Symbol::Eth => "ETH",
+            Symbol::Btc => "BTC",
+            Symbol::Bnb => "BNB",
+        }
+    }
+
+    /// 计价资产，如 ETHUSDT 中的 USDT
+    pub fn quote_asset(&self) -> &'static str {
+        "USDT"
+    }
+
+    /// 交易所的交易对名称，如 `pair("")` 为 ETHUSDT，`pair("-")` 为 ETH-USDT
+    pub fn pair(&self, separator: &str) -> String {
+        format!("{}{}{}", self.base_asset(), separator, self.quote_asset())
+    }
+}
+
 impl fmt::Display for Symbol {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
+impl FromStr for Symbol {
+    type Err = TgError;
+
+    /// 接受 `eth`、`ETH`、`ETHUSDT` 等写法
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().trim_end_matches("USDT") {
+            "ETH" => Ok(Symbol::Eth),
+            "BTC" => Ok(Symbol::Btc),
+            "BNB" => Ok(Symbol::Bnb),
+            _ => Err(TgError::ConfError(format!("Unknown symbol: {}", s))),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Coin {
+    /// 网格策略名称，对应 `GridRegistry` 中注册的策略
+    #[serde(default = "default_strategy")]
+    pub strategy: String,
+    /// 策略专属参数，即 `[coin.xxx.params]` 表
+    #[serde(default)]
+    pub params: Option<toml::Value>,
+    #[serde(default)]
     pub buy_price: f64,
+    #[serde(default)]
     pub sell_price: f64,
-    #[serde(deserialize_with = "percentage_as_f64")]
+    #[serde(default, deserialize_with = "percentage_as_f64")]
     pub profit_ratio: f64,
-    #[serde(deserialize_with = "percentage_as_f64")]
+    #[serde(default, deserialize_with = "percentage_as_f64")]
     pub double_throw_ratio: f64,
+    /// 成交后不按 k 线振幅重新计算 `profit_ratio`、`double_throw_ratio`，始终使用配置的值
+    #[serde(default)]
+    pub fixed_ratio: bool,
     pub quantity: f64,
+    /// 每次买入数量的计算方式
+    #[serde(default)]
+    pub sizing: Sizing,
+    /// 卖出时与持仓配对的方式，默认 `Lifo`，经典网格总是 `Pair`
+    #[serde(default)]
+    pub matching: Option<Matching>,
+    /// 价格下限，低于该价格时执行 `out_of_range`，仅固定网格支持
+    #[serde(default)]
+    pub lower_limit: Option<f64>,
+    /// 价格上限，高于该价格时执行 `out_of_range`，仅固定网格支持
+    #[serde(default)]
+    pub upper_limit: Option<f64>,
+    #[serde(default)]
+    pub out_of_range: OutOfRange,
+    /// 整体持仓的止损/止盈，仅固定网格支持
+    #[serde(default)]
+    pub stop: Option<StopConfig>,
+    /// 趋势过滤规则，任意一条规则命中时禁止对应方向的交易
+    #[serde(default)]
+    pub filters: Vec<TrendRule>,
+    /// 成交及未成交后的等待时间
+    #[serde(default)]
+    pub cooldown: Cooldown,
+    /// 模拟下单，未设置时使用所在账户的 `dry_run`
+    #[serde(default)]
+    pub dry_run: Option<bool>,
+    /// 使用的账户，对应 `[accounts.xxx]`，未设置时使用 `[trade]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+fn default_strategy() -> String {
+    FIXED_STRATEGY.to_string()
+}
+
+impl Coin {
+    /// 是否模拟下单，`global` 为所在账户的 `dry_run`
+    pub fn is_dry_run(&self, global: bool) -> bool {
+        self.dry_run.unwrap_or(global)
+    }
+
+    /// 将策略专属参数解析为 `T`
+    pub fn params<T: DeserializeOwned>(&self) -> Result<T, TgError> {
+        let params = self.params.clone().ok_or_else(|| {
+            TgError::ConfError(format!("Strategy {} requires params", self.strategy))
+        })?;
+        params.try_into().map_err(|e| {
+            TgError::ConfError(format!(
+                "Invalid params of strategy {}: {}",
+                self.strategy, e
+            ))
+        })
+    }
+}
+
+/// 网格每次执行后的等待时间(秒)
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct Cooldown {
+    /// 买入成交后
+    pub buy: u64,
+    /// 卖出成交后
+    pub sell: u64,
+    /// 未成交时
+    pub idle: u64,
+}
+
+impl Default for Cooldown {
+    fn default() -> Self {
+        Self {
+            buy: 120,
+            sell: 60,
+            idle: 1,
+        }
+    }
+}
+
+impl Cooldown {
+    pub fn buy(&self) -> Duration {
+        Duration::from_secs(self.buy)
+    }
+
+    pub fn sell(&self) -> Duration {
+        Duration::from_secs(self.sell)
+    }
+
+    pub fn idle(&self) -> Duration {
+        Duration::from_secs(self.idle)
+    }
+}
+
+/// 每次买入数量的计算方式，以 `mode` 字段区分
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+#[serde(tag = "mode")]
+pub enum Sizing {
+    /// 每次买入 `quantity` 个基础资产
+    #[default]
+    Base,
+    /// 每次买入固定金额(U)
+    Quote { amount: f64 },
+    /// 每次买入可用计价资产(扣除保留数量)的比例
+    Percent {
+        #[serde(deserialize_with = "percentage_as_f64")]
+        ratio: f64,
+    },
+    /// 每多持有一层，买入数量乘以 `multiplier`
+    Scaled { multiplier: f64 },
+}
+
+/// 卖出时选择与之配对的买入持仓
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum Matching {
+    /// 最近买入的一份
+    #[default]
+    Lifo,
+    /// 最早买入的一份
+    Fifo,
+    /// 网格中对应的一份：经典网格为下一个价位的持仓，其它为买入价最接近且不高于卖出价的持仓
+    Pair,
+}
+
+/// 价格超出 `lower_limit`/`upper_limit` 时的处理方式
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum OutOfRange {
+    /// 暂停交易，价格回到区间后继续
+    #[default]
+    Pause,
+    /// 卖出全部持仓后暂停
+    StopLoss,
+    /// 平移区间并以当前价格重新设置网格
+    Shift,
+}
+
+/// 整体持仓的止损/止盈，触发后网格停止并记录原因
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct StopConfig {
+    /// 低于持仓均价的比例止损
+    #[serde(default, deserialize_with = "percentage_as_option_f64")]
+    pub stop_loss_ratio: Option<f64>,
+    /// 绝对价格止损
+    #[serde(default)]
+    pub stop_loss_price: Option<f64>,
+    /// 跟踪止损: 比例止损以持仓以来的最高价为基准
+    #[serde(default)]
+    pub trailing: bool,
+    /// 持仓总盈利(已实现+未实现，单位U)的止盈目标
+    #[serde(default)]
+    pub take_profit: Option<f64>,
+    #[serde(default)]
+    pub action: StopAction,
+}
+
+/// 止损/止盈触发后的处理方式
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum StopAction {
+    /// 卖出全部持仓后停止
+    #[default]
+    Liquidate,
+    /// 保留持仓并停止
+    Freeze,
+}
+
+/// 多价位网格的参数: 在 `lower_price` 与 `upper_price` 之间划分 `levels` 个价位
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LevelGrid {
+    pub lower_price: f64,
+    pub upper_price: f64,
+    pub levels: usize,
+    #[serde(default)]
+    pub spacing: Spacing,
+}
+
+/// 反向网格的参数: 先卖后买，以基础资产计算盈利
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ReverseGrid {
+    /// 允许网格卖出的基础资产总量
+    pub inventory: f64,
+}
+
+/// 网格价位的间隔方式
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum Spacing {
+    /// 等差，相邻价位差值相同
+    #[default]
+    Arithmetic,
+    /// 等比，相邻价位比例相同
+    Geometric,
+}
+
+/// 交易方向
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+/// 趋势过滤规则: `when` 中的条件全部满足时，禁止 `side` 方向的交易
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TrendRule {
+    pub side: TradeSide,
+    /// k线周期，如 '1h'/'4h'/'1d'
+    #[serde(default = "default_rule_interval")]
+    pub interval: Interval,
+    pub when: Vec<Condition>,
+}
+
+fn default_rule_interval() -> Interval {
+    Interval::Hour4
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Condition {
+    #[serde(flatten)]
+    pub indicator: Indicator,
+    pub compare: Compare,
+    /// `Above`/`Below` 比较的阈值
+    #[serde(default)]
+    pub value: Option<f64>,
+}
+
+/// 技术指标，以 `indicator` 字段区分
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "indicator")]
+pub enum Indicator {
+    Sma {
+        period: usize,
+    },
+    Ema {
+        period: usize,
+    },
+    Rsi {
+        #[serde(default = "default_rsi_period")]
+        period: usize,
+    },
+    MacdHistogram {
+        #[serde(default = "default_macd_fast")]
+        fast: usize,
+        #[serde(default = "default_macd_slow")]
+        slow: usize,
+        #[serde(default = "default_macd_signal")]
+        signal: usize,
+    },
+}
+
+fn default_rsi_period() -> usize {
+    14
+}
+
+fn default_macd_fast() -> usize {
+    12
+}
+
+fn default_macd_slow() -> usize {
+    26
+}
+
+fn default_macd_signal() -> usize {
+    9
+}
+
+/// 条件的比较方式
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Compare {
+    /// 当前价格高于指标
+    PriceAbove,
+    /// 当前价格低于指标
+    PriceBelow,
+    /// 指标高于 `value`
+    Above,
+    /// 指标低于 `value`
+    Below,
+    /// 指标最新值大于上一根k线的值
+    Rising,
+    /// 指标最新值小于上一根k线的值
+    Falling,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -81,6 +516,70 @@ pub struct LogConfig {
     pub rotation: LogRotationType,
 }
 
+/// 所有币种共享的风控限制，未配置的项不做限制，只限制买入
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct RiskConfig {
+    /// 所有持仓占用资金(U)的上限
+    pub max_capital: Option<f64>,
+    /// 单个币种持仓份数的上限
+    pub max_lots_per_coin: Option<usize>,
+    /// 当日已实现亏损(U)的上限，达到后不再买入
+    pub max_daily_loss: Option<f64>,
+    /// 最近一小时下单次数的上限
+    pub max_orders_per_hour: Option<usize>,
+}
+
+/// 通知方式
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum NotifyKind {
+    /// 以 POST 请求发送到 `url`
+    Webhook,
+    /// 执行本地命令 `command`
+    Exec,
+}
+
+/// 一个通知渠道，即 `[[notify]]`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct NotifyConfig {
+    pub kind: NotifyKind,
+    /// Webhook 的地址
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Webhook 的请求体模板，可使用 `{event}`、`{symbol}`、`{message}`、`{time}`，
+    /// 替换的值已按 JSON 字符串转义；不设置时发送完整的 JSON
+    #[serde(default)]
+    pub template: Option<String>,
+    /// 执行的命令，事件 JSON 从标准输入传入
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// 通知的事件，如 `OrderFilled`、`Halted`、`Error`，为空时通知 `PriceTick` 等高频事件以外的全部事件
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// 每种事件每分钟最多通知的次数，超出的通知被忽略
+    #[serde(default = "default_rate_limit")]
+    pub rate_limit: usize,
+}
+
+fn default_rate_limit() -> usize {
+    10
+}
+
+/// 运行状态的保存位置，默认为工作目录下的 `state`，重启后仍需保留
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct StateConfig {
+    pub path: String,
+}
+
+impl Default for StateConfig {
+    fn default() -> Self {
+        Self {
+            path: "state".to_string(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum LogRotationType {
     Hourly,
@@ -88,21 +587,143 @@ pub enum LogRotationType {
     Never,
 }
 
+/// 覆盖配置的环境变量前缀，`TGS_TRADE__SECRET` 对应 `trade.secret`
+const ENV_PREFIX: &str = "TGS_";
+/// 配置文件路径的环境变量，不参与覆盖
+const ENV_CONFIG: &str = "TGS_CONFIG";
+
 impl ServerConfig {
-    pub fn load(path: &str) -> Result<Self, TgError> {
+    /// 读取配置文件，并以 `TGS_*` 环境变量覆盖
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TgError> {
         let str = fs::read_to_string(path)?;
-        let config = Self::from_str(str.as_str())?;
-        Ok(config)
+        Self::from_str_with_env(str.as_str(), env::vars())
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self, TgError> {
-        let mut config: Self = toml::from_str(s)?;
-        let url = &mut config.trade.url;
-        if !url.ends_with('/') {
-            url.push('/');
+        Self::from_str_with_env(s, std::iter::empty())
+    }
+
+    /// 解析配置，`vars` 中 `TGS_` 开头的变量覆盖对应的键，层级以 `__` 分隔
+    pub fn from_str_with_env(
+        s: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, TgError> {
+        let mut value: toml::Value = toml::from_str(s)?;
+        for (name, raw) in vars {
+            if name == ENV_CONFIG {
+                continue;
+            }
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                override_value(&mut value, key, &raw)?;
+            }
+        }
+        let mut config: Self = value.try_into()?;
+        config.trade.prepare("trade")?;
+        for (name, account) in config.accounts.iter_mut() {
+            account.prepare(&format!("accounts.{}", name))?;
         }
         Ok(config)
     }
+
+    /// 全部账户，`[trade]` 的名称为 `None`
+    pub fn accounts(&self) -> impl Iterator<Item = (Option<&str>, &TradeConfig)> {
+        std::iter::once((None, &self.trade)).chain(
+            self.accounts
+                .iter()
+                .map(|(name, account)| (Some(name.as_str()), account)),
+        )
+    }
+
+    /// 名为 `name` 的账户，`None` 为 `[trade]`
+    pub fn account(&self, name: Option<&str>) -> Result<&TradeConfig, TgError> {
+        match name {
+            None => Ok(&self.trade),
+            Some(name) => self
+                .accounts
+                .get(name)
+                .ok_or_else(|| TgError::ConfError(format!("Unknown account: {}", name))),
+        }
+    }
+}
+
+impl TradeConfig {
+    /// 读取密钥文件并补全 `url` 末尾的 `/`，`section` 为配置中的位置
+    fn prepare(&mut self, section: &str) -> Result<(), TgError> {
+        self.read_secret_files(section)?;
+        if !self.url.ends_with('/') {
+            self.url.push('/');
+        }
+        Ok(())
+    }
+
+    /// 以 `key_file`、`secret_file` 的内容替换 `key`、`secret`
+    fn read_secret_files(&mut self, section: &str) -> Result<(), TgError> {
+        if let Some(path) = self.key_file.as_ref() {
+            self.key = read_secret(path, &format!("{}.key_file", section))?.into();
+        }
+        if let Some(path) = self.secret_file.as_ref() {
+            self.secret = read_secret(path, &format!("{}.secret_file", section))?.into();
+        }
+        Ok(())
+    }
+}
+
+fn read_secret(path: &str, name: &str) -> Result<String, TgError> {
+    fs::read_to_string(path)
+        .map(|s| s.trim().to_string())
+        .map_err(|e| TgError::ConfError(format!("Cannot read {} '{}': {}", name, path, e)))
+}
+
+/// 将 `TRADE__SECRET` 形式的键写入配置，原值为字符串时保持字符串，否则按 TOML 值解析
+fn override_value(root: &mut toml::Value, key: &str, raw: &str) -> Result<(), TgError> {
+    let path: Vec<&str> = key.split("__").collect();
+    if path.iter().any(|k| k.is_empty()) {
+        return Err(TgError::ConfError(format!(
+            "Invalid environment variable: {}{}",
+            ENV_PREFIX, key
+        )));
+    }
+    let not_table = || {
+        TgError::ConfError(format!(
+            "Cannot override {}{}: not a table",
+            ENV_PREFIX, key
+        ))
+    };
+    let (last, parents) = path.split_last().unwrap();
+    let mut table = root;
+    let mut parent = None;
+    for k in parents {
+        let t = table.as_table_mut().ok_or_else(not_table)?;
+        let name = table_key(t, k, parent);
+        table = t
+            .entry(name)
+            .or_insert_with(|| toml::Value::Table(Default::default()));
+        parent = Some(*k);
+    }
+    let table = table.as_table_mut().ok_or_else(not_table)?;
+    let last = table_key(table, last, parent);
+    let value = match table.get(&last) {
+        Some(toml::Value::String(_)) => toml::Value::String(raw.to_string()),
+        _ => toml::from_str::<BTreeMap<String, toml::Value>>(&format!("v = {}", raw))
+            .ok()
+            .and_then(|mut v| v.remove("v"))
+            .unwrap_or_else(|| toml::Value::String(raw.to_string())),
+    };
+    table.insert(last, value);
+    Ok(())
+}
+
+/// 环境变量中的键在 `table` 中的名称: 优先匹配已有的键(不区分大小写)，
+/// 否则 `reserve` 下为大写的资产名，其余为小写的字段名
+fn table_key(table: &toml::value::Table, key: &str, parent: Option<&str>) -> String {
+    if let Some(existing) = table.keys().find(|k| k.eq_ignore_ascii_case(key)) {
+        return existing.clone();
+    }
+    match parent {
+        Some(p) if p.eq_ignore_ascii_case("reserve") => key.to_uppercase(),
+        _ => key.to_lowercase(),
+    }
 }
 
 pub fn percentage_as_f64<'de, D>(deserializer: D) -> Result<f64, D::Error>
@@ -143,6 +764,13 @@ where
     deserializer.deserialize_str(PercentageVisitor)
 }
 
+pub fn percentage_as_option_f64<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    percentage_as_f64(deserializer).map(Some)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -156,6 +784,289 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn env_should_override_config() {
+        let vars = [
+            ("TGS_CONFIG", "/etc/tgs/tgs.conf"),
+            ("TGS_TRADE__SECRET", "123456"),
+            ("TGS_COIN__ETH__QUANTITY", "0.5"),
+            ("TGS_RISK__MAX_ORDERS_PER_HOUR", "10"),
+            ("TGS_TRADE__RESERVE__USDT", "50"),
+            ("TGS_TRADE__RESERVE__BTC", "0.01"),
+            ("TGS_ACCOUNTS__MAIN__SECRET", "654321"),
+            ("HOME", "/root"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let source = format!(
+            "{}\n{}",
+            include_str!("../fixtures/tgs.conf"),
+            r#"
+            [accounts.Main]
+            key = 'x'
+            secret = 'x'
+            url = 'https://api.binance.com/api/v3/'
+            "#
+        );
+        let config = ServerConfig::from_str_with_env(&source, vars).unwrap();
+        assert_eq!(config.trade.secret.expose(), "123456");
+        assert_eq!(config.coin.eth.unwrap().quantity, 0.5);
+        assert_eq!(config.risk.max_orders_per_hour, Some(10));
+        // 已有的键保持原有大小写，新增的资产名为大写
+        let reserve = &config.trade.reserve;
+        assert_eq!(reserve.get("USDT"), Some(&50.0));
+        assert_eq!(reserve.get("BTC"), Some(&0.01));
+        assert_eq!(reserve.len(), 2);
+        assert_eq!(config.accounts["Main"].secret.expose(), "654321");
+        assert_eq!(config.accounts.len(), 1);
+
+        let vars = [("TGS_TRADE__URL__X".to_string(), "1".to_string())];
+        assert!(
+            ServerConfig::from_str_with_env(include_str!("../fixtures/tgs.conf"), vars).is_err()
+        );
+    }
+
+    #[test]
+    fn secret_files_should_be_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("key");
+        fs::write(&key, "file-key\n").unwrap();
+        let vars = [(
+            "TGS_TRADE__KEY_FILE".to_string(),
+            key.to_string_lossy().to_string(),
+        )];
+        let config =
+            ServerConfig::from_str_with_env(include_str!("../fixtures/tgs.conf"), vars).unwrap();
+        assert_eq!(config.trade.key.expose(), "file-key");
+        assert_eq!(config.trade.secret.expose(), "x");
+
+        let vars = [(
+            "TGS_TRADE__SECRET_FILE".to_string(),
+            "/nonexistent".to_string(),
+        )];
+        assert!(
+            ServerConfig::from_str_with_env(include_str!("../fixtures/tgs.conf"), vars).is_err()
+        );
+    }
+
+    #[test]
+    fn accounts_should_be_resolved() {
+        let source = format!(
+            "{}\n{}",
+            include_str!("../fixtures/tgs.conf"),
+            r#"
+            [accounts.sub1]
+            exchange = 'Bybit'
+            key = 'sub-key'
+            secret = 'sub-secret'
+            url = 'https://api-testnet.bybit.com'
+            "#
+        );
+        let vars = [(
+            "TGS_ACCOUNTS__SUB1__SECRET".to_string(),
+            "env-secret".to_string(),
+        )];
+        let config = ServerConfig::from_str_with_env(&source, vars).unwrap();
+        let sub1 = config.account(Some("sub1")).unwrap();
+        assert_eq!(sub1.exchange, Exchange::Bybit);
+        assert_eq!(sub1.url, "https://api-testnet.bybit.com/");
+        assert_eq!(sub1.secret.expose(), "env-secret");
+        assert_eq!(config.account(None).unwrap(), &config.trade);
+        assert!(config.account(Some("sub2")).is_err());
+        let names: Vec<Option<&str>> = config.accounts().map(|(name, _)| name).collect();
+        assert_eq!(names, vec![None, Some("sub1")]);
+    }
+
+    #[test]
+    fn secrets_should_be_redacted() {
+        let vars = [
+            ("TGS_TRADE__KEY", "api-key-123"),
+            ("TGS_TRADE__SECRET", "api-secret-456"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let config =
+            ServerConfig::from_str_with_env(include_str!("../fixtures/tgs.conf"), vars).unwrap();
+        assert_eq!(config.trade.key.expose(), "api-key-123");
+
+        let outputs = [
+            format!("{:?}", config),
+            format!("{:#?}", config),
+            format!("{} {}", config.trade.key, config.trade.secret),
+            serde_json::to_string(&config).unwrap(),
+            toml::to_string(&config.trade).unwrap(),
+            logged(&config),
+        ];
+        for output in outputs {
+            assert!(!output.contains("api-key-123"), "{}", output);
+            assert!(!output.contains("api-secret-456"), "{}", output);
+        }
+        assert!(format!("{:?}", config.trade).contains("******"));
+    }
+
+    /// 以 tracing 记录配置，返回日志内容
+    fn logged(config: &ServerConfig) -> String {
+        use std::io::Write;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Buf(Arc<Mutex<Vec<u8>>>);
+        impl Write for Buf {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buf = Buf::default();
+        let writer = buf.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("config: {:?}, trade: {}", config, config.trade.secret)
+        });
+        let log = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert!(log.contains("config: ServerConfig"));
+        log
+    }
+
+    #[test]
+    fn trend_rules_should_be_loaded() {
+        let config = ServerConfig::from_str(include_str!("../fixtures/tgs.conf")).unwrap();
+        let filters = &config.coin.eth.unwrap().filters;
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0].side, TradeSide::Buy);
+        assert_eq!(filters[0].interval, Interval::Hour4);
+        assert_eq!(filters[0].when[0].indicator, Indicator::Ema { period: 200 });
+        assert_eq!(filters[1].when[0].value, Some(70.0));
+        assert_eq!(
+            filters[1].when[1].indicator,
+            Indicator::MacdHistogram {
+                fast: 12,
+                slow: 26,
+                signal: 9
+            }
+        );
+        assert_eq!(filters[1].when[1].compare, Compare::Rising);
+    }
+
+    #[test]
+    fn strategy_should_default_to_fixed() {
+        let config = ServerConfig::from_str(include_str!("../fixtures/tgs.conf")).unwrap();
+        let eth = config.coin.eth.unwrap();
+        assert_eq!(eth.strategy, "fixed");
+        assert!(eth.params::<LevelGrid>().is_err());
+    }
+
+    #[test]
+    fn level_grid_should_be_loaded() {
+        let config = ServerConfig::from_str(
+            r#"
+            [trade]
+            key = 'x'
+            secret = 'x'
+            url = 'https://testnet.binance.vision/api/v3/'
+
+            [coin.btc]
+            strategy = 'level'
+            quantity = 0.001
+            [coin.btc.params]
+            lower_price = 20000
+            upper_price = 30000
+            levels = 11
+            spacing = 'Geometric'
+
+            [log]
+            enable_log_file = false
+            log_level = 'info'
+            path = '/tmp/tgs-log'
+            rotation = 'Daily'
+            "#,
+        )
+        .unwrap();
+        let btc = config.coin.btc.unwrap();
+        assert_eq!(btc.strategy, "level");
+        assert_eq!(btc.buy_price, 0.0);
+        let level: LevelGrid = btc.params().unwrap();
+        assert_eq!(level.levels, 11);
+        assert_eq!(level.spacing, Spacing::Geometric);
+    }
+
+    #[test]
+    fn reverse_grid_should_be_loaded() {
+        let config = ServerConfig::from_str(
+            r#"
+            [trade]
+            key = 'x'
+            secret = 'x'
+            url = 'https://testnet.binance.vision/api/v3/'
+
+            [coin.eth]
+            strategy = 'reverse'
+            sell_price = 4000
+            profit_ratio = '2%'
+            double_throw_ratio = '3%'
+            quantity = 0.01
+            [coin.eth.params]
+            inventory = 0.1
+
+            [log]
+            enable_log_file = false
+            log_level = 'info'
+            path = '/tmp/tgs-log'
+            rotation = 'Daily'
+            "#,
+        )
+        .unwrap();
+        let eth = config.coin.eth.unwrap();
+        assert_eq!(eth.strategy, "reverse");
+        let reverse: ReverseGrid = eth.params().unwrap();
+        assert_eq!(reverse.inventory, 0.1);
+    }
+
+    #[test]
+    fn stop_config_should_be_loaded() {
+        let config = ServerConfig::from_str(include_str!("../fixtures/tgs.conf")).unwrap();
+        assert_eq!(config.state, StateConfig::default());
+        let stop = config.coin.eth.as_ref().unwrap().stop.clone().unwrap();
+        assert_eq!(stop.stop_loss_ratio, Some(0.15));
+        assert_eq!(stop.stop_loss_price, None);
+        assert!(!stop.trailing);
+        assert_eq!(stop.action, StopAction::Liquidate);
+        assert_eq!(config.risk.max_capital, Some(1000.0));
+        assert_eq!(config.risk.max_daily_loss, None);
+        assert_eq!(config.trade.reserve.get("USDT"), Some(&100.0));
+        assert_eq!(
+            config.coin.eth.as_ref().unwrap().sizing,
+            Sizing::Quote { amount: 20.0 }
+        );
+        assert_eq!(
+            config.coin.eth.as_ref().unwrap().matching,
+            Some(Matching::Fifo)
+        );
+        assert_eq!(
+            config.coin.eth.as_ref().unwrap().cooldown,
+            Cooldown {
+                buy: 120,
+                sell: 30,
+                idle: 1
+            }
+        );
+    }
+
+    #[test]
+    fn symbol_should_be_parsed() {
+        assert_eq!("eth".parse::<Symbol>().unwrap(), Symbol::Eth);
+        assert_eq!("BTCUSDT".parse::<Symbol>().unwrap(), Symbol::Btc);
+        assert!("doge".parse::<Symbol>().is_err());
+        let config = ServerConfig::from_str(include_str!("../fixtures/tgs.conf")).unwrap();
+        let coins = config.coin.coins();
+        assert_eq!(coins.len(), 1);
+        assert_eq!(coins[0].0, Symbol::Eth);
+        assert!(config.coin.get(&Symbol::Btc).is_none());
+    }
+
     #[test]
     fn percentage_test() {
         let str = "2.3%";
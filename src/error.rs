@@ -1,6 +1,8 @@
 use thiserror::Error;
 use tracing::{debug, warn};
 
+use crate::ConfigIssue;
+
 #[derive(Error, Debug)]
 pub enum TgError {
     #[error("Not found: tgs.conf. Set env variable TGS_CONFIG")]
@@ -11,6 +13,8 @@ pub enum TgError {
     ConfigParseError(#[from] toml::de::Error),
     #[error("Config error:{0}")]
     ConfError(String),
+    #[error("Invalid config:{}", .0.iter().map(|i| format!("\n  {}", i)).collect::<String>())]
+    InvalidConfig(Vec<ConfigIssue>),
     #[error("Can not connect to the server")]
     PingError(),
     #[error("Url Error: {0}")]
@@ -19,6 +23,8 @@ pub enum TgError {
     RequestError(#[from] reqwest::Error),
     #[error("Binance error code:{0}-{1}")]
     BinanceError(u16, String),
+    #[error("Bybit error code:{0}-{1}")]
+    BybitError(i64, String),
     #[error("Decode response body error {0}")]
     DecodeError(String),
     #[error("Internal error: {0}")]
@@ -0,0 +1,162 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::debug;
+
+use crate::grid::HaltReason;
+use crate::risk::Veto;
+use crate::{OutOfRange, Symbol, TradeSide};
+
+/// 事件通道的默认容量，慢速的订阅者会丢失最早的事件
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// 全部事件的名称，见 `GridEvent::kind`
+pub const EVENT_KINDS: &[&str] = &[
+    "RangeExited",
+    "RangeEntered",
+    "Halted",
+    "Vetoed",
+    "OrderFilled",
+    "Error",
+    "PriceTick",
+    "OrderSubmitted",
+    "LevelsChanged",
+    "RatioRecalculated",
+];
+
+/// 高频的事件，通知渠道未指定 `events` 时不通知
+pub const NOISY_KINDS: &[&str] = &[
+    "PriceTick",
+    "OrderSubmitted",
+    "LevelsChanged",
+    "RatioRecalculated",
+];
+
+/// 网格运行过程中产生的事件
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub enum GridEvent {
+    /// 价格离开了 `lower_limit`/`upper_limit` 区间，`action` 不是 `Shift` 时网格暂停交易
+    RangeExited {
+        symbol: Symbol,
+        price: f64,
+        limit: f64,
+        action: OutOfRange,
+    },
+    /// 价格回到区间内
+    RangeEntered { symbol: Symbol, price: f64 },
+    /// 触发止损/止盈，网格停止交易
+    Halted {
+        symbol: Symbol,
+        reason: HaltReason,
+        price: f64,
+    },
+    /// 风控拒绝了下单
+    Vetoed(Veto),
+    /// 订单成交，卖出时 `profit` 为预计盈亏(U)
+    OrderFilled {
+        symbol: Symbol,
+        side: TradeSide,
+        price: f64,
+        quantity: f64,
+        profit: Option<f64>,
+    },
+    /// 网格运行出错
+    Error { symbol: Symbol, message: String },
+    /// 获取到最新价格，每轮执行前发布
+    PriceTick { symbol: Symbol, price: f64 },
+    /// 提交订单，买入时 `quantity` 与 `quote` 二选一，`price` 为下单时的市价
+    OrderSubmitted {
+        symbol: Symbol,
+        side: TradeSide,
+        price: f64,
+        quantity: Option<f64>,
+        quote: Option<f64>,
+    },
+    /// 网格的买入/卖出价调整
+    LevelsChanged { symbol: Symbol, buy: f64, sell: f64 },
+    /// 按 k 线重新计算了网格比例
+    RatioRecalculated { symbol: Symbol, ratio: f64 },
+}
+
+impl GridEvent {
+    /// 事件名称，用于过滤及限流
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GridEvent::RangeExited { .. } => "RangeExited",
+            GridEvent::RangeEntered { .. } => "RangeEntered",
+            GridEvent::Halted { .. } => "Halted",
+            GridEvent::Vetoed(_) => "Vetoed",
+            GridEvent::OrderFilled { .. } => "OrderFilled",
+            GridEvent::Error { .. } => "Error",
+            GridEvent::PriceTick { .. } => "PriceTick",
+            GridEvent::OrderSubmitted { .. } => "OrderSubmitted",
+            GridEvent::LevelsChanged { .. } => "LevelsChanged",
+            GridEvent::RatioRecalculated { .. } => "RatioRecalculated",
+        }
+    }
+
+    pub fn symbol(&self) -> &Symbol {
+        match self {
+            GridEvent::RangeExited { symbol, .. }
+            | GridEvent::RangeEntered { symbol, .. }
+            | GridEvent::Halted { symbol, .. }
+            | GridEvent::OrderFilled { symbol, .. }
+            | GridEvent::Error { symbol, .. }
+            | GridEvent::PriceTick { symbol, .. }
+            | GridEvent::OrderSubmitted { symbol, .. }
+            | GridEvent::LevelsChanged { symbol, .. }
+            | GridEvent::RatioRecalculated { symbol, .. } => symbol,
+            GridEvent::Vetoed(veto) => &veto.symbol,
+        }
+    }
+}
+
+/// 广播网格事件，订阅者通过 `subscribe` 获取
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<GridEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// 发布事件，没有订阅者时直接丢弃
+    pub fn publish(&self, event: GridEvent) {
+        debug!("Publish event: {:?}", event);
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<GridEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn subscriber_should_receive_published_events() {
+        let bus = EventBus::default();
+        bus.publish(GridEvent::RangeEntered {
+            symbol: Symbol::Eth,
+            price: 1.0,
+        });
+
+        let mut rx = bus.subscribe();
+        let event = GridEvent::RangeEntered {
+            symbol: Symbol::Btc,
+            price: 2.0,
+        };
+        bus.publish(event.clone());
+        assert_eq!(rx.recv().await.unwrap(), event);
+    }
+}
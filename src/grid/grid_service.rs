@@ -1,21 +1,40 @@
 use std::borrow::Borrow;
 use std::ops::{Add, Div, Mul, Sub};
 use std::sync::Arc;
-use std::time::Duration;
 
 use async_trait::async_trait;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
-use crate::grid::GridService;
-use crate::trade::{MarketService, TradeService};
-use crate::{Coin, Symbol, TgError};
+use crate::clock::Clock;
+use crate::event::{EventBus, GridEvent};
+use crate::grid::price_range::PriceRange;
+use crate::grid::protection::{Halt, Protection};
+use crate::grid::sizing::OrderSize;
+use crate::grid::store::StateStore;
+use crate::grid::trend_filter::TrendFilter;
+use crate::grid::{GridContext, GridService, Ledger};
+use crate::risk::RiskManager;
+use crate::trade::{BalanceGuard, Interval, KlineQuery, MarketService, TradeService};
+use crate::{Coin, Cooldown, Matching, OutOfRange, Sizing, StopAction, Symbol, TgError, TradeSide};
 
-const HUNDRED_PERCENT: f64 = 1.0;
+pub(crate) const HUNDRED_PERCENT: f64 = 1.0;
+/// 停止记录的状态名
+pub const HALT: &str = "halt";
 
 pub struct FixedGridService {
     symbol: Symbol,
     market: Arc<dyn MarketService>,
     trade: Arc<dyn TradeService>,
+    events: EventBus,
+    risk: Arc<RiskManager>,
+    balance: Arc<BalanceGuard>,
+    clock: Arc<dyn Clock>,
+    cooldown: Cooldown,
+    filter: TrendFilter,
+    range: PriceRange,
+    protection: Option<(Protection, StopAction)>,
+    store: StateStore,
+    halt: Option<Halt>,
     db: Db,
 }
 
@@ -25,77 +44,375 @@ pub struct Db {
     quantity: f64,
     profit_ratio: f64,
     double_throw_ratio: f64,
-    history: Vec<f64>,
+    /// 不按 k 线重新计算比例
+    fixed_ratio: bool,
+    sizing: Sizing,
+    matching: Matching,
+    ledger: Ledger,
 }
 
 #[async_trait]
 impl GridService for FixedGridService {
     async fn execute(&mut self, price: f64) -> anyhow::Result<()> {
-        let symbol = self.symbol.borrow();
-        let quantity = self.db.quantity;
-        if self.is_buy(price) {
-            if let Ok(Some(success_price)) = self.trade.buy(symbol, quantity).await {
+        if !self.check_stop(price).await || !self.check_range(price).await {
+            self.clock.sleep(self.cooldown.idle()).await;
+            return Ok(());
+        }
+
+        let symbol = &self.symbol.clone();
+        let buy = match self.is_buy(price) {
+            true => self.allows_buy(price).await,
+            false => None,
+        };
+        if let Some(size) = buy {
+            self.events.publish(size.submitted(symbol, price));
+            if let Ok(Some(fill)) = size.buy(self.trade.as_ref(), symbol).await {
+                self.balance.invalidate().await;
+                self.risk
+                    .record_buy(symbol, 1, fill.price.mul(fill.quantity));
                 info!(
                     "交易成功: 买入币种为: {},价格为: {}, 数量: {},",
-                    symbol, success_price, quantity
+                    symbol, fill.price, fill.quantity
                 );
-                self.reset_ratio().await?;
-                self.db.push_record(success_price);
+                self.filled(TradeSide::Buy, fill.price, fill.quantity, None);
+                self.db.ledger.open(symbol, &fill, None);
+                self.reset_ratio().await;
                 self.modify_price_buy(price);
-                tokio::time::sleep(Duration::from_secs(120)).await;
+                self.clock.sleep(self.cooldown.buy()).await;
             }
-        } else if self.is_sell(price) {
-            if self.is_air() {
-                self.modify_price_air(price);
-            } else {
-                if let Ok(Some(success_price)) = self.trade.sell(symbol, quantity).await {
-                    println!("##Sell: {} {}", success_price, price);
-                    let last_price = self.db.last_record().cloned().ok_or(TgError::Internal(
-                        "Sell success. But price history is empty".to_string(),
-                    ))?;
-                    let profit = price.sub(last_price).mul(quantity);
-                    warn!(
-                        "交易成功：卖出币种为：{}。卖单量为：{}。预计盈利{}U",
-                        symbol, quantity, profit
-                    );
-                    self.reset_ratio().await?;
-                    self.modify_price(last_price, price);
-                    self.db.pop_record();
-                    tokio::time::sleep(Duration::from_secs(60)).await;
-                }
+        } else if self.is_sell(price) && self.is_air() {
+            self.modify_price_air(price);
+        } else if self.is_sell(price) && self.allows_sell(price).await {
+            let lot = self
+                .db
+                .ledger
+                .matched(self.db.matching, price, None)
+                .cloned()
+                .ok_or(TgError::Internal(
+                    "Sell allowed. But ledger is empty".to_string(),
+                ))?;
+            self.submitted(TradeSide::Sell, price, lot.quantity);
+            if let Ok(Some(fill)) = self.trade.sell(symbol, lot.quantity).await {
+                self.balance.invalidate().await;
+                println!("##Sell: {} {}", fill.price, price);
+                let lots = self.db.ledger.lots().len();
+                let pairs = self.db.ledger.close(lot.id, &fill, fill.quote_fee(symbol));
+                let lots = lots - self.db.ledger.lots().len();
+                let cost: f64 = pairs.iter().map(|p| p.cost()).sum();
+                let profit: f64 = pairs.iter().map(|p| p.pnl).sum();
+                self.risk.record_sell(symbol, lots, cost, profit);
+                warn!(
+                    "交易成功：卖出币种为：{}。卖单量为：{}。预计盈利{}U",
+                    symbol, fill.quantity, profit
+                );
+                self.filled(TradeSide::Sell, fill.price, fill.quantity, Some(profit));
+                self.reset_ratio().await;
+                self.modify_price(lot.price, price);
+                self.clock.sleep(self.cooldown.sell()).await;
             }
         } else {
             warn!("币种:{},当前市价：{}。未能满足交易,继续运行", symbol, price);
-            tokio::time::sleep(Duration::from_secs(1)).await;
+            self.clock.sleep(self.cooldown.idle()).await;
         }
 
         Ok(())
     }
+
+    fn update(&mut self, old: &Coin, new: &Coin) -> anyhow::Result<()> {
+        let db = &mut self.db;
+        // 配置中的买卖价只是初始价格，变化时才覆盖运行中调整过的价格
+        if old.buy_price != new.buy_price {
+            db.buy = new.buy_price;
+        }
+        if old.sell_price != new.sell_price {
+            db.sell = new.sell_price;
+        }
+        // 比例在成交后按 k 线重新计算，同样只在配置变化时覆盖
+        if old.profit_ratio != new.profit_ratio {
+            db.profit_ratio = new.profit_ratio;
+        }
+        if old.double_throw_ratio != new.double_throw_ratio {
+            db.double_throw_ratio = new.double_throw_ratio;
+        }
+        db.fixed_ratio = new.fixed_ratio;
+        db.quantity = new.quantity;
+        db.sizing = new.sizing.clone();
+        db.matching = new.matching.unwrap_or_default();
+        self.cooldown = new.cooldown.clone();
+        self.filter.set_rules(new.filters.clone());
+        if old.lower_limit != new.lower_limit
+            || old.upper_limit != new.upper_limit
+            || old.out_of_range != new.out_of_range
+        {
+            self.range.update(new);
+        }
+        self.protection = match (self.protection.take(), new.stop.clone()) {
+            (Some((mut protection, _)), Some(stop)) => {
+                let action = stop.action;
+                protection.set_config(stop);
+                Some((protection, action))
+            }
+            (None, Some(stop)) => {
+                let action = stop.action;
+                Some((Protection::new(stop), action))
+            }
+            (_, None) => None,
+        };
+        Ok(())
+    }
 }
 
 impl FixedGridService {
-    pub fn new(
-        symbol: Symbol,
-        c: &Coin,
-        market: Arc<dyn MarketService>,
-        trade: Arc<dyn TradeService>,
-    ) -> Result<Self, TgError> {
+    pub fn new(ctx: GridContext, c: &Coin) -> Result<Self, TgError> {
+        let GridContext {
+            symbol,
+            market,
+            trade,
+            events,
+            store,
+            risk,
+            balance,
+            clock,
+        } = ctx;
         let db = Db {
             buy: c.buy_price,
             sell: c.sell_price,
-            history: Vec::new(),
+            matching: c.matching.unwrap_or_default(),
+            ledger: Ledger::default(),
             profit_ratio: c.profit_ratio,
             double_throw_ratio: c.double_throw_ratio,
+            fixed_ratio: c.fixed_ratio,
             quantity: c.quantity,
+            sizing: c.sizing.clone(),
         };
+        let halt: Option<Halt> = store.load(&symbol, HALT)?;
+        if let Some(halt) = halt.as_ref() {
+            warn!(
+                "币种:{},网格已停止: {:?}。删除{:?}后恢复",
+                symbol,
+                halt,
+                store.path(&symbol, HALT)
+            );
+        }
+        let filter = TrendFilter::new(
+            symbol.clone(),
+            c.filters.clone(),
+            market.clone(),
+            clock.clone(),
+        );
         Ok(Self {
             symbol,
             market,
             trade,
+            events,
+            risk,
+            balance,
+            clock,
+            cooldown: c.cooldown.clone(),
+            filter,
+            range: PriceRange::new(c),
+            protection: c.stop.clone().map(|s| {
+                let action = s.action;
+                (Protection::new(s), action)
+            }),
+            halt,
+            store,
             db,
         })
     }
 
+    /// 趋势过滤、余额及风控允许买入时返回买入数量
+    async fn allows_buy(&mut self, price: f64) -> Option<OrderSize> {
+        if !self.filter.allows(TradeSide::Buy, price).await {
+            return None;
+        }
+        let depth = self.db.ledger.lots().len();
+        let size = match OrderSize::of(
+            &self.db.sizing,
+            self.db.quantity,
+            depth,
+            &self.symbol,
+            &self.balance,
+        )
+        .await
+        {
+            Ok(size) => size,
+            Err(e) => {
+                warn!("币种:{},计算买入数量失败: {}", self.symbol, e);
+                return None;
+            }
+        };
+        let quote = size.quote(price);
+        let allowed = self.balance.can_buy(&self.symbol, quote).await
+            && self.risk.check(&self.symbol, TradeSide::Buy, quote).is_ok();
+        allowed.then_some(size)
+    }
+
+    /// 趋势过滤、余额及风控是否允许卖出配对的一份
+    async fn allows_sell(&mut self, price: f64) -> bool {
+        let quantity = match self.db.ledger.matched(self.db.matching, price, None) {
+            Some(lot) => lot.quantity,
+            None => return false,
+        };
+        self.filter.allows(TradeSide::Sell, price).await
+            && self.balance.can_sell(&self.symbol, quantity).await
+            && self.risk.check(&self.symbol, TradeSide::Sell, 0.0).is_ok()
+    }
+
+    /// 检查止损/止盈，返回 false 时本轮不交易
+    async fn check_stop(&mut self, price: f64) -> bool {
+        if self.halt.is_some() {
+            return false;
+        }
+        let (protection, action) = match self.protection.as_mut() {
+            Some((protection, action)) => (protection, *action),
+            None => return true,
+        };
+        let reason = match protection.check(price, self.db.ledger.lots(), self.db.ledger.realized())
+        {
+            Some(reason) => reason,
+            None => return true,
+        };
+
+        warn!(
+            "币种:{},当前市价：{}。触发{:?}，执行{:?}",
+            self.symbol, price, reason, action
+        );
+        if action == StopAction::Liquidate {
+            self.liquidate(price).await;
+            if !self.is_air() {
+                // 清仓失败，下一轮重试
+                return false;
+            }
+        }
+
+        let halt = Halt {
+            reason,
+            price,
+            time: self.clock.now(),
+        };
+        if let Err(e) = self.store.save(&self.symbol, HALT, &halt) {
+            error!("币种:{},保存停止记录失败: {}", self.symbol, e);
+        }
+        self.events.publish(GridEvent::Halted {
+            symbol: self.symbol.clone(),
+            reason: halt.reason.clone(),
+            price,
+        });
+        self.halt = Some(halt);
+        false
+    }
+
+    /// 检查价格区间，返回 false 时本轮不交易
+    async fn check_range(&mut self, price: f64) -> bool {
+        let breach = match self.range.breach(price) {
+            Some(breach) => breach,
+            None => {
+                if self.range.outside {
+                    self.range.outside = false;
+                    info!(
+                        "币种:{},当前市价：{}。回到价格区间，恢复交易",
+                        self.symbol, price
+                    );
+                    self.events.publish(GridEvent::RangeEntered {
+                        symbol: self.symbol.clone(),
+                        price,
+                    });
+                }
+                return true;
+            }
+        };
+
+        let action = self.range.action;
+        if !self.range.outside || action == OutOfRange::Shift {
+            warn!(
+                "币种:{},当前市价：{}。超出价格区间{:?}，执行{:?}",
+                self.symbol, price, breach, action
+            );
+            self.events.publish(GridEvent::RangeExited {
+                symbol: self.symbol.clone(),
+                price,
+                limit: breach.limit(),
+                action,
+            });
+        }
+        match action {
+            OutOfRange::Pause => {
+                self.range.outside = true;
+                false
+            }
+            OutOfRange::StopLoss => {
+                self.range.outside = true;
+                self.liquidate(price).await;
+                false
+            }
+            OutOfRange::Shift => {
+                self.range.shift(price);
+                info!(
+                    "币种:{},平移后的价格区间为:{:?}",
+                    self.symbol,
+                    self.range.limits()
+                );
+                self.modify_price_buy(price);
+                true
+            }
+        }
+    }
+
+    /// 以市价 `price` 卖出全部持仓
+    async fn liquidate(&mut self, price: f64) {
+        if self.is_air() {
+            return;
+        }
+        let lots = self.db.ledger.lots().len();
+        let quantity = self.db.ledger.quantity();
+        if !self.balance.can_sell(&self.symbol, quantity).await
+            || self.risk.check(&self.symbol, TradeSide::Sell, 0.0).is_err()
+        {
+            return;
+        }
+        self.submitted(TradeSide::Sell, price, quantity);
+        match self.trade.sell(self.symbol.borrow(), quantity).await {
+            Ok(Some(fill)) => {
+                self.balance.invalidate().await;
+                let pairs = self
+                    .db
+                    .ledger
+                    .close_all(&fill, fill.quote_fee(&self.symbol));
+                let cost: f64 = pairs.iter().map(|p| p.cost()).sum();
+                let profit: f64 = pairs.iter().map(|p| p.pnl).sum();
+                warn!(
+                    "清仓成功：卖出币种为：{}。卖单量为：{}，成交价为：{}。预计盈亏{}U",
+                    self.symbol, fill.quantity, fill.price, profit
+                );
+                self.filled(TradeSide::Sell, fill.price, fill.quantity, Some(profit));
+                self.risk.record_sell(&self.symbol, lots, cost, profit);
+            }
+            Ok(None) => warn!("币种:{},清仓未成交", self.symbol),
+            Err(e) => warn!("币种:{},清仓失败: {}", self.symbol, e),
+        }
+    }
+
+    fn submitted(&self, side: TradeSide, price: f64, quantity: f64) {
+        self.events.publish(GridEvent::OrderSubmitted {
+            symbol: self.symbol.clone(),
+            side,
+            price,
+            quantity: Some(quantity),
+            quote: None,
+        });
+    }
+
+    fn filled(&self, side: TradeSide, price: f64, quantity: f64, profit: Option<f64>) {
+        self.events.publish(GridEvent::OrderFilled {
+            symbol: self.symbol.clone(),
+            side,
+            price,
+            quantity,
+            profit,
+        });
+    }
+
     fn is_buy(&self, price: f64) -> bool {
         self.db.buy >= price
     }
@@ -105,14 +422,34 @@ impl FixedGridService {
     }
 
     fn is_air(&self) -> bool {
-        self.db.history.is_empty()
+        self.db.ledger.is_empty()
     }
 
-    async fn reset_ratio(&mut self) -> anyhow::Result<()> {
-        let value = self.calc_k_lines().await?;
+    /// 成交后按 k 线重新计算比例，失败时沿用原比例，不影响已成交订单的后续处理
+    async fn reset_ratio(&mut self) {
+        if self.db.fixed_ratio {
+            return;
+        }
+        let value = match self.calc_k_lines().await {
+            Ok(value) => value,
+            Err(e) => {
+                error!(
+                    "币种:{},重新计算网格比例失败，沿用原比例: {}",
+                    self.symbol, e
+                );
+                self.events.publish(GridEvent::Error {
+                    symbol: self.symbol.clone(),
+                    message: format!("重新计算网格比例失败: {}", e),
+                });
+                return;
+            }
+        };
         self.db.double_throw_ratio = value;
         self.db.profit_ratio = value;
-        Ok(())
+        self.events.publish(GridEvent::RatioRecalculated {
+            symbol: self.symbol.clone(),
+            ratio: value,
+        });
     }
 
     fn modify_price_buy(&mut self, market_price: f64) {
@@ -135,33 +472,327 @@ impl FixedGridService {
             "修改后的补仓（买入）价格为:{},修改后的网格（卖出）价格为:{}.",
             self.db.buy, self.db.sell
         );
+        self.events.publish(GridEvent::LevelsChanged {
+            symbol: self.symbol.clone(),
+            buy: self.db.buy,
+            sell: self.db.sell,
+        });
     }
 
     async fn calc_k_lines(&self) -> anyhow::Result<f64> {
-        let k_lines = self.market.k_lines(self.symbol.borrow()).await?;
-        let mut percent_total = 0.0;
-        for line in k_lines.iter() {
-            let v = (line.high - line.open).abs() / line.low;
-            percent_total = v + percent_total;
-        }
-        let value = percent_total.div(k_lines.len() as f64);
-        Ok(value)
+        calc_k_lines(self.market.as_ref(), self.symbol.borrow()).await
     }
 }
 
-impl Db {
-    fn last_record(&self) -> Option<&f64> {
-        self.history.last()
+/// 以最近 20 根 4 小时 k 线的平均振幅作为新的网格比例
+pub(crate) async fn calc_k_lines(
+    market: &dyn MarketService,
+    symbol: &Symbol,
+) -> anyhow::Result<f64> {
+    let k_lines = market
+        .k_lines(symbol, &KlineQuery::latest(Interval::Hour4, 20))
+        .await?;
+    if k_lines.is_empty() {
+        return Err(TgError::Internal("No k lines to calculate ratio".to_string()).into());
+    }
+    let mut percent_total = 0.0;
+    for line in k_lines.iter() {
+        let v = (line.high - line.open).abs() / line.low;
+        percent_total += v;
+    }
+    let value = percent_total.div(k_lines.len() as f64);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{TimeZone, Utc};
+    use tempfile::TempDir;
+
+    use crate::clock::ManualClock;
+    use crate::trade::fake::{bar, FakeMarket, FakeTrade};
+    use crate::RiskConfig;
+
+    use super::*;
+
+    /// k 线平均振幅为 5%
+    const RATIO: f64 = 0.05;
+
+    struct Harness {
+        grid: FixedGridService,
+        coin: Coin,
+        market: Arc<FakeMarket>,
+        trade: Arc<FakeTrade>,
+        events: EventBus,
+        _state: TempDir,
+    }
+
+    impl Harness {
+        /// 买入价 100，卖出价 110，比例均为 10%，每次 1 个
+        fn new(prices: &[f64]) -> Self {
+            Self::with_fee_rate(prices, 0.0)
+        }
+
+        fn with_fee_rate(prices: &[f64], fee_rate: f64) -> Self {
+            let coin: Coin = toml::from_str(
+                r#"
+                buy_price = 100
+                sell_price = 110
+                profit_ratio = '10%'
+                double_throw_ratio = '10%'
+                quantity = 1
+                "#,
+            )
+            .unwrap();
+            let clock: Arc<dyn Clock> = Arc::new(ManualClock::new(
+                Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            ));
+            let market = Arc::new(
+                FakeMarket::new(prices.to_vec())
+                    .with_k_lines(vec![bar(100.0, 105.0, 100.0, 102.0)]),
+            );
+            let trade = Arc::new(
+                FakeTrade::new(market.clone())
+                    .with_clock(clock.clone())
+                    .with_balance("USDT", 1000.0)
+                    .with_fee_rate(fee_rate),
+            );
+            let state = TempDir::new().unwrap();
+            let events = EventBus::default();
+            let ctx = GridContext {
+                symbol: Symbol::Eth,
+                market: market.clone(),
+                trade: trade.clone(),
+                events: events.clone(),
+                store: StateStore::new(state.path()),
+                risk: Arc::new(RiskManager::new(
+                    RiskConfig::default(),
+                    events.clone(),
+                    clock.clone(),
+                )),
+                balance: Arc::new(BalanceGuard::new(
+                    trade.clone(),
+                    BTreeMap::new(),
+                    clock.clone(),
+                )),
+                clock,
+            };
+            Self {
+                grid: FixedGridService::new(ctx, &coin).unwrap(),
+                coin,
+                market,
+                trade,
+                events,
+                _state: state,
+            }
+        }
+
+        /// 按脚本取下一个价格并执行
+        async fn tick(&mut self) -> anyhow::Result<()> {
+            let price = self.market.ticker_price(&Symbol::Eth).await?;
+            self.grid.execute(price).await
+        }
+
+        fn prices(&self) -> (f64, f64) {
+            (self.grid.db.buy, self.grid.db.sell)
+        }
     }
 
-    fn push_record(&mut self, price: f64) {
-        self.history.push(price);
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
     }
 
-    fn pop_record(&mut self) {
-        self.history.pop();
+    #[tokio::test]
+    async fn price_below_buy_should_buy_and_recalculate_ratio() {
+        let mut h = Harness::new(&[95.0]);
+        h.tick().await.unwrap();
+
+        let orders = h.trade.orders();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].side, TradeSide::Buy);
+        assert_eq!(orders[0].quantity, Some(1.0));
+        assert_eq!(h.grid.db.ledger.lots().len(), 1);
+        assert_eq!(h.grid.db.ledger.lots()[0].price, 95.0);
+
+        assert_close(h.grid.db.profit_ratio, RATIO);
+        assert_close(h.grid.db.double_throw_ratio, RATIO);
+        let (buy, sell) = h.prices();
+        assert_close(buy, 95.0 * (1.0 - RATIO));
+        assert_close(sell, 95.0 * (1.0 + RATIO));
     }
-}
 
-#[cfg(test)]
-mod tests {}
+    #[tokio::test]
+    async fn update_should_keep_recalculated_values() {
+        let mut h = Harness::new(&[95.0]);
+        h.tick().await.unwrap();
+        let prices = h.prices();
+
+        // 只修改数量时保留运行中调整过的价格和比例
+        let mut new = h.coin.clone();
+        new.quantity = 2.0;
+        h.grid.update(&h.coin, &new).unwrap();
+        assert_eq!(h.grid.db.quantity, 2.0);
+        assert_eq!(h.prices(), prices);
+        assert_close(h.grid.db.profit_ratio, RATIO);
+        assert_close(h.grid.db.double_throw_ratio, RATIO);
+
+        let old = new.clone();
+        new.profit_ratio = 0.2;
+        new.buy_price = 80.0;
+        h.grid.update(&old, &new).unwrap();
+        assert_eq!(h.grid.db.profit_ratio, 0.2);
+        assert_close(h.grid.db.double_throw_ratio, RATIO);
+        assert_eq!(h.prices(), (80.0, prices.1));
+    }
+
+    #[tokio::test]
+    async fn risk_should_record_pnl_after_fees() {
+        let mut h = Harness::with_fee_rate(&[95.0, 105.0], 0.01);
+        h.tick().await.unwrap();
+        h.tick().await.unwrap();
+
+        // 盈利 10U，扣除买卖手续费 0.95U 和 1.05U
+        let realized = h.grid.db.ledger.realized();
+        assert_close(realized, 8.0);
+        assert_close(h.grid.risk.daily_pnl(), realized);
+    }
+
+    #[tokio::test]
+    async fn buy_should_publish_order_lifecycle_events() {
+        let mut h = Harness::new(&[95.0]);
+        let mut rx = h.events.subscribe();
+        h.tick().await.unwrap();
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        let kinds: Vec<_> = events.iter().map(|e| e.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "OrderSubmitted",
+                "OrderFilled",
+                "RatioRecalculated",
+                "LevelsChanged"
+            ]
+        );
+        assert_eq!(
+            events[0],
+            GridEvent::OrderSubmitted {
+                symbol: Symbol::Eth,
+                side: TradeSide::Buy,
+                price: 95.0,
+                quantity: Some(1.0),
+                quote: None,
+            }
+        );
+        assert_eq!(
+            events[2],
+            GridEvent::RatioRecalculated {
+                symbol: Symbol::Eth,
+                ratio: h.grid.db.profit_ratio,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn price_above_sell_should_sell_held_lot() {
+        let mut h = Harness::new(&[95.0, 100.0]);
+        h.tick().await.unwrap();
+        h.tick().await.unwrap();
+
+        let orders = h.trade.orders();
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[1].side, TradeSide::Sell);
+        assert_eq!(orders[1].quantity, Some(1.0));
+        assert!(h.grid.db.ledger.is_empty());
+        assert_close(h.grid.db.ledger.realized(), 5.0);
+        assert_close(h.trade.balance("USDT"), 1005.0);
+
+        // 以买入价重新设置网格，市价已高于新的卖出价时以市价为准
+        let (buy, sell) = h.prices();
+        assert_close(buy, 95.0 * (1.0 - RATIO));
+        assert_close(sell, 100.0 * (1.0 + RATIO));
+    }
+
+    #[tokio::test]
+    async fn empty_grid_should_follow_price_up_without_orders() {
+        let mut h = Harness::new(&[120.0, 130.0]);
+        h.tick().await.unwrap();
+
+        assert!(h.trade.orders().is_empty());
+        let (buy, sell) = h.prices();
+        assert_close(buy, 110.0 * 0.9);
+        assert_close(sell, 110.0 * 1.1);
+        // 未成交不重新计算比例
+        assert_close(h.grid.db.profit_ratio, 0.1);
+
+        // 每次只上移一格
+        h.tick().await.unwrap();
+        assert!(h.trade.orders().is_empty());
+        let (buy, sell) = h.prices();
+        assert_close(buy, 121.0 * 0.9);
+        assert_close(sell, 121.0 * 1.1);
+    }
+
+    #[tokio::test]
+    async fn price_between_buy_and_sell_should_not_trade() {
+        let mut h = Harness::new(&[105.0]);
+        h.tick().await.unwrap();
+        assert!(h.trade.orders().is_empty());
+        assert_eq!(h.prices(), (100.0, 110.0));
+    }
+
+    #[tokio::test]
+    async fn failed_order_should_leave_grid_unchanged() {
+        let mut h = Harness::new(&[95.0, 95.0]);
+        h.trade.fail_next(1);
+        h.tick().await.unwrap();
+
+        let orders = h.trade.orders();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].fill, None);
+        assert!(h.grid.db.ledger.is_empty());
+        assert_eq!(h.prices(), (100.0, 110.0));
+
+        h.trade.reject_next(1);
+        h.tick().await.unwrap();
+        assert!(h.grid.db.ledger.is_empty());
+
+        h.tick().await.unwrap();
+        assert_eq!(h.grid.db.ledger.lots().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn ratio_failure_should_still_move_prices() {
+        let mut h = Harness::new(&[95.0, 95.0]);
+        let mut rx = h.events.subscribe();
+        let price = h.market.ticker_price(&Symbol::Eth).await.unwrap();
+        // 买入成交后 k 线请求失败，沿用原比例调整价格
+        h.market.fail_next(1);
+        h.grid.execute(price).await.unwrap();
+
+        assert_eq!(h.grid.db.ledger.lots().len(), 1);
+        assert_close(h.grid.db.profit_ratio, 0.1);
+        let (buy, sell) = h.prices();
+        assert_close(buy, 95.0 * 0.9);
+        assert_close(sell, 95.0 * 1.1);
+        let mut kinds = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            kinds.push(event.kind());
+        }
+        assert!(kinds.contains(&"Error"), "{:?}", kinds);
+
+        // 同一价格不再重复买入
+        h.tick().await.unwrap();
+        assert_eq!(h.trade.orders().len(), 1);
+    }
+}
@@ -0,0 +1,125 @@
+//! 技术指标计算，输入均为按时间升序排列的收盘价
+
+/// 简单移动平均线序列，第 i 个值对应 `values[i + period - 1]`
+pub fn sma(values: &[f64], period: usize) -> Vec<f64> {
+    if period == 0 || values.len() < period {
+        return Vec::new();
+    }
+    values
+        .windows(period)
+        .map(|w| w.iter().sum::<f64>() / period as f64)
+        .collect()
+}
+
+/// 指数移动平均线序列，以前 `period` 个值的 SMA 作为种子
+pub fn ema(values: &[f64], period: usize) -> Vec<f64> {
+    if period == 0 || values.len() < period {
+        return Vec::new();
+    }
+    let k = 2.0 / (period as f64 + 1.0);
+    let seed = values[..period].iter().sum::<f64>() / period as f64;
+    let mut result = Vec::with_capacity(values.len() - period + 1);
+    result.push(seed);
+    for v in &values[period..] {
+        let prev = result[result.len() - 1];
+        result.push(v * k + prev * (1.0 - k));
+    }
+    result
+}
+
+/// 相对强弱指数(Wilder 平滑)，取值范围 0~100
+pub fn rsi(values: &[f64], period: usize) -> Vec<f64> {
+    if period == 0 || values.len() <= period {
+        return Vec::new();
+    }
+    let changes: Vec<f64> = values.windows(2).map(|w| w[1] - w[0]).collect();
+    let mut gain = changes[..period].iter().filter(|c| **c > 0.0).sum::<f64>() / period as f64;
+    let mut loss = -changes[..period].iter().filter(|c| **c < 0.0).sum::<f64>() / period as f64;
+
+    let to_rsi = |gain: f64, loss: f64| {
+        if loss == 0.0 {
+            100.0
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        }
+    };
+
+    let mut result = vec![to_rsi(gain, loss)];
+    for c in &changes[period..] {
+        gain = (gain * (period - 1) as f64 + c.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-c).max(0.0)) / period as f64;
+        result.push(to_rsi(gain, loss));
+    }
+    result
+}
+
+/// MACD 柱状图序列: (快线 EMA - 慢线 EMA) - 信号线
+pub fn macd_histogram(values: &[f64], fast: usize, slow: usize, signal: usize) -> Vec<f64> {
+    if fast == 0 || fast >= slow {
+        return Vec::new();
+    }
+    let fast_line = ema(values, fast);
+    let slow_line = ema(values, slow);
+    if slow_line.is_empty() {
+        return Vec::new();
+    }
+    // 两条线的尾部对齐
+    let offset = fast_line.len() - slow_line.len();
+    let macd: Vec<f64> = slow_line
+        .iter()
+        .zip(&fast_line[offset..])
+        .map(|(s, f)| f - s)
+        .collect();
+    let signal_line = ema(&macd, signal);
+    let offset = macd.len().saturating_sub(signal_line.len());
+    signal_line
+        .iter()
+        .zip(&macd[offset..])
+        .map(|(s, m)| m - s)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sma_should_average_windows() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(sma(&values, 3), vec![2.0, 3.0, 4.0]);
+        assert!(sma(&values, 6).is_empty());
+    }
+
+    #[test]
+    fn ema_should_follow_trend() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let result = ema(&values, 3);
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0], 2.0);
+        assert_eq!(result[1], 3.0);
+        assert!(result.windows(2).all(|w| w[1] > w[0]));
+    }
+
+    #[test]
+    fn rsi_should_be_bounded() {
+        let up: Vec<f64> = (0..30).map(|i| i as f64).collect();
+        assert_eq!(rsi(&up, 14).last().cloned(), Some(100.0));
+
+        let down: Vec<f64> = (0..30).map(|i| 100.0 - i as f64).collect();
+        assert_eq!(rsi(&down, 14).last().cloned(), Some(0.0));
+
+        let zigzag: Vec<f64> = (0..30)
+            .map(|i| if i % 2 == 0 { 10.0 } else { 11.0 })
+            .collect();
+        let value = rsi(&zigzag, 14).last().cloned().unwrap();
+        assert!(value > 40.0 && value < 60.0);
+    }
+
+    #[test]
+    fn macd_histogram_should_align_with_input() {
+        let values: Vec<f64> = (0..60).map(|i| (i as f64).sqrt()).collect();
+        let hist = macd_histogram(&values, 12, 26, 9);
+        assert_eq!(hist.len(), 60 - 26 + 1 - 9 + 1);
+        assert!(macd_histogram(&values, 26, 12, 9).is_empty());
+    }
+}
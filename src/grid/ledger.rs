@@ -0,0 +1,249 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::trade::Fill;
+use crate::{Matching, Symbol};
+
+/// 保留的最近配对记录数量
+const MAX_PAIRS: usize = 1000;
+
+/// 一份持仓，即一次买入成交
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Lot {
+    pub id: u64,
+    pub time: DateTime<Utc>,
+    /// 成交均价
+    pub price: f64,
+    /// 剩余数量，部分卖出后减少
+    pub quantity: f64,
+    /// 剩余数量对应的手续费(U)
+    pub fee: f64,
+    pub order_id: Option<usize>,
+    /// 经典网格中买入的价位
+    pub level: Option<usize>,
+}
+
+/// 一次卖出与一份持仓的配对
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pair {
+    pub lot: u64,
+    pub buy_price: f64,
+    pub sell_price: f64,
+    pub quantity: f64,
+    /// 买入和卖出分摊的手续费(U)
+    pub fee: f64,
+    /// 扣除手续费后的已实现盈亏(U)
+    pub pnl: f64,
+    pub time: DateTime<Utc>,
+}
+
+impl Pair {
+    /// 配对部分的买入成本
+    pub fn cost(&self) -> f64 {
+        self.buy_price * self.quantity
+    }
+}
+
+/// 持仓台账: 每次买入记为一份持仓，卖出时按配对方式扣减并计算盈亏
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Ledger {
+    next_id: u64,
+    lots: Vec<Lot>,
+    pairs: Vec<Pair>,
+    realized: f64,
+}
+
+impl Ledger {
+    /// 当前持仓，按买入先后排列
+    pub fn lots(&self) -> &[Lot] {
+        &self.lots
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lots.is_empty()
+    }
+
+    /// 持仓总数量
+    pub fn quantity(&self) -> f64 {
+        self.lots.iter().map(|l| l.quantity).sum()
+    }
+
+    /// 最近的配对记录
+    pub fn pairs(&self) -> &[Pair] {
+        &self.pairs
+    }
+
+    /// 累计已实现盈亏(U)
+    pub fn realized(&self) -> f64 {
+        self.realized
+    }
+
+    /// 记录买入成交，以基础资产收取的手续费从持仓数量中扣除，同时按计价资产计入成本
+    pub fn open(&mut self, symbol: &Symbol, fill: &Fill, level: Option<usize>) -> &Lot {
+        self.next_id += 1;
+        self.lots.push(Lot {
+            id: self.next_id,
+            time: fill.time,
+            price: fill.price,
+            quantity: fill.net_quantity(symbol),
+            fee: fill.quote_fee(symbol),
+            order_id: fill.order_id,
+            level,
+        });
+        self.lots.last().unwrap()
+    }
+
+    /// 以 `price` 卖出时按 `matching` 配对的持仓
+    pub fn matched(&self, matching: Matching, price: f64, level: Option<usize>) -> Option<&Lot> {
+        match matching {
+            Matching::Lifo => self.lots.last(),
+            Matching::Fifo => self.lots.first(),
+            Matching::Pair => match level {
+                Some(level) => self.lots.iter().find(|l| l.level == Some(level)),
+                None => self
+                    .lots
+                    .iter()
+                    .filter(|l| l.price <= price)
+                    .max_by(|a, b| a.price.total_cmp(&b.price)),
+            }
+            .or_else(|| self.lots.last()),
+        }
+    }
+
+    /// 记录卖出成交: 先扣减 `lot`，超出部分从最近买入的持仓扣减
+    pub fn close(&mut self, lot: u64, fill: &Fill, fee: f64) -> Vec<Pair> {
+        let mut order: Vec<u64> = self.lots.iter().rev().map(|l| l.id).collect();
+        if let Some(i) = order.iter().position(|id| *id == lot) {
+            order.remove(i);
+            order.insert(0, lot);
+        }
+        self.settle(&order, fill, fee)
+    }
+
+    /// 记录清仓成交，按买入先后扣减
+    pub fn close_all(&mut self, fill: &Fill, fee: f64) -> Vec<Pair> {
+        let order: Vec<u64> = self.lots.iter().map(|l| l.id).collect();
+        self.settle(&order, fill, fee)
+    }
+
+    fn settle(&mut self, order: &[u64], fill: &Fill, fee: f64) -> Vec<Pair> {
+        let mut remaining = fill.quantity;
+        let mut pairs = Vec::new();
+        for id in order {
+            if remaining <= 0.0 {
+                break;
+            }
+            let lot = match self.lots.iter_mut().find(|l| l.id == *id) {
+                Some(lot) => lot,
+                None => continue,
+            };
+            let quantity = remaining.min(lot.quantity);
+            let buy_fee = lot.fee * quantity / lot.quantity;
+            let sell_fee = fee * quantity / fill.quantity;
+            lot.quantity -= quantity;
+            lot.fee -= buy_fee;
+            remaining -= quantity;
+            pairs.push(Pair {
+                lot: lot.id,
+                buy_price: lot.price,
+                sell_price: fill.price,
+                quantity,
+                fee: buy_fee + sell_fee,
+                pnl: (fill.price - lot.price) * quantity - buy_fee - sell_fee,
+                time: fill.time,
+            });
+        }
+        // 数量误差很小的持仓视为已卖完
+        self.lots.retain(|l| l.quantity > 1e-12);
+
+        self.realized += pairs.iter().map(|p| p.pnl).sum::<f64>();
+        self.pairs.extend(pairs.iter().cloned());
+        if self.pairs.len() > MAX_PAIRS {
+            let excess = self.pairs.len() - MAX_PAIRS;
+            self.pairs.drain(..excess);
+        }
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    /// 以 `asset` 收取手续费的成交
+    fn fill(price: f64, quantity: f64, fee: f64, asset: &str) -> Fill {
+        Fill {
+            fee,
+            fee_asset: Some(asset.to_string()),
+            ..Fill::new(price, quantity, at())
+        }
+    }
+
+    fn ledger() -> Ledger {
+        let mut ledger = Ledger::default();
+        ledger.open(&Symbol::Eth, &fill(100.0, 1.0, 0.1, "USDT"), Some(2));
+        ledger.open(&Symbol::Eth, &fill(90.0, 1.0, 0.1, "USDT"), Some(1));
+        ledger.open(&Symbol::Eth, &fill(80.0, 2.0, 0.2, "USDT"), Some(0));
+        ledger
+    }
+
+    #[test]
+    fn base_asset_fee_should_reduce_quantity() {
+        let mut ledger = Ledger::default();
+        let lot = ledger.open(&Symbol::Eth, &fill(100.0, 1.0, 0.001, "ETH"), None);
+        assert_eq!(lot.quantity, 0.999);
+        assert!((lot.fee - 0.1).abs() < 1e-9);
+
+        // 其它币种的手续费不影响数量
+        let lot = ledger.open(&Symbol::Eth, &fill(100.0, 1.0, 0.01, "BNB"), None);
+        assert_eq!((lot.quantity, lot.fee), (1.0, 0.0));
+        assert_eq!(ledger.quantity(), 1.999);
+
+        // 卖出全部到账数量后持仓清空，成本包含买入手续费
+        let pairs = ledger.close_all(&Fill::new(110.0, 1.999, at()), 0.0);
+        assert!(ledger.is_empty());
+        assert!((pairs[0].pnl - (10.0 * 0.999 - 0.1)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lots_should_be_matched_by_policy() {
+        let ledger = ledger();
+        assert_eq!(ledger.matched(Matching::Lifo, 95.0, None).unwrap().id, 3);
+        assert_eq!(ledger.matched(Matching::Fifo, 95.0, None).unwrap().id, 1);
+        assert_eq!(ledger.matched(Matching::Pair, 95.0, None).unwrap().id, 2);
+        assert_eq!(ledger.matched(Matching::Pair, 70.0, None).unwrap().id, 3);
+        assert_eq!(ledger.matched(Matching::Pair, 0.0, Some(2)).unwrap().id, 1);
+        assert!(Ledger::default()
+            .matched(Matching::Fifo, 95.0, None)
+            .is_none());
+    }
+
+    #[test]
+    fn sells_should_realize_pnl_per_pair() {
+        let mut ledger = ledger();
+        let mut fill = Fill::new(110.0, 1.5, at());
+        fill.order_id = Some(9);
+        let pairs = ledger.close(2, &fill, 0.3);
+        assert_eq!(pairs.len(), 2);
+        assert_eq!((pairs[0].lot, pairs[0].quantity), (2, 1.0));
+        assert!((pairs[0].pnl - (20.0 - 0.1 - 0.2)).abs() < 1e-9);
+        assert_eq!((pairs[1].lot, pairs[1].quantity), (3, 0.5));
+        assert!((pairs[1].pnl - (15.0 - 0.05 - 0.1)).abs() < 1e-9);
+
+        assert_eq!(ledger.lots().len(), 2);
+        assert_eq!(ledger.quantity(), 2.5);
+        assert!((ledger.lots()[1].fee - 0.15).abs() < 1e-9);
+
+        let pairs = ledger.close_all(&Fill::new(70.0, 2.5, at()), 0.0);
+        assert_eq!(pairs.iter().map(|p| p.lot).collect::<Vec<_>>(), vec![1, 3]);
+        assert!(ledger.is_empty());
+        let realized: f64 = ledger.pairs().iter().map(|p| p.pnl).sum();
+        assert!((ledger.realized() - realized).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,375 @@
+use std::borrow::Borrow;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::{info, warn};
+
+use crate::clock::Clock;
+use crate::event::{EventBus, GridEvent};
+use crate::grid::sizing::OrderSize;
+use crate::grid::trend_filter::TrendFilter;
+use crate::grid::{GridContext, GridService, Ledger};
+use crate::risk::RiskManager;
+use crate::trade::{BalanceGuard, Fill, TradeService};
+use crate::{Coin, Cooldown, LevelGrid, Matching, Sizing, Spacing, Symbol, TgError, TradeSide};
+
+/// 经典网格: 在上下界之间划分多个价位，每个价位持有一份，在上一个价位卖出。
+/// 只在价格自上而下穿过价位时买入，启动后的第一次行情只记录价格
+pub struct LevelGridService {
+    symbol: Symbol,
+    trade: Arc<dyn TradeService>,
+    events: EventBus,
+    risk: Arc<RiskManager>,
+    balance: Arc<BalanceGuard>,
+    clock: Arc<dyn Clock>,
+    cooldown: Cooldown,
+    filter: TrendFilter,
+    quantity: f64,
+    sizing: Sizing,
+    ladder: Ladder,
+    ledger: Ledger,
+}
+
+/// 网格价位及每个价位的持仓
+pub struct Ladder {
+    prices: Vec<f64>,
+    /// `holdings[i]` 为 `prices[i]` 是否持仓
+    holdings: Vec<bool>,
+    last_price: Option<f64>,
+}
+
+#[async_trait]
+impl GridService for LevelGridService {
+    async fn execute(&mut self, price: f64) -> anyhow::Result<()> {
+        let symbol = self.symbol.borrow();
+        let (mut sold, mut bought) = (false, false);
+
+        let sells = self.ladder.sells(price);
+        if !sells.is_empty() && self.filter.allows(TradeSide::Sell, price).await {
+            for level in sells {
+                // 每个价位的持仓只在上一个价位卖出，数量及盈亏都按该价位的一份计算
+                let lot = match self.ledger.matched(Matching::Pair, price, Some(level)) {
+                    Some(lot) => lot.clone(),
+                    None => break,
+                };
+                if !self.balance.can_sell(symbol, lot.quantity).await
+                    || self.risk.check(symbol, TradeSide::Sell, 0.0).is_err()
+                {
+                    break;
+                }
+                self.events.publish(GridEvent::OrderSubmitted {
+                    symbol: symbol.clone(),
+                    side: TradeSide::Sell,
+                    price,
+                    quantity: Some(lot.quantity),
+                    quote: None,
+                });
+                if let Ok(Some(fill)) = self.trade.sell(symbol, lot.quantity).await {
+                    self.balance.invalidate().await;
+                    self.ladder.release(level);
+                    let pairs = self.ledger.close(lot.id, &fill, fill.quote_fee(symbol));
+                    let cost: f64 = pairs.iter().map(|p| p.cost()).sum();
+                    let profit: f64 = pairs.iter().map(|p| p.pnl).sum();
+                    self.risk.record_sell(symbol, 1, cost, profit);
+                    warn!(
+                        "交易成功：卖出币种为：{}。网格价位：{}，成交价：{}，卖单量为：{}。预计盈利{}U",
+                        symbol,
+                        self.ladder.prices[level + 1],
+                        fill.price,
+                        fill.quantity,
+                        profit
+                    );
+                    self.filled(TradeSide::Sell, &fill, Some(profit));
+                    sold = true;
+                }
+            }
+        }
+
+        let buys = self.ladder.buys(price);
+        if !buys.is_empty() && self.filter.allows(TradeSide::Buy, price).await {
+            for level in buys {
+                // 越靠下的价位深度越大
+                let depth = self.ladder.holdings.len() - 1 - level;
+                let size =
+                    match OrderSize::of(&self.sizing, self.quantity, depth, symbol, &self.balance)
+                        .await
+                    {
+                        Ok(size) => size,
+                        Err(e) => {
+                            warn!("币种:{},计算买入数量失败: {}", symbol, e);
+                            break;
+                        }
+                    };
+                let quote = size.quote(price);
+                if !self.balance.can_buy(symbol, quote).await
+                    || self.risk.check(symbol, TradeSide::Buy, quote).is_err()
+                {
+                    break;
+                }
+                self.events.publish(size.submitted(symbol, price));
+                if let Ok(Some(fill)) = size.buy(self.trade.as_ref(), symbol).await {
+                    self.balance.invalidate().await;
+                    self.risk.record_buy(symbol, 1, fill.price * fill.quantity);
+                    info!(
+                        "交易成功: 买入币种为: {},网格价位：{}，成交价: {}, 数量: {},",
+                        symbol, self.ladder.prices[level], fill.price, fill.quantity
+                    );
+                    self.filled(TradeSide::Buy, &fill, None);
+                    self.ladder.hold(level);
+                    self.ledger.open(symbol, &fill, Some(level));
+                    bought = true;
+                }
+            }
+        }
+
+        self.ladder.last_price = Some(price);
+        // 与固定网格相同，成交后按买入或卖出的冷却时间等待
+        let wait = match (bought, sold) {
+            (true, true) => self.cooldown.buy().max(self.cooldown.sell()),
+            (true, false) => self.cooldown.buy(),
+            (false, true) => self.cooldown.sell(),
+            (false, false) => self.cooldown.idle(),
+        };
+        self.clock.sleep(wait).await;
+        Ok(())
+    }
+
+    /// 价位由 `params` 决定，其变化会重建网格
+    fn update(&mut self, _old: &Coin, new: &Coin) -> anyhow::Result<()> {
+        self.quantity = new.quantity;
+        self.sizing = new.sizing.clone();
+        self.cooldown = new.cooldown.clone();
+        self.filter.set_rules(new.filters.clone());
+        Ok(())
+    }
+}
+
+impl LevelGridService {
+    pub fn new(ctx: GridContext, c: &Coin, level: &LevelGrid) -> Result<Self, TgError> {
+        let ladder = Ladder::new(level)?;
+        info!("币种:{},网格价位为:{:?}", ctx.symbol, ladder.prices);
+        let filter = TrendFilter::new(
+            ctx.symbol.clone(),
+            c.filters.clone(),
+            ctx.market,
+            ctx.clock.clone(),
+        );
+        Ok(Self {
+            symbol: ctx.symbol,
+            trade: ctx.trade,
+            events: ctx.events,
+            risk: ctx.risk,
+            balance: ctx.balance,
+            clock: ctx.clock,
+            cooldown: c.cooldown.clone(),
+            filter,
+            quantity: c.quantity,
+            sizing: c.sizing.clone(),
+            ladder,
+            ledger: Ledger::default(),
+        })
+    }
+
+    fn filled(&self, side: TradeSide, fill: &Fill, profit: Option<f64>) {
+        self.events.publish(GridEvent::OrderFilled {
+            symbol: self.symbol.clone(),
+            side,
+            price: fill.price,
+            quantity: fill.quantity,
+            profit,
+        });
+    }
+}
+
+impl Ladder {
+    pub fn new(level: &LevelGrid) -> Result<Self, TgError> {
+        if level.levels < 2 {
+            return Err(TgError::ConfError(
+                "level grid needs at least 2 levels".into(),
+            ));
+        }
+        if level.lower_price <= 0.0 || level.upper_price <= level.lower_price {
+            return Err(TgError::ConfError(
+                "level grid needs 0 < lower_price < upper_price".into(),
+            ));
+        }
+        let (lower, upper) = (level.lower_price, level.upper_price);
+        let steps = (level.levels - 1) as f64;
+        let prices: Vec<f64> = (0..level.levels)
+            .map(|i| match level.spacing {
+                Spacing::Arithmetic => lower + (upper - lower) * i as f64 / steps,
+                Spacing::Geometric => lower * (upper / lower).powf(i as f64 / steps),
+            })
+            .collect();
+        // 最高价位只用于卖出
+        let holdings = vec![false; prices.len() - 1];
+        Ok(Self {
+            prices,
+            holdings,
+            last_price: None,
+        })
+    }
+
+    /// 价格自上而下穿过的、尚未持仓的价位，没有上一次价格时为空
+    fn buys(&self, price: f64) -> Vec<usize> {
+        let last = match self.last_price {
+            Some(last) => last,
+            None => return Vec::new(),
+        };
+        (0..self.holdings.len())
+            .rev()
+            .filter(|i| !self.holdings[*i])
+            .filter(|i| price <= self.prices[*i] && self.prices[*i] < last)
+            .collect()
+    }
+
+    /// 价格已到达上一个价位的持仓
+    fn sells(&self, price: f64) -> Vec<usize> {
+        (0..self.holdings.len())
+            .filter(|i| self.holdings[*i] && price >= self.prices[*i + 1])
+            .collect()
+    }
+
+    fn hold(&mut self, level: usize) {
+        self.holdings[level] = true;
+    }
+
+    fn release(&mut self, level: usize) {
+        self.holdings[level] = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::clock::ManualClock;
+    use crate::grid::StateStore;
+    use crate::trade::fake::{FakeMarket, FakeTrade};
+    use crate::trade::MarketService;
+    use crate::RiskConfig;
+
+    use super::*;
+
+    fn level(spacing: Spacing) -> LevelGrid {
+        LevelGrid {
+            lower_price: 100.0,
+            upper_price: 400.0,
+            levels: 3,
+            spacing,
+        }
+    }
+
+    #[test]
+    fn ladder_prices_should_follow_spacing() {
+        let arithmetic = Ladder::new(&level(Spacing::Arithmetic)).unwrap();
+        assert_eq!(arithmetic.prices, vec![100.0, 250.0, 400.0]);
+        let geometric = Ladder::new(&level(Spacing::Geometric)).unwrap();
+        assert_eq!(geometric.prices, vec![100.0, 200.0, 400.0]);
+    }
+
+    #[test]
+    fn invalid_ladder_should_be_rejected() {
+        let mut l = level(Spacing::Arithmetic);
+        l.levels = 1;
+        assert!(Ladder::new(&l).is_err());
+        let mut l = level(Spacing::Arithmetic);
+        l.upper_price = 50.0;
+        assert!(Ladder::new(&l).is_err());
+    }
+
+    #[test]
+    fn ladder_should_buy_on_cross_and_sell_at_level_above() {
+        let mut ladder = Ladder::new(&level(Spacing::Geometric)).unwrap();
+        assert!(ladder.buys(150.0).is_empty());
+
+        ladder.last_price = Some(450.0);
+        assert_eq!(ladder.buys(150.0), vec![1]);
+        assert_eq!(ladder.buys(90.0), vec![1, 0]);
+
+        ladder.hold(1);
+        ladder.last_price = Some(150.0);
+        assert!(ladder.buys(150.0).is_empty());
+        assert!(ladder.sells(399.0).is_empty());
+        assert_eq!(ladder.sells(400.0), vec![1]);
+        ladder.release(1);
+        assert!(ladder.sells(400.0).is_empty());
+    }
+
+    #[tokio::test]
+    async fn each_level_should_sell_its_own_lot() {
+        // matching 对经典网格无效，总是卖出该价位的一份
+        let coin: Coin = toml::from_str(
+            r#"
+            strategy = 'level'
+            quantity = 1
+            sizing = { mode = 'Scaled', multiplier = 2 }
+            matching = 'Fifo'
+            cooldown = { buy = 120, sell = 30, idle = 1 }
+            params = { lower_price = 100, upper_price = 400, levels = 3, spacing = 'Geometric' }
+            "#,
+        )
+        .unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let market = Arc::new(FakeMarket::new([450.0, 90.0, 250.0]));
+        let trade = Arc::new(
+            FakeTrade::new(market.clone())
+                .with_clock(clock.clone())
+                .with_balance("USDT", 1000.0),
+        );
+        let events = EventBus::default();
+        let ctx = GridContext {
+            symbol: Symbol::Eth,
+            market: market.clone(),
+            trade: trade.clone(),
+            events: events.clone(),
+            store: StateStore::new(state.path()),
+            risk: Arc::new(RiskManager::new(
+                RiskConfig::default(),
+                events,
+                clock.clone(),
+            )),
+            balance: Arc::new(BalanceGuard::new(
+                trade.clone(),
+                BTreeMap::new(),
+                clock.clone(),
+            )),
+            clock: clock.clone(),
+        };
+        let mut grid = LevelGridService::new(ctx, &coin, &coin.params().unwrap()).unwrap();
+        let elapsed = || (clock.now() - start).to_std().unwrap();
+
+        // 第一次行情只记录价格
+        grid.execute(market.ticker_price(&Symbol::Eth).await.unwrap())
+            .await
+            .unwrap();
+        assert!(trade.orders().is_empty());
+        assert_eq!(elapsed(), Duration::from_secs(1));
+
+        // 跌破两个价位，越低的价位买入越多
+        grid.execute(market.ticker_price(&Symbol::Eth).await.unwrap())
+            .await
+            .unwrap();
+        let quantities: Vec<_> = trade.orders().iter().map(|o| o.quantity).collect();
+        assert_eq!(quantities, vec![Some(1.0), Some(2.0)]);
+        assert_eq!(elapsed(), Duration::from_secs(121));
+
+        // 涨过 200 只卖出 100 价位的 2 个
+        grid.execute(market.ticker_price(&Symbol::Eth).await.unwrap())
+            .await
+            .unwrap();
+        let orders = trade.orders();
+        assert_eq!(orders[2].side, TradeSide::Sell);
+        assert_eq!(orders[2].quantity, Some(2.0));
+        assert_eq!(elapsed(), Duration::from_secs(151));
+        let lots = grid.ledger.lots();
+        assert_eq!(lots.len(), 1);
+        assert_eq!((lots[0].level, lots[0].quantity), (Some(1), 1.0));
+        assert_eq!(grid.ladder.holdings, vec![false, true]);
+    }
+}
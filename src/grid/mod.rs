@@ -1,30 +1,48 @@
-use std::sync::Arc;
-
 use anyhow::Result;
 use async_trait::async_trait;
 use tracing::info;
 
-use crate::trade::{MarketService, TradeService};
-use crate::{Coin, Symbol};
+use crate::{Coin, TgError};
 
-pub use self::grid_service::FixedGridService;
+pub use self::grid_service::{FixedGridService, HALT};
+pub use self::ledger::{Ledger, Lot, Pair};
+pub use self::level_grid_service::LevelGridService;
+pub use self::protection::{Halt, HaltReason};
+pub use self::registry::{
+    GridConstructor, GridContext, GridRegistry, FIXED_STRATEGY, LEVEL_STRATEGY, REVERSE_STRATEGY,
+};
+pub use self::reverse_grid_service::ReverseGridService;
+pub use self::store::StateStore;
 
 mod grid_service;
+mod indicator;
+mod ledger;
+mod level_grid_service;
+mod price_range;
+mod protection;
+mod registry;
+mod reverse_grid_service;
+mod sizing;
+mod store;
+mod trend_filter;
 
 /// Abstraction of Grid services
 #[async_trait]
 pub trait GridService: Send {
     /// whether to buy
     async fn execute(&mut self, price: f64) -> Result<()>;
+
+    /// 热更新币种参数，`old` 为当前运行的配置，策略及其 `params` 变化时网格会被重建而不会调用此方法
+    fn update(&mut self, _old: &Coin, _new: &Coin) -> Result<()> {
+        Err(TgError::ConfError("grid strategy does not support hot reload".into()).into())
+    }
 }
 
 pub fn factory(
-    symbol: &Symbol,
+    registry: &GridRegistry,
+    ctx: GridContext,
     config: &Coin,
-    market: Arc<dyn MarketService>,
-    trade: Arc<dyn TradeService>,
 ) -> Result<Box<dyn GridService>> {
-    info!("Initialize Grid Service: {:?} - {:?}", symbol, config);
-    let fgs = FixedGridService::new(symbol.clone(), config, market, trade)?;
-    Ok(Box::new(fgs))
+    info!("Initialize Grid Service: {:?} - {:?}", ctx.symbol, config);
+    registry.build(ctx, config)
 }
@@ -0,0 +1,100 @@
+use crate::{Coin, OutOfRange};
+
+/// 价格区间保护，限制网格只在 `lower`~`upper` 之间交易
+pub struct PriceRange {
+    lower: Option<f64>,
+    upper: Option<f64>,
+    pub action: OutOfRange,
+    /// 上一次检查时价格是否在区间外
+    pub outside: bool,
+}
+
+/// 越界的方向及对应的边界
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Breach {
+    Below(f64),
+    Above(f64),
+}
+
+impl Breach {
+    pub fn limit(&self) -> f64 {
+        match *self {
+            Breach::Below(limit) | Breach::Above(limit) => limit,
+        }
+    }
+}
+
+impl PriceRange {
+    pub fn new(c: &Coin) -> Self {
+        Self {
+            lower: c.lower_limit,
+            upper: c.upper_limit,
+            action: c.out_of_range,
+            outside: false,
+        }
+    }
+
+    /// 按新配置设置区间，保留越界状态
+    pub fn update(&mut self, c: &Coin) {
+        self.lower = c.lower_limit;
+        self.upper = c.upper_limit;
+        self.action = c.out_of_range;
+    }
+
+    pub fn breach(&self, price: f64) -> Option<Breach> {
+        match (self.lower, self.upper) {
+            (Some(lower), _) if price < lower => Some(Breach::Below(lower)),
+            (_, Some(upper)) if price > upper => Some(Breach::Above(upper)),
+            _ => None,
+        }
+    }
+
+    /// 平移区间，使价格落在越界一侧的边界上
+    pub fn shift(&mut self, price: f64) {
+        let delta = match self.breach(price) {
+            Some(breach) => price - breach.limit(),
+            None => return,
+        };
+        self.lower = self.lower.map(|l| l + delta);
+        self.upper = self.upper.map(|u| u + delta);
+    }
+
+    pub fn limits(&self) -> (Option<f64>, Option<f64>) {
+        (self.lower, self.upper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(lower: Option<f64>, upper: Option<f64>) -> PriceRange {
+        PriceRange {
+            lower,
+            upper,
+            action: OutOfRange::Shift,
+            outside: false,
+        }
+    }
+
+    #[test]
+    fn breach_should_respect_limits() {
+        let r = range(Some(100.0), Some(200.0));
+        assert_eq!(r.breach(150.0), None);
+        assert_eq!(r.breach(100.0), None);
+        assert_eq!(r.breach(99.0), Some(Breach::Below(100.0)));
+        assert_eq!(r.breach(201.0), Some(Breach::Above(200.0)));
+        assert_eq!(range(None, None).breach(0.1), None);
+    }
+
+    #[test]
+    fn shift_should_move_both_limits() {
+        let mut r = range(Some(100.0), Some(200.0));
+        r.shift(250.0);
+        assert_eq!(r.limits(), (Some(150.0), Some(250.0)));
+        r.shift(100.0);
+        assert_eq!(r.limits(), (Some(100.0), Some(200.0)));
+        r.shift(150.0);
+        assert_eq!(r.limits(), (Some(100.0), Some(200.0)));
+    }
+}
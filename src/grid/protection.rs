@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::grid::Lot;
+use crate::StopConfig;
+
+/// 网格停止的原因
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum HaltReason {
+    /// 价格跌破止损价
+    StopLoss { stop_price: f64 },
+    /// 持仓总盈利达到目标
+    TakeProfit { profit: f64 },
+}
+
+/// 持久化的停止记录，存在时网格不再交易
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Halt {
+    pub reason: HaltReason,
+    pub price: f64,
+    pub time: DateTime<Utc>,
+}
+
+/// 整体持仓的止损/止盈判断
+pub struct Protection {
+    config: StopConfig,
+    /// 持仓以来的最高价，用于跟踪止损
+    peak: Option<f64>,
+}
+
+impl Protection {
+    pub fn new(config: StopConfig) -> Self {
+        Self { config, peak: None }
+    }
+
+    /// 替换止损/止盈配置，保留跟踪的最高价
+    pub fn set_config(&mut self, config: StopConfig) {
+        self.config = config;
+    }
+
+    /// `lots` 为当前持仓，`realized` 为已实现盈利
+    pub fn check(&mut self, price: f64, lots: &[Lot], realized: f64) -> Option<HaltReason> {
+        if lots.is_empty() {
+            self.peak = None;
+            return None;
+        }
+        let peak = self.peak.map_or(price, |p| p.max(price));
+        self.peak = Some(peak);
+
+        let quantity: f64 = lots.iter().map(|l| l.quantity).sum();
+        let cost = lots.iter().map(|l| l.price * l.quantity).sum::<f64>() / quantity;
+        if let Some(stop_price) = self.stop_price(cost, peak) {
+            if price <= stop_price {
+                return Some(HaltReason::StopLoss { stop_price });
+            }
+        }
+
+        let profit = realized + (price - cost) * quantity;
+        match self.config.take_profit {
+            Some(target) if profit >= target => Some(HaltReason::TakeProfit { profit }),
+            _ => None,
+        }
+    }
+
+    /// 比例止损与绝对价格止损中较高的一个
+    fn stop_price(&self, cost: f64, peak: f64) -> Option<f64> {
+        let base = if self.config.trailing {
+            peak.max(cost)
+        } else {
+            cost
+        };
+        let by_ratio = self.config.stop_loss_ratio.map(|r| base * (1.0 - r));
+        match (by_ratio, self.config.stop_loss_price) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StopAction;
+
+    fn lots(prices: &[f64], quantity: f64) -> Vec<Lot> {
+        prices
+            .iter()
+            .map(|&price| Lot {
+                price,
+                quantity,
+                ..Lot::default()
+            })
+            .collect()
+    }
+
+    fn config() -> StopConfig {
+        StopConfig {
+            stop_loss_ratio: Some(0.1),
+            stop_loss_price: None,
+            trailing: false,
+            take_profit: None,
+            action: StopAction::Liquidate,
+        }
+    }
+
+    #[test]
+    fn stop_loss_should_trigger_below_average_cost() {
+        let mut p = Protection::new(config());
+        assert_eq!(p.check(50.0, &lots(&[], 1.0), 0.0), None);
+        assert_eq!(p.check(91.0, &lots(&[100.0, 100.0], 1.0), 0.0), None);
+        assert_eq!(
+            p.check(90.0, &lots(&[100.0, 100.0], 1.0), 0.0),
+            Some(HaltReason::StopLoss { stop_price: 90.0 })
+        );
+    }
+
+    #[test]
+    fn trailing_stop_should_follow_peak() {
+        let mut p = Protection::new(StopConfig {
+            trailing: true,
+            ..config()
+        });
+        assert_eq!(p.check(200.0, &lots(&[100.0], 1.0), 0.0), None);
+        assert_eq!(
+            p.check(180.0, &lots(&[100.0], 1.0), 0.0),
+            Some(HaltReason::StopLoss { stop_price: 180.0 })
+        );
+        // 清仓后重新计算最高价
+        assert_eq!(p.check(180.0, &lots(&[], 1.0), 0.0), None);
+        assert_eq!(p.check(180.0, &lots(&[170.0], 1.0), 0.0), None);
+    }
+
+    #[test]
+    fn absolute_stop_and_take_profit_should_trigger() {
+        let mut p = Protection::new(StopConfig {
+            stop_loss_ratio: None,
+            stop_loss_price: Some(95.0),
+            take_profit: Some(30.0),
+            ..config()
+        });
+        assert_eq!(
+            p.check(94.0, &lots(&[100.0], 1.0), 0.0),
+            Some(HaltReason::StopLoss { stop_price: 95.0 })
+        );
+        assert_eq!(p.check(110.0, &lots(&[100.0], 2.0), 5.0), None);
+        assert_eq!(
+            p.check(110.0, &lots(&[100.0], 2.0), 10.0),
+            Some(HaltReason::TakeProfit { profit: 30.0 })
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::clock::Clock;
+use crate::event::EventBus;
+use crate::grid::store::StateStore;
+use crate::grid::{FixedGridService, GridService, LevelGridService, ReverseGridService};
+use crate::risk::RiskManager;
+use crate::trade::{BalanceGuard, MarketService, TradeService};
+use crate::{Coin, LevelGrid, ReverseGrid, Symbol, TgError};
+
+/// 构造网格策略所需的依赖
+#[derive(Clone)]
+pub struct GridContext {
+    pub symbol: Symbol,
+    pub market: Arc<dyn MarketService>,
+    pub trade: Arc<dyn TradeService>,
+    pub events: EventBus,
+    pub store: StateStore,
+    pub risk: Arc<RiskManager>,
+    pub balance: Arc<BalanceGuard>,
+    pub clock: Arc<dyn Clock>,
+}
+
+/// 网格策略的构造函数
+pub type GridConstructor =
+    Box<dyn Fn(GridContext, &Coin) -> Result<Box<dyn GridService>> + Send + Sync>;
+
+/// 策略名称到构造函数的映射，通过币种配置中的 `strategy` 选择
+pub struct GridRegistry {
+    constructors: HashMap<String, GridConstructor>,
+}
+
+/// 固定网格，默认策略
+pub const FIXED_STRATEGY: &str = "fixed";
+/// 多价位网格
+pub const LEVEL_STRATEGY: &str = "level";
+/// 先卖后买的反向网格
+pub const REVERSE_STRATEGY: &str = "reverse";
+
+impl Default for GridRegistry {
+    /// 包含内置策略的注册表
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(FIXED_STRATEGY, |ctx, coin| {
+            Ok(Box::new(FixedGridService::new(ctx, coin)?))
+        });
+        registry.register(LEVEL_STRATEGY, |ctx, coin| {
+            let level: LevelGrid = coin.params()?;
+            Ok(Box::new(LevelGridService::new(ctx, coin, &level)?))
+        });
+        registry.register(REVERSE_STRATEGY, |ctx, coin| {
+            let reverse: ReverseGrid = coin.params()?;
+            Ok(Box::new(ReverseGridService::new(ctx, coin, &reverse)?))
+        });
+        registry
+    }
+}
+
+impl GridRegistry {
+    /// 不包含任何策略的注册表
+    pub fn empty() -> Self {
+        Self {
+            constructors: HashMap::new(),
+        }
+    }
+
+    /// 注册策略，同名策略会被覆盖
+    pub fn register<F>(&mut self, name: &str, constructor: F)
+    where
+        F: Fn(GridContext, &Coin) -> Result<Box<dyn GridService>> + Send + Sync + 'static,
+    {
+        self.constructors
+            .insert(name.to_string(), Box::new(constructor));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.constructors.contains_key(name)
+    }
+
+    /// 按 `coin.strategy` 构造网格
+    pub fn build(&self, ctx: GridContext, coin: &Coin) -> Result<Box<dyn GridService>> {
+        let constructor = self
+            .constructors
+            .get(coin.strategy.as_str())
+            .ok_or_else(|| {
+                TgError::ConfError(format!("Unknown grid strategy: {}", coin.strategy))
+            })?;
+        constructor(ctx, coin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+
+    struct NoopGrid;
+
+    #[async_trait]
+    impl GridService for NoopGrid {
+        async fn execute(&mut self, _price: f64) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn registry_should_contain_builtin_strategies() {
+        let registry = GridRegistry::default();
+        assert!(registry.contains(FIXED_STRATEGY));
+        assert!(registry.contains(LEVEL_STRATEGY));
+        assert!(registry.contains(REVERSE_STRATEGY));
+        assert!(!GridRegistry::empty().contains(FIXED_STRATEGY));
+    }
+
+    #[test]
+    fn custom_strategy_should_be_registered() {
+        let mut registry = GridRegistry::empty();
+        registry.register("noop", |_, _| Ok(Box::new(NoopGrid)));
+        assert!(registry.contains("noop"));
+    }
+}
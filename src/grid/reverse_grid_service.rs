@@ -0,0 +1,397 @@
+use std::ops::{Add, Mul, Sub};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::{error, info, warn};
+
+use crate::clock::Clock;
+use crate::event::{EventBus, GridEvent};
+use crate::grid::grid_service::{calc_k_lines, HUNDRED_PERCENT};
+use crate::grid::trend_filter::TrendFilter;
+use crate::grid::{GridContext, GridService, Lot};
+use crate::risk::RiskManager;
+use crate::trade::{BalanceGuard, MarketService, TradeService};
+use crate::{Coin, Cooldown, ReverseGrid, Symbol, TgError, TradeSide};
+
+/// 反向网格: 持有基础资产，上涨时卖出一份，下跌时用卖出所得买回，盈利以基础资产计
+pub struct ReverseGridService {
+    symbol: Symbol,
+    market: Arc<dyn MarketService>,
+    trade: Arc<dyn TradeService>,
+    events: EventBus,
+    risk: Arc<RiskManager>,
+    balance: Arc<BalanceGuard>,
+    clock: Arc<dyn Clock>,
+    cooldown: Cooldown,
+    filter: TrendFilter,
+    /// 允许卖出的基础资产总量
+    inventory: f64,
+    db: Db,
+}
+
+struct Db {
+    buy: f64,
+    sell: f64,
+    quantity: f64,
+    profit_ratio: f64,
+    double_throw_ratio: f64,
+    /// 不按 k 线重新计算比例
+    fixed_ratio: bool,
+    /// 尚未买回的卖出，最近的在最后
+    sold: Vec<Lot>,
+    /// 已实现盈利(基础资产)
+    profit: f64,
+}
+
+#[async_trait]
+impl GridService for ReverseGridService {
+    async fn execute(&mut self, price: f64) -> anyhow::Result<()> {
+        let symbol = &self.symbol.clone();
+        if self.db.is_sell(price) && self.allows_sell(price).await {
+            self.events.publish(GridEvent::OrderSubmitted {
+                symbol: symbol.clone(),
+                side: TradeSide::Sell,
+                price,
+                quantity: Some(self.db.quantity),
+                quote: None,
+            });
+            if let Ok(Some(fill)) = self.trade.sell(symbol, self.db.quantity).await {
+                self.balance.invalidate().await;
+                // 反向网格不占用计价资产，只计入下单次数
+                self.risk.record_order();
+                info!(
+                    "交易成功: 卖出币种为: {},价格为: {}, 数量: {},",
+                    symbol, fill.price, fill.quantity
+                );
+                self.filled(TradeSide::Sell, fill.price, fill.quantity);
+                self.db.sold.push(Lot {
+                    time: fill.time,
+                    price: fill.price,
+                    quantity: fill.quantity,
+                    fee: fill.quote_fee(symbol),
+                    order_id: fill.order_id,
+                    ..Lot::default()
+                });
+                self.reset_ratio().await;
+                self.db.modify_price_sell(price);
+                self.levels_changed();
+                self.clock.sleep(self.cooldown.sell()).await;
+            }
+        } else if self.db.is_buy(price) && self.db.is_air() {
+            self.db.modify_price_air(price);
+            self.levels_changed();
+        } else if self.db.is_buy(price) && self.allows_buy(price).await {
+            let lot = self.db.sold.last().cloned().ok_or(TgError::Internal(
+                "Buy allowed. But nothing was sold".to_string(),
+            ))?;
+            let quote = lot.price.mul(lot.quantity).sub(lot.fee);
+            self.events.publish(GridEvent::OrderSubmitted {
+                symbol: symbol.clone(),
+                side: TradeSide::Buy,
+                price,
+                quantity: None,
+                quote: Some(quote),
+            });
+            if let Ok(Some(fill)) = self.trade.buy_quote(symbol, quote).await {
+                self.balance.invalidate().await;
+                self.risk.record_order();
+                let fee = fill.quote_fee(symbol) / fill.price;
+                let profit = fill.quantity.sub(fee).sub(lot.quantity);
+                self.db.profit += profit;
+                warn!(
+                    "交易成功：买回币种为：{}。买单量为：{}。预计盈利{}{}",
+                    symbol,
+                    fill.quantity,
+                    profit,
+                    symbol.base_asset()
+                );
+                // 盈利以基础资产计，不是 U
+                self.filled(TradeSide::Buy, fill.price, fill.quantity);
+                self.reset_ratio().await;
+                self.db.modify_price(lot.price, price);
+                self.levels_changed();
+                self.db.sold.pop();
+                self.clock.sleep(self.cooldown.buy()).await;
+            }
+        } else {
+            warn!("币种:{},当前市价：{}。未能满足交易,继续运行", symbol, price);
+            self.clock.sleep(self.cooldown.idle()).await;
+        }
+
+        Ok(())
+    }
+
+    fn update(&mut self, old: &Coin, new: &Coin) -> anyhow::Result<()> {
+        let db = &mut self.db;
+        if old.buy_price != new.buy_price {
+            db.buy = new.buy_price;
+        }
+        if old.sell_price != new.sell_price {
+            db.sell = new.sell_price;
+        }
+        // 比例在成交后按 k 线重新计算，只在配置变化时覆盖
+        if old.profit_ratio != new.profit_ratio {
+            db.profit_ratio = new.profit_ratio;
+        }
+        if old.double_throw_ratio != new.double_throw_ratio {
+            db.double_throw_ratio = new.double_throw_ratio;
+        }
+        db.fixed_ratio = new.fixed_ratio;
+        db.quantity = new.quantity;
+        self.cooldown = new.cooldown.clone();
+        self.filter.set_rules(new.filters.clone());
+        Ok(())
+    }
+}
+
+impl ReverseGridService {
+    pub fn new(ctx: GridContext, c: &Coin, reverse: &ReverseGrid) -> Result<Self, TgError> {
+        if reverse.inventory < c.quantity {
+            return Err(TgError::ConfError(
+                "reverse grid needs inventory >= quantity".into(),
+            ));
+        }
+        let filter = TrendFilter::new(
+            ctx.symbol.clone(),
+            c.filters.clone(),
+            ctx.market.clone(),
+            ctx.clock.clone(),
+        );
+        Ok(Self {
+            symbol: ctx.symbol,
+            market: ctx.market,
+            trade: ctx.trade,
+            events: ctx.events,
+            risk: ctx.risk,
+            balance: ctx.balance,
+            clock: ctx.clock,
+            cooldown: c.cooldown.clone(),
+            filter,
+            inventory: reverse.inventory,
+            db: Db {
+                buy: c.buy_price,
+                sell: c.sell_price,
+                quantity: c.quantity,
+                profit_ratio: c.profit_ratio,
+                double_throw_ratio: c.double_throw_ratio,
+                fixed_ratio: c.fixed_ratio,
+                sold: Vec::new(),
+                profit: 0.0,
+            },
+        })
+    }
+
+    /// 库存、趋势过滤、余额及风控是否允许再卖出一份
+    async fn allows_sell(&mut self, price: f64) -> bool {
+        let quantity = self.db.quantity;
+        if self.db.sold_quantity() + quantity > self.inventory {
+            return false;
+        }
+        self.filter.allows(TradeSide::Sell, price).await
+            && self.balance.can_sell(&self.symbol, quantity).await
+            && self.risk.check(&self.symbol, TradeSide::Sell, 0.0).is_ok()
+    }
+
+    /// 趋势过滤、余额及风控是否允许买回最近卖出的一份
+    async fn allows_buy(&mut self, price: f64) -> bool {
+        let quote = match self.db.sold.last() {
+            Some(lot) => lot.price.mul(lot.quantity).sub(lot.fee),
+            None => return false,
+        };
+        // 买回使用的是卖出所得，不计入占用资金
+        self.filter.allows(TradeSide::Buy, price).await
+            && self.balance.can_buy(&self.symbol, quote).await
+            && self.risk.check(&self.symbol, TradeSide::Buy, 0.0).is_ok()
+    }
+
+    /// 成交后按 k 线重新计算比例，失败时沿用原比例，不影响已成交订单的后续处理
+    async fn reset_ratio(&mut self) {
+        if self.db.fixed_ratio {
+            return;
+        }
+        let value = match calc_k_lines(self.market.as_ref(), &self.symbol).await {
+            Ok(value) => value,
+            Err(e) => {
+                error!(
+                    "币种:{},重新计算网格比例失败，沿用原比例: {}",
+                    self.symbol, e
+                );
+                self.events.publish(GridEvent::Error {
+                    symbol: self.symbol.clone(),
+                    message: format!("重新计算网格比例失败: {}", e),
+                });
+                return;
+            }
+        };
+        self.db.double_throw_ratio = value;
+        self.db.profit_ratio = value;
+        self.events.publish(GridEvent::RatioRecalculated {
+            symbol: self.symbol.clone(),
+            ratio: value,
+        });
+    }
+
+    fn filled(&self, side: TradeSide, price: f64, quantity: f64) {
+        self.events.publish(GridEvent::OrderFilled {
+            symbol: self.symbol.clone(),
+            side,
+            price,
+            quantity,
+            profit: None,
+        });
+    }
+
+    fn levels_changed(&self) {
+        self.events.publish(GridEvent::LevelsChanged {
+            symbol: self.symbol.clone(),
+            buy: self.db.buy,
+            sell: self.db.sell,
+        });
+    }
+}
+
+impl Db {
+    fn is_sell(&self, price: f64) -> bool {
+        self.sell <= price
+    }
+
+    fn is_buy(&self, price: f64) -> bool {
+        self.buy > price
+    }
+
+    fn is_air(&self) -> bool {
+        self.sold.is_empty()
+    }
+
+    fn sold_quantity(&self) -> f64 {
+        self.sold.iter().map(|l| l.quantity).sum()
+    }
+
+    fn modify_price_sell(&mut self, market_price: f64) {
+        self.modify_price(market_price, market_price);
+    }
+
+    fn modify_price_air(&mut self, market_price: f64) {
+        self.modify_price(self.buy, market_price);
+    }
+
+    /// 与固定网格相反: 涨 `double_throw_ratio` 再卖一份，跌 `profit_ratio` 买回
+    fn modify_price(&mut self, deal_price: f64, market_price: f64) {
+        self.sell = deal_price.mul(HUNDRED_PERCENT.add(self.double_throw_ratio));
+        self.buy = deal_price.mul(HUNDRED_PERCENT.sub(self.profit_ratio));
+        if self.is_sell(market_price) {
+            self.sell = market_price.mul(HUNDRED_PERCENT.add(self.double_throw_ratio));
+        } else if self.is_buy(market_price) {
+            self.buy = market_price.mul(HUNDRED_PERCENT.sub(self.profit_ratio));
+        }
+        info!(
+            "修改后的加仓（卖出）价格为:{},修改后的网格（买回）价格为:{}.",
+            self.sell, self.buy
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::clock::SystemClock;
+    use crate::grid::StateStore;
+    use crate::trade::fake::{FakeMarket, FakeTrade};
+    use crate::RiskConfig;
+
+    use super::*;
+
+    fn db() -> Db {
+        Db {
+            buy: 0.0,
+            sell: 100.0,
+            quantity: 1.0,
+            profit_ratio: 0.1,
+            double_throw_ratio: 0.2,
+            fixed_ratio: false,
+            sold: Vec::new(),
+            profit: 0.0,
+        }
+    }
+
+    #[test]
+    fn prices_should_mirror_fixed_grid() {
+        let mut db = db();
+        assert!(!db.is_sell(99.0));
+        assert!(db.is_sell(100.0));
+        assert!(!db.is_buy(50.0));
+
+        db.modify_price_sell(100.0);
+        assert_eq!((db.buy, db.sell), (90.0, 120.0));
+        assert!(db.is_buy(89.0));
+
+        // 买回后以卖出价重新设置网格
+        db.modify_price(100.0, 89.0);
+        assert_eq!(db.sell, 120.0);
+        assert!((db.buy - 80.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn empty_grid_should_follow_price_down() {
+        let mut db = db();
+        db.buy = 90.0;
+        db.modify_price_air(50.0);
+        assert_eq!(db.sell, 108.0);
+        assert_eq!(db.buy, 45.0);
+        assert_eq!(db.sold_quantity(), 0.0);
+    }
+
+    #[test]
+    fn update_should_keep_recalculated_ratio() {
+        let state = tempfile::tempdir().unwrap();
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let market = Arc::new(FakeMarket::new([]));
+        let trade = Arc::new(FakeTrade::new(market.clone()));
+        let events = EventBus::default();
+        let ctx = GridContext {
+            symbol: Symbol::Bnb,
+            market,
+            trade: trade.clone(),
+            events: events.clone(),
+            store: StateStore::new(state.path()),
+            risk: Arc::new(RiskManager::new(
+                RiskConfig::default(),
+                events,
+                clock.clone(),
+            )),
+            balance: Arc::new(BalanceGuard::new(trade, BTreeMap::new(), clock.clone())),
+            clock,
+        };
+        let old: Coin = toml::from_str(
+            r#"
+            strategy = 'reverse'
+            sell_price = 100
+            profit_ratio = '10%'
+            double_throw_ratio = '20%'
+            quantity = 1
+            "#,
+        )
+        .unwrap();
+        let mut grid = ReverseGridService::new(ctx, &old, &ReverseGrid { inventory: 5.0 }).unwrap();
+        grid.db.profit_ratio = 0.05;
+        grid.db.double_throw_ratio = 0.05;
+
+        let mut new = old.clone();
+        new.quantity = 2.0;
+        grid.update(&old, &new).unwrap();
+        assert_eq!(grid.db.quantity, 2.0);
+        assert_eq!(
+            (grid.db.profit_ratio, grid.db.double_throw_ratio),
+            (0.05, 0.05)
+        );
+
+        let old = new.clone();
+        new.double_throw_ratio = 0.3;
+        grid.update(&old, &new).unwrap();
+        assert_eq!(
+            (grid.db.profit_ratio, grid.db.double_throw_ratio),
+            (0.05, 0.3)
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::Result;
+
+use crate::event::GridEvent;
+use crate::trade::{BalanceGuard, Fill, TradeService};
+use crate::{Sizing, Symbol, TradeSide};
+
+/// 一次买入的数量
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderSize {
+    /// 基础资产数量
+    Base(f64),
+    /// 计价资产金额
+    Quote(f64),
+}
+
+impl OrderSize {
+    /// 按 `sizing` 计算买入数量，`depth` 为当前已持有的层数
+    pub async fn of(
+        sizing: &Sizing,
+        quantity: f64,
+        depth: usize,
+        symbol: &Symbol,
+        balance: &BalanceGuard,
+    ) -> Result<Self> {
+        let available = match sizing {
+            Sizing::Percent { .. } => balance.available(symbol.quote_asset()).await?,
+            _ => 0.0,
+        };
+        Ok(Self::compute(sizing, quantity, depth, available))
+    }
+
+    fn compute(sizing: &Sizing, quantity: f64, depth: usize, available: f64) -> Self {
+        match *sizing {
+            Sizing::Base => OrderSize::Base(quantity),
+            Sizing::Quote { amount } => OrderSize::Quote(amount),
+            Sizing::Percent { ratio } => OrderSize::Quote(available * ratio),
+            Sizing::Scaled { multiplier } => {
+                OrderSize::Base(quantity * multiplier.powi(depth as i32))
+            }
+        }
+    }
+
+    /// 以 `price` 估算需要的计价资产金额
+    pub fn quote(&self, price: f64) -> f64 {
+        match *self {
+            OrderSize::Base(quantity) => quantity * price,
+            OrderSize::Quote(quote) => quote,
+        }
+    }
+
+    /// 提交买单的事件，`price` 为下单时的市价
+    pub fn submitted(&self, symbol: &Symbol, price: f64) -> GridEvent {
+        let (quantity, quote) = match *self {
+            OrderSize::Base(quantity) => (Some(quantity), None),
+            OrderSize::Quote(quote) => (None, Some(quote)),
+        };
+        GridEvent::OrderSubmitted {
+            symbol: symbol.clone(),
+            side: TradeSide::Buy,
+            price,
+            quantity,
+            quote,
+        }
+    }
+
+    pub async fn buy(&self, trade: &dyn TradeService, symbol: &Symbol) -> Result<Option<Fill>> {
+        match *self {
+            OrderSize::Base(quantity) => trade.buy(symbol, quantity).await,
+            OrderSize::Quote(quote) => trade.buy_quote(symbol, quote).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_size_should_follow_sizing() {
+        assert_eq!(
+            OrderSize::compute(&Sizing::Base, 0.1, 3, 0.0),
+            OrderSize::Base(0.1)
+        );
+        assert_eq!(
+            OrderSize::compute(&Sizing::Quote { amount: 20.0 }, 0.1, 3, 0.0),
+            OrderSize::Quote(20.0)
+        );
+        assert_eq!(
+            OrderSize::compute(&Sizing::Percent { ratio: 0.1 }, 0.1, 3, 500.0),
+            OrderSize::Quote(50.0)
+        );
+        assert_eq!(
+            OrderSize::compute(&Sizing::Scaled { multiplier: 2.0 }, 0.1, 3, 0.0),
+            OrderSize::Base(0.8)
+        );
+        assert_eq!(OrderSize::Base(0.5).quote(100.0), 50.0);
+        assert_eq!(OrderSize::Quote(20.0).quote(100.0), 20.0);
+    }
+}
@@ -0,0 +1,110 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{Symbol, TgError};
+
+/// 以 JSON 文件保存网格需要跨重启保留的状态，文件名为 `<symbol>.<name>.json`
+#[derive(Clone, Debug)]
+pub struct StateStore {
+    dir: PathBuf,
+}
+
+impl StateStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// 保存在子目录 `name` 下的状态，与当前目录下的状态互不影响
+    pub fn child(&self, name: &str) -> Self {
+        Self::new(self.dir.join(name))
+    }
+
+    pub fn path(&self, symbol: &Symbol, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}.json", symbol, name))
+    }
+
+    pub fn load<T: DeserializeOwned>(
+        &self,
+        symbol: &Symbol,
+        name: &str,
+    ) -> Result<Option<T>, TgError> {
+        match fs::read_to_string(self.path(symbol, name)) {
+            Ok(s) => serde_json::from_str(&s)
+                .map(Some)
+                .map_err(|e| TgError::DecodeError(e.to_string())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save<T: Serialize>(
+        &self,
+        symbol: &Symbol,
+        name: &str,
+        value: &T,
+    ) -> Result<(), TgError> {
+        fs::create_dir_all(&self.dir)?;
+        let s =
+            serde_json::to_string_pretty(value).map_err(|e| TgError::Internal(e.to_string()))?;
+        fs::write(self.path(symbol, name), s)?;
+        Ok(())
+    }
+
+    /// 追加记录的文件，名为 `<symbol>.<name>.jsonl`
+    pub fn journal_path(&self, symbol: &Symbol, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}.jsonl", symbol, name))
+    }
+
+    /// 以一行 JSON 追加记录
+    pub fn append<T: Serialize>(
+        &self,
+        symbol: &Symbol,
+        name: &str,
+        value: &T,
+    ) -> Result<(), TgError> {
+        fs::create_dir_all(&self.dir)?;
+        let s = serde_json::to_string(value).map_err(|e| TgError::Internal(e.to_string()))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.journal_path(symbol, name))?;
+        writeln!(file, "{}", s)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, symbol: &Symbol, name: &str) -> Result<(), TgError> {
+        match fs::remove_file(self.path(symbol, name)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_should_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = StateStore::new(dir.path().join("state"));
+        assert_eq!(store.load::<Vec<f64>>(&Symbol::Eth, "x").unwrap(), None);
+
+        store.save(&Symbol::Eth, "x", &vec![1.0, 2.0]).unwrap();
+        let child = store.child("dry_run");
+        assert_eq!(child.load::<Vec<f64>>(&Symbol::Eth, "x").unwrap(), None);
+        assert_eq!(
+            store.load::<Vec<f64>>(&Symbol::Eth, "x").unwrap(),
+            Some(vec![1.0, 2.0])
+        );
+        assert_eq!(store.load::<Vec<f64>>(&Symbol::Btc, "x").unwrap(), None);
+
+        store.remove(&Symbol::Eth, "x").unwrap();
+        store.remove(&Symbol::Eth, "x").unwrap();
+        assert_eq!(store.load::<Vec<f64>>(&Symbol::Eth, "x").unwrap(), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+
+use crate::clock::{self, Clock};
+use crate::grid::indicator;
+use crate::trade::{Interval, KlineQuery, MarketService};
+use crate::{Compare, Condition, Indicator, Symbol, TradeSide, TrendRule};
+
+/// k线缓存的有效期，按 `Clock` 计时以便回测时随模拟时间刷新
+const REFRESH: Duration = Duration::from_secs(60);
+/// 单条规则最多请求的k线数量
+const MAX_BARS: usize = 1000;
+
+/// 根据技术指标判断是否允许网格买入/卖出
+pub struct TrendFilter {
+    symbol: Symbol,
+    market: Arc<dyn MarketService>,
+    rules: Vec<TrendRule>,
+    clock: Arc<dyn Clock>,
+    closes: HashMap<Interval, (DateTime<Utc>, Vec<f64>)>,
+    /// 当前被禁止的方向，只在禁止状态变化时记录日志
+    blocked: HashSet<TradeSide>,
+}
+
+impl TrendFilter {
+    pub fn new(
+        symbol: Symbol,
+        rules: Vec<TrendRule>,
+        market: Arc<dyn MarketService>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            symbol,
+            market,
+            rules,
+            clock,
+            closes: HashMap::new(),
+            blocked: HashSet::new(),
+        }
+    }
+
+    /// 替换过滤规则，规则变化时清空k线缓存
+    pub fn set_rules(&mut self, rules: Vec<TrendRule>) {
+        if self.rules != rules {
+            self.rules = rules;
+            self.closes.clear();
+        }
+    }
+
+    /// 是否允许 `side` 方向的交易，k线获取失败时保守地拒绝交易
+    pub async fn allows(&mut self, side: TradeSide, price: f64) -> bool {
+        for i in 0..self.rules.len() {
+            if self.rules[i].side != side {
+                continue;
+            }
+            let rule = self.rules[i].clone();
+            match self.blocks(&rule, price).await {
+                Ok(false) => {}
+                Ok(true) => {
+                    if self.blocked.insert(side) {
+                        info!(
+                            "币种:{},趋势过滤禁止{:?},当前市价：{}。规则: {:?}",
+                            self.symbol, side, price, rule.when
+                        );
+                    }
+                    return false;
+                }
+                Err(e) => {
+                    warn!("币种:{},趋势过滤计算失败: {}", self.symbol, e);
+                    return false;
+                }
+            }
+        }
+        if self.blocked.remove(&side) {
+            info!(
+                "币种:{},趋势过滤解除{:?},当前市价：{}",
+                self.symbol, side, price
+            );
+        }
+        true
+    }
+
+    async fn blocks(&mut self, rule: &TrendRule, price: f64) -> anyhow::Result<bool> {
+        let bars = rule
+            .when
+            .iter()
+            .map(|c| required_bars(&c.indicator))
+            .max()
+            .unwrap_or(0)
+            .min(MAX_BARS);
+        let closes = self.closes(rule.interval, bars).await?;
+        Ok(rule.when.iter().all(|c| matches(c, closes, price)))
+    }
+
+    async fn closes(&mut self, interval: Interval, bars: usize) -> anyhow::Result<&[f64]> {
+        let stale = match self.closes.get(&interval) {
+            Some((at, closes)) => {
+                clock::elapsed(self.clock.as_ref(), *at, REFRESH) || closes.len() < bars
+            }
+            None => true,
+        };
+        if stale {
+            let k_lines = self
+                .market
+                .k_lines(&self.symbol, &KlineQuery::latest(interval, bars))
+                .await?;
+            let closes = k_lines.iter().map(|k| k.close).collect();
+            self.closes.insert(interval, (self.clock.now(), closes));
+        }
+        Ok(self.closes[&interval].1.as_slice())
+    }
+}
+
+/// 指标收敛所需的k线数量
+fn required_bars(indicator: &Indicator) -> usize {
+    match *indicator {
+        Indicator::Sma { period } => period + 1,
+        Indicator::Ema { period } | Indicator::Rsi { period } => period * 3 + 1,
+        Indicator::MacdHistogram { slow, signal, .. } => (slow + signal) * 3,
+    }
+}
+
+fn series(indicator: &Indicator, closes: &[f64]) -> Vec<f64> {
+    match *indicator {
+        Indicator::Sma { period } => indicator::sma(closes, period),
+        Indicator::Ema { period } => indicator::ema(closes, period),
+        Indicator::Rsi { period } => indicator::rsi(closes, period),
+        Indicator::MacdHistogram { fast, slow, signal } => {
+            indicator::macd_histogram(closes, fast, slow, signal)
+        }
+    }
+}
+
+/// 条件是否满足，数据不足时视为不满足
+fn matches(condition: &Condition, closes: &[f64], price: f64) -> bool {
+    let values = series(&condition.indicator, closes);
+    let (last, prev) = match values.as_slice() {
+        [.., prev, last] => (*last, Some(*prev)),
+        [last] => (*last, None),
+        [] => return false,
+    };
+    match condition.compare {
+        Compare::PriceAbove => price > last,
+        Compare::PriceBelow => price < last,
+        Compare::Above => condition.value.is_some_and(|v| last > v),
+        Compare::Below => condition.value.is_some_and(|v| last < v),
+        Compare::Rising => prev.is_some_and(|p| last > p),
+        Compare::Falling => prev.is_some_and(|p| last < p),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+    use crate::trade::fake::{bar, FakeMarket};
+
+    fn condition(indicator: Indicator, compare: Compare, value: Option<f64>) -> Condition {
+        Condition {
+            indicator,
+            compare,
+            value,
+        }
+    }
+
+    #[test]
+    fn price_below_ema_should_match() {
+        let closes: Vec<f64> = (1..=30).map(|i| i as f64).collect();
+        let c = condition(Indicator::Ema { period: 10 }, Compare::PriceBelow, None);
+        assert!(matches(&c, &closes, 10.0));
+        assert!(!matches(&c, &closes, 40.0));
+    }
+
+    #[test]
+    fn threshold_and_slope_should_match() {
+        let closes: Vec<f64> = (1..=30).map(|i| i as f64).collect();
+        let above = condition(Indicator::Rsi { period: 14 }, Compare::Above, Some(70.0));
+        assert!(matches(&above, &closes, 30.0));
+        let rising = condition(Indicator::Sma { period: 5 }, Compare::Rising, None);
+        assert!(matches(&rising, &closes, 30.0));
+        let falling = condition(Indicator::Sma { period: 5 }, Compare::Falling, None);
+        assert!(!matches(&falling, &closes, 30.0));
+    }
+
+    #[test]
+    fn insufficient_data_should_not_match() {
+        let closes = [1.0, 2.0];
+        let c = condition(Indicator::Ema { period: 200 }, Compare::PriceBelow, None);
+        assert!(!matches(&c, &closes, 0.5));
+        let no_value = condition(Indicator::Sma { period: 1 }, Compare::Above, None);
+        assert!(!matches(&no_value, &closes, 0.5));
+    }
+
+    #[tokio::test]
+    async fn blocked_side_should_be_tracked_until_allowed() {
+        let market = FakeMarket::new([]).with_k_lines(vec![bar(10.0, 10.0, 10.0, 10.0); 5]);
+        let rule = TrendRule {
+            side: TradeSide::Buy,
+            interval: Interval::Hour4,
+            when: vec![condition(
+                Indicator::Sma { period: 1 },
+                Compare::PriceBelow,
+                None,
+            )],
+        };
+        let mut filter = TrendFilter::new(
+            Symbol::Eth,
+            vec![rule],
+            Arc::new(market),
+            Arc::new(SystemClock),
+        );
+        assert!(!filter.allows(TradeSide::Buy, 5.0).await);
+        assert!(!filter.allows(TradeSide::Buy, 5.0).await);
+        assert!(filter.blocked.contains(&TradeSide::Buy));
+        assert!(filter.allows(TradeSide::Sell, 5.0).await);
+        assert!(filter.allows(TradeSide::Buy, 15.0).await);
+        assert!(filter.blocked.is_empty());
+    }
+}
@@ -0,0 +1,344 @@
+//! 历史 k 线: 分页下载并保存为本地 CSV 文件，供回测及参数调整使用
+
+use std::fs::{self, File, OpenOptions};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::trade::{Interval, Kline, KlineQuery, MarketService};
+use crate::{Symbol, TgError};
+
+/// 每次请求的 k 线数量，币安的上限
+const PAGE_SIZE: usize = 1000;
+
+/// 以 CSV 文件保存的 k 线，文件名为 `<symbol>.<interval>.csv`，按开盘时间排序
+#[derive(Clone, Debug)]
+pub struct KlineStore {
+    dir: PathBuf,
+}
+
+/// CSV 中的一行
+#[derive(Serialize, Deserialize)]
+struct Row {
+    open_time: i64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    close_time: i64,
+    count: usize,
+}
+
+impl From<&Kline> for Row {
+    fn from(k: &Kline) -> Self {
+        Self {
+            open_time: k.open_time,
+            open: k.open,
+            high: k.high,
+            low: k.low,
+            close: k.close,
+            close_time: k.close_time,
+            count: k.count,
+        }
+    }
+}
+
+impl From<Row> for Kline {
+    fn from(r: Row) -> Self {
+        Self {
+            open_time: r.open_time,
+            open: r.open,
+            high: r.high,
+            low: r.low,
+            close: r.close,
+            close_time: r.close_time,
+            count: r.count,
+        }
+    }
+}
+
+impl KlineStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn path(&self, symbol: &Symbol, interval: Interval) -> PathBuf {
+        self.dir.join(format!("{}.{}.csv", symbol, interval))
+    }
+
+    /// 已保存的全部 k 线，文件不存在时为空
+    pub fn load(&self, symbol: &Symbol, interval: Interval) -> Result<Vec<Kline>, TgError> {
+        let path = self.path(symbol, interval);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut reader = csv::Reader::from_path(path).map_err(csv_error)?;
+        reader
+            .deserialize::<Row>()
+            .map(|row| row.map(Kline::from).map_err(csv_error))
+            .collect()
+    }
+
+    /// 按查询条件读取已保存的 k 线
+    pub fn query(&self, symbol: &Symbol, query: &KlineQuery) -> Result<Vec<Kline>, TgError> {
+        Ok(query.select(&self.load(symbol, query.interval)?))
+    }
+
+    /// 在文件末尾追加 `bars`，调用方保证其晚于已保存的 k 线
+    pub fn append(
+        &self,
+        symbol: &Symbol,
+        interval: Interval,
+        bars: &[Kline],
+    ) -> Result<(), TgError> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(symbol, interval);
+        let header = !path.exists();
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        write_rows(file, header, bars)
+    }
+
+    /// 以 `bars` 替换已保存的全部 k 线
+    pub fn save(&self, symbol: &Symbol, interval: Interval, bars: &[Kline]) -> Result<(), TgError> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(symbol, interval);
+        // 先写临时文件再替换，中途失败时不损坏已有数据
+        let tmp = path.with_extension("csv.tmp");
+        write_rows(File::create(&tmp)?, true, bars)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// 最后一根 k 线的开盘时间
+    pub fn last_open_time(
+        &self,
+        symbol: &Symbol,
+        interval: Interval,
+    ) -> Result<Option<i64>, TgError> {
+        Ok(self.load(symbol, interval)?.last().map(|k| k.open_time))
+    }
+}
+
+fn write_rows(file: File, header: bool, bars: &[Kline]) -> Result<(), TgError> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(header)
+        .from_writer(file);
+    for bar in bars {
+        writer.serialize(Row::from(bar)).map_err(csv_error)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn csv_error(e: csv::Error) -> TgError {
+    TgError::DecodeError(e.to_string())
+}
+
+/// 下载 `[start_time, end_time)` 内已收盘的 k 线并保存到 `store`，返回新增的数量。
+/// 只请求已保存范围之前及之后的 k 线，之后的部分从已保存的最后一根接着下载，保证文件中没有缺口
+pub async fn download(
+    market: &dyn MarketService,
+    store: &KlineStore,
+    symbol: &Symbol,
+    interval: Interval,
+    start_time: i64,
+    end_time: i64,
+) -> Result<usize> {
+    let stored = store.load(symbol, interval)?;
+    let (first, last) = match (stored.first(), stored.last()) {
+        (Some(first), Some(last)) => (first.open_time, last.open_time),
+        _ => {
+            let bars = fetch(market, symbol, interval, start_time, end_time).await?;
+            store.append(symbol, interval, &bars)?;
+            return Ok(bars.len());
+        }
+    };
+    let before = match start_time < first {
+        true => fetch(market, symbol, interval, start_time, first.min(end_time)).await?,
+        false => Vec::new(),
+    };
+    let after = fetch(market, symbol, interval, last + interval.millis(), end_time).await?;
+    let total = before.len() + after.len();
+    if before.is_empty() {
+        store.append(symbol, interval, &after)?;
+    } else {
+        let mut bars = before;
+        bars.extend(stored);
+        bars.extend(after);
+        store.save(symbol, interval, &bars)?;
+    }
+    Ok(total)
+}
+
+/// 分页请求 `[from, end_time)` 内已收盘的 k 线
+async fn fetch(
+    market: &dyn MarketService,
+    symbol: &Symbol,
+    interval: Interval,
+    mut from: i64,
+    end_time: i64,
+) -> Result<Vec<Kline>> {
+    let mut bars = Vec::new();
+    while from < end_time {
+        let query = KlineQuery::range(interval, from, Some(end_time)).with_limit(PAGE_SIZE);
+        let page = market.k_lines(symbol, &query).await?;
+        let full = page.len() == PAGE_SIZE;
+        let start = bars.len();
+        bars.extend(
+            page.into_iter()
+                .filter(|k| k.open_time >= from && k.close_time < end_time),
+        );
+        let last = match bars[start..].last() {
+            Some(last) => last.open_time,
+            None => break,
+        };
+        info!("币种:{},已下载{}根{}k线", symbol, bars.len(), interval);
+        if !full {
+            break;
+        }
+        from = last + interval.millis();
+    }
+    Ok(bars)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::trade::fake::{bar, FakeMarket};
+
+    use super::*;
+
+    fn hourly(n: i64) -> Vec<Kline> {
+        let millis = Interval::Hour1.millis();
+        (0..n)
+            .map(|i| Kline {
+                open_time: i * millis,
+                close_time: (i + 1) * millis - 1,
+                ..bar(100.0, 101.0, 99.0, 100.0 + i as f64)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn k_lines_should_be_downloaded_incrementally() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KlineStore::new(dir.path());
+        let market = Arc::new(FakeMarket::default().with_k_lines(hourly(2500)));
+        let millis = Interval::Hour1.millis();
+
+        let n = download(
+            market.as_ref(),
+            &store,
+            &Symbol::Eth,
+            Interval::Hour1,
+            0,
+            1200 * millis,
+        )
+        .await
+        .unwrap();
+        assert_eq!(n, 1200);
+        let n = download(
+            market.as_ref(),
+            &store,
+            &Symbol::Eth,
+            Interval::Hour1,
+            0,
+            1200 * millis,
+        )
+        .await
+        .unwrap();
+        assert_eq!(n, 0);
+        // 最后一根未收盘，不保存
+        let end = 2499 * millis + 1;
+        let n = download(
+            market.as_ref(),
+            &store,
+            &Symbol::Eth,
+            Interval::Hour1,
+            0,
+            end,
+        )
+        .await
+        .unwrap();
+        assert_eq!(n, 1299);
+
+        let bars = store.load(&Symbol::Eth, Interval::Hour1).unwrap();
+        assert_eq!(bars, hourly(2499));
+        let latest = store
+            .query(&Symbol::Eth, &KlineQuery::latest(Interval::Hour1, 10))
+            .unwrap();
+        assert_eq!(latest[0].open_time, 2489 * millis);
+    }
+
+    #[tokio::test]
+    async fn earlier_start_should_be_prepended() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KlineStore::new(dir.path());
+        let market = FakeMarket::default().with_k_lines(hourly(300));
+        let millis = Interval::Hour1.millis();
+
+        let n = download(
+            &market,
+            &store,
+            &Symbol::Eth,
+            Interval::Hour1,
+            100 * millis,
+            200 * millis,
+        )
+        .await
+        .unwrap();
+        assert_eq!(n, 100);
+        let n = download(
+            &market,
+            &store,
+            &Symbol::Eth,
+            Interval::Hour1,
+            0,
+            200 * millis,
+        )
+        .await
+        .unwrap();
+        assert_eq!(n, 100);
+        assert_eq!(
+            store.load(&Symbol::Eth, Interval::Hour1).unwrap(),
+            hourly(200)
+        );
+    }
+
+    #[tokio::test]
+    async fn later_start_should_not_leave_a_gap() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KlineStore::new(dir.path());
+        let market = FakeMarket::default().with_k_lines(hourly(300));
+        let millis = Interval::Hour1.millis();
+
+        download(
+            &market,
+            &store,
+            &Symbol::Eth,
+            Interval::Hour1,
+            0,
+            10 * millis,
+        )
+        .await
+        .unwrap();
+        let n = download(
+            &market,
+            &store,
+            &Symbol::Eth,
+            Interval::Hour1,
+            50 * millis,
+            60 * millis,
+        )
+        .await
+        .unwrap();
+        assert_eq!(n, 50);
+        assert_eq!(
+            store.load(&Symbol::Eth, Interval::Hour1).unwrap(),
+            hourly(60)
+        );
+    }
+}
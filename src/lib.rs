@@ -1,11 +1,11 @@
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
 
 use anyhow::Result;
-use tokio::task::JoinHandle;
-use tracing::{error, info, span, warn};
+use tracing::{error, info, warn};
 use tracing_subscriber::{
     filter,
     fmt::{self, format},
@@ -16,84 +16,120 @@ use tracing_subscriber::{
 
 pub use config::*;
 pub use error::TgError;
+pub use validate::ConfigIssue;
 
-use crate::trade::{MarketService, TradeService};
+use crate::clock::{Clock, SystemClock};
+use crate::event::EventBus;
+use crate::grid::{GridContext, GridRegistry, StateStore};
+use crate::risk::RiskManager;
+use crate::supervisor::Supervisor;
+use crate::trade::BalanceGuard;
 
+pub mod backtest;
+pub mod clock;
 mod config;
 mod error;
-mod grid;
+pub mod event;
+pub mod grid;
+pub mod history;
+pub mod notify;
+pub mod optimize;
+pub mod risk;
 mod serde;
-mod trade;
+mod supervisor;
+pub mod trade;
+mod validate;
 
 /// 通过配置创建 TG 服务器
 pub async fn start_server_with_config(config: &ServerConfig) -> Result<()> {
-    info!("Starting: Trend Grid Server");
-    let (market, trade) = trade::factory(config.trade.as_ref())?;
-
-    // If the server fails, shut down the server directly
-    if let Err(e) = market.ping().await {
-        let url = config.trade.as_ref().url.as_str();
-        error!("Unable to ping service: {}", url);
-        return Err(e);
-    }
-
-    let mut handles = Vec::new();
-
-    if let Some(eth) = config.coin.eth.as_ref() {
-        let join_eth = start_with_coin(Symbol::Eth, eth, market.clone(), trade.clone())?;
-        handles.push(join_eth);
-    };
+    start_server_with_registry(config, GridRegistry::default(), EventBus::default()).await
+}
 
-    if let Some(bnb) = config.coin.bnb.as_ref() {
-        let join_eth = start_with_coin(Symbol::Bnb, bnb, market.clone(), trade.clone())?;
-        handles.push(join_eth);
-    };
+/// 通过配置创建 TG 服务器，网格策略从 `registry` 中选择，运行事件发布到 `events`
+pub async fn start_server_with_registry(
+    config: &ServerConfig,
+    registry: GridRegistry,
+    events: EventBus,
+) -> Result<()> {
+    let supervisor = start(config, registry, events).await?;
+    supervisor.join().await;
+    Ok(())
+}
 
-    if let Some(btc) = config.coin.btc.as_ref() {
-        let join_eth = start_with_coin(Symbol::Btc, btc, market.clone(), trade.clone())?;
-        handles.push(join_eth);
-    };
+/// 读取配置文件 `path` 创建 TG 服务器，文件修改或收到 SIGHUP 时热更新币种配置:
+/// 运行中的网格更新参数，新增的币种启动网格，移除的币种停止网格
+pub async fn start_server_with_reload(
+    path: impl Into<PathBuf>,
+    registry: GridRegistry,
+    events: EventBus,
+) -> Result<()> {
+    let path = path.into();
+    let config = ServerConfig::load(&path)?;
+    let supervisor = start(&config, registry, events).await?;
+    supervisor::watch(path, config, supervisor).await;
+    Ok(())
+}
 
-    if handles.is_empty() {
-        warn!("No option coin is running.");
+async fn start(
+    config: &ServerConfig,
+    registry: GridRegistry,
+    events: EventBus,
+) -> Result<Supervisor> {
+    info!("Starting: Trend Grid Server");
+    config.check(&registry)?;
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    // 只连接币种使用的账户，没有指定 account 的币种使用 [trade]
+    let used: HashSet<Option<&str>> = config
+        .coin
+        .coins()
+        .into_iter()
+        .map(|(_, coin)| coin.account.as_deref())
+        .collect();
+    let mut services = Vec::new();
+    for (name, account) in config.accounts().filter(|(name, _)| used.contains(name)) {
+        let (market, trade) = trade::factory(account, clock.clone())?;
+        // If the server fails, shut down the server directly
+        if let Err(e) = market.ping().await {
+            error!("Unable to ping service: {}", account.url);
+            return Err(e);
+        }
+        services.push((name.map(String::from), account, market, trade));
     }
 
-    for handle in handles {
-        if let (Err(e),) = tokio::join!(handle) {
-            error!("Processor error: {}", e);
+    notify::spawn(&config.notify, &events, clock.clone())?;
+    let store = StateStore::new(&config.state.path);
+    let risk = Arc::new(RiskManager::new(
+        config.risk.clone(),
+        events.clone(),
+        clock.clone(),
+    ));
+    let mut accounts = HashMap::new();
+    for (name, account, market, trade) in services {
+        let balance = Arc::new(BalanceGuard::new(
+            trade.clone(),
+            account.reserve.clone(),
+            clock.clone(),
+        ));
+        let ctx = GridContext {
+            symbol: Symbol::Eth,
+            market,
+            trade,
+            events: events.clone(),
+            store: store.clone(),
+            risk: risk.clone(),
+            balance,
+            clock: clock.clone(),
         };
+        let dry_run = account.dry_run;
+        accounts.insert(name, supervisor::Account { ctx, dry_run });
     }
+    let mut supervisor = Supervisor::new(registry, accounts);
+    supervisor.apply(&config.coin).await?;
 
-    Ok(())
-}
-
-fn start_with_coin(
-    symbol: Symbol,
-    coin: &Coin,
-    market: Arc<dyn MarketService>,
-    trade: Arc<dyn TradeService>,
-) -> Result<JoinHandle<()>> {
-    let mut grid = grid::factory(&symbol, coin, market.clone(), trade.clone())?;
-
-    let root = span!(tracing::Level::INFO, "Grid");
-    let _enter = root.enter();
-
-    let join = tokio::spawn(async move {
-        loop {
-            match market.ticker_price(&symbol).await {
-                Ok(price) => {
-                    if let Err(e) = grid.execute(price).await {
-                        error!("Grid execute error: {}", e);
-                    }
-                }
-                Err(e) => {
-                    error!("Get ticker price error: {}.", e);
-                    tokio::time::sleep(Duration::from_secs(1)).await;
-                }
-            }
-        }
-    });
-    Ok(join)
+    if supervisor.is_empty() {
+        warn!("No option coin is running.");
+    }
+    Ok(supervisor)
 }
 
 // 初始化日志配置
@@ -0,0 +1,536 @@
+//! 事件通知: 订阅 `EventBus`，把成交、停止、错误等事件发送到 Webhook 或本地命令，
+//! 每个渠道按事件种类限流，避免错误风暴刷屏
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::clock::{self, Clock};
+use crate::event::{EventBus, GridEvent, NOISY_KINDS};
+use crate::{NotifyConfig, NotifyKind, Symbol, TgError, TradeSide};
+
+/// 限流的统计窗口
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// 等待通知命令结束的时间
+const EXEC_TIMEOUT: Duration = Duration::from_secs(10);
+/// 等待 Webhook 响应的时间
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 发送给通知渠道的内容
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Notification {
+    pub event: &'static str,
+    pub symbol: Symbol,
+    pub message: String,
+    pub time: DateTime<Utc>,
+    /// 原始事件
+    pub detail: GridEvent,
+}
+
+impl Notification {
+    pub fn new(event: GridEvent, time: DateTime<Utc>) -> Self {
+        Self {
+            event: event.kind(),
+            symbol: event.symbol().clone(),
+            message: message(&event),
+            time,
+            detail: event,
+        }
+    }
+}
+
+/// 事件的文字描述
+fn message(event: &GridEvent) -> String {
+    match event {
+        GridEvent::OrderFilled {
+            symbol,
+            side,
+            price,
+            quantity,
+            profit,
+        } => {
+            let side = side_name(side);
+            match profit {
+                Some(profit) => format!(
+                    "{}{}成交，价格: {}，数量: {}，预计盈亏{:.4}U",
+                    symbol, side, price, quantity, profit
+                ),
+                None => format!(
+                    "{}{}成交，价格: {}，数量: {}",
+                    symbol, side, price, quantity
+                ),
+            }
+        }
+        GridEvent::Halted {
+            symbol,
+            reason,
+            price,
+        } => format!("{}触发{:?}，网格停止，价格: {}", symbol, reason, price),
+        GridEvent::RangeExited {
+            symbol,
+            price,
+            limit,
+            action,
+        } => format!("{}价格{}超出区间{}，执行{:?}", symbol, price, limit, action),
+        GridEvent::RangeEntered { symbol, price } => {
+            format!("{}价格{}回到区间内，恢复交易", symbol, price)
+        }
+        GridEvent::Vetoed(veto) => {
+            format!(
+                "{}的{:?}被风控拒绝: {}",
+                veto.symbol, veto.side, veto.reason
+            )
+        }
+        GridEvent::Error { symbol, message } => format!("{}运行出错: {}", symbol, message),
+        GridEvent::PriceTick { symbol, price } => format!("{}最新价格: {}", symbol, price),
+        GridEvent::OrderSubmitted {
+            symbol,
+            side,
+            price,
+            quantity,
+            quote,
+        } => match (quantity, quote) {
+            (_, Some(quote)) => format!(
+                "{}提交{}订单，市价: {}，金额: {}U",
+                symbol,
+                side_name(side),
+                price,
+                quote
+            ),
+            (quantity, None) => format!(
+                "{}提交{}订单，市价: {}，数量: {}",
+                symbol,
+                side_name(side),
+                price,
+                quantity.unwrap_or_default()
+            ),
+        },
+        GridEvent::LevelsChanged { symbol, buy, sell } => {
+            format!("{}网格调整，买入价: {}，卖出价: {}", symbol, buy, sell)
+        }
+        GridEvent::RatioRecalculated { symbol, ratio } => {
+            format!("{}网格比例重新计算为{:.4}", symbol, ratio)
+        }
+    }
+}
+
+fn side_name(side: &TradeSide) -> &'static str {
+    match side {
+        TradeSide::Buy => "买入",
+        TradeSide::Sell => "卖出",
+    }
+}
+
+/// 通知渠道
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<()>;
+}
+
+/// 以 POST 请求发送 JSON
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    template: Option<String>,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: impl Into<String>, template: Option<String>) -> Result<Self, TgError> {
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()?,
+            url: url.into(),
+            template,
+        })
+    }
+
+    /// 按模板生成请求体，没有模板时为完整的 JSON
+    fn body(&self, n: &Notification) -> Result<String> {
+        let template = match self.template.as_ref() {
+            Some(template) => template,
+            None => return Ok(serde_json::to_string(n)?),
+        };
+        // 替换为 JSON 字符串的内容(不含引号)，模板中的值写在引号内
+        let escape = |s: &str| -> Result<String> {
+            let quoted = serde_json::to_string(s)?;
+            Ok(quoted[1..quoted.len() - 1].to_string())
+        };
+        Ok(template
+            .replace("{event}", &escape(n.event)?)
+            .replace("{symbol}", &escape(&n.symbol.to_string())?)
+            .replace("{message}", &escape(&n.message)?)
+            .replace("{time}", &escape(&n.time.to_rfc3339())?))
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, n: &Notification) -> Result<()> {
+        let res = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(self.body(n)?)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            return Err(TgError::Internal(format!("Webhook {}: {}", status, text)).into());
+        }
+        Ok(())
+    }
+}
+
+/// 执行本地命令，事件 JSON 从标准输入传入，
+/// 并设置环境变量 `TGS_EVENT`、`TGS_SYMBOL`、`TGS_MESSAGE`
+pub struct ExecNotifier {
+    command: String,
+    args: Vec<String>,
+}
+
+impl ExecNotifier {
+    pub fn new(command: impl Into<String>, args: Vec<String>) -> Self {
+        Self {
+            command: command.into(),
+            args,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for ExecNotifier {
+    async fn notify(&self, n: &Notification) -> Result<()> {
+        let mut child = tokio::process::Command::new(&self.command)
+            .args(&self.args)
+            .env("TGS_EVENT", n.event)
+            .env("TGS_SYMBOL", n.symbol.to_string())
+            .env("TGS_MESSAGE", &n.message)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(serde_json::to_string(n)?.as_bytes())
+                .await?;
+        }
+        let status = tokio::time::timeout(EXEC_TIMEOUT, child.wait())
+            .await
+            .map_err(|_| TgError::Internal(format!("{} timed out", self.command)))??;
+        if !status.success() {
+            return Err(
+                TgError::Internal(format!("{} exited with {}", self.command, status)).into(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// 窗口开始时间、已通过次数及被忽略次数
+type Window = (DateTime<Utc>, usize, usize);
+
+/// 每种事件在 `window` 内最多通过 `limit` 次
+pub struct RateLimiter {
+    limit: usize,
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    counts: Mutex<HashMap<&'static str, Window>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: usize, window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            limit,
+            window,
+            clock,
+            counts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 允许时返回上一个窗口中被忽略的次数
+    pub fn acquire(&self, kind: &'static str) -> Option<usize> {
+        let now = self.clock.now();
+        let mut counts = self.counts.lock().unwrap();
+        let (start, passed, dropped) = counts.entry(kind).or_insert((now, 0, 0));
+        let mut suppressed = 0;
+        if clock::elapsed(self.clock.as_ref(), *start, self.window) {
+            suppressed = *dropped;
+            *start = now;
+            *passed = 0;
+            *dropped = 0;
+        }
+        if *passed >= self.limit {
+            *dropped += 1;
+            return None;
+        }
+        *passed += 1;
+        Some(suppressed)
+    }
+}
+
+/// 一个配置好的渠道
+struct Channel {
+    notifier: Box<dyn Notifier>,
+    events: Vec<String>,
+    limiter: RateLimiter,
+}
+
+impl Channel {
+    fn from_config(config: &NotifyConfig, clock: Arc<dyn Clock>) -> Result<Self, TgError> {
+        let notifier: Box<dyn Notifier> = match config.kind {
+            NotifyKind::Webhook => {
+                let url = config
+                    .url
+                    .clone()
+                    .ok_or_else(|| TgError::ConfError("notify.url is required".to_string()))?;
+                Box::new(WebhookNotifier::new(url, config.template.clone())?)
+            }
+            NotifyKind::Exec => {
+                let command = config
+                    .command
+                    .clone()
+                    .ok_or_else(|| TgError::ConfError("notify.command is required".to_string()))?;
+                Box::new(ExecNotifier::new(command, config.args.clone()))
+            }
+        };
+        Ok(Self {
+            notifier,
+            events: config.events.clone(),
+            limiter: RateLimiter::new(config.rate_limit, RATE_WINDOW, clock),
+        })
+    }
+
+    /// 未指定 `events` 时通知高频事件以外的全部事件
+    fn wants(&self, event: &str) -> bool {
+        match self.events.is_empty() {
+            true => !NOISY_KINDS.contains(&event),
+            false => self.events.iter().any(|e| e == event),
+        }
+    }
+
+    async fn send(&self, notification: &Notification) {
+        if !self.wants(notification.event) {
+            return;
+        }
+        let suppressed = match self.limiter.acquire(notification.event) {
+            Some(suppressed) => suppressed,
+            None => {
+                warn!("{}通知过于频繁，已忽略", notification.event);
+                return;
+            }
+        };
+        let mut notification = notification.clone();
+        if suppressed > 0 {
+            notification.message = format!(
+                "{}(此前忽略了{}条同类通知)",
+                notification.message, suppressed
+            );
+        }
+        if let Err(e) = self.notifier.notify(&notification).await {
+            error!("发送{}通知失败: {}", notification.event, e);
+        }
+    }
+}
+
+/// 按配置创建通知渠道，每个渠道单独订阅 `events`，一个渠道缓慢或失败不影响其他渠道
+pub fn spawn(
+    configs: &[NotifyConfig],
+    events: &EventBus,
+    clock: Arc<dyn Clock>,
+) -> Result<Vec<JoinHandle<()>>, TgError> {
+    let channels = configs
+        .iter()
+        .map(|c| Channel::from_config(c, clock.clone()))
+        .collect::<Result<Vec<Channel>, TgError>>()?;
+    if !channels.is_empty() {
+        info!("已启用{}个通知渠道", channels.len());
+    }
+    Ok(channels
+        .into_iter()
+        .map(|channel| {
+            let mut rx = events.subscribe();
+            let clock = clock.clone();
+            tokio::spawn(async move {
+                loop {
+                    let event = match rx.recv().await {
+                        Ok(event) => event,
+                        Err(RecvError::Lagged(n)) => {
+                            warn!("通知处理过慢，丢失了{}个事件", n);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+                    channel.send(&Notification::new(event, clock.now())).await;
+                }
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    use crate::clock::{ManualClock, SystemClock};
+
+    use super::*;
+
+    fn filled() -> GridEvent {
+        GridEvent::OrderFilled {
+            symbol: Symbol::Eth,
+            side: TradeSide::Sell,
+            price: 110.0,
+            quantity: 1.0,
+            profit: Some(10.0),
+        }
+    }
+
+    #[test]
+    fn notifications_should_be_rate_limited() {
+        let clock = Arc::new(ManualClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ));
+        let limiter = RateLimiter::new(2, RATE_WINDOW, clock.clone());
+        assert_eq!(limiter.acquire("Error"), Some(0));
+        assert_eq!(limiter.acquire("Error"), Some(0));
+        assert_eq!(limiter.acquire("Error"), None);
+        assert_eq!(limiter.acquire("Error"), None);
+        // 其他种类的事件不受影响
+        assert_eq!(limiter.acquire("OrderFilled"), Some(0));
+
+        clock.advance(Duration::from_secs(61));
+        assert_eq!(limiter.acquire("Error"), Some(2));
+        assert_eq!(limiter.acquire("Error"), Some(0));
+    }
+
+    #[test]
+    fn noisy_events_should_need_explicit_subscription() {
+        let clock = Arc::new(SystemClock);
+        let config: NotifyConfig = toml::from_str("kind = 'Exec'\ncommand = 'true'").unwrap();
+        let channel = Channel::from_config(&config, clock.clone()).unwrap();
+        assert!(channel.wants("OrderFilled"));
+        assert!(!channel.wants("PriceTick"));
+        assert!(channel.wants("Halted"));
+
+        let config = NotifyConfig {
+            events: vec!["PriceTick".to_string()],
+            ..config
+        };
+        let channel = Channel::from_config(&config, clock).unwrap();
+        assert!(channel.wants("PriceTick"));
+        assert!(!channel.wants("OrderFilled"));
+    }
+
+    #[tokio::test]
+    async fn webhook_should_post_rendered_template() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            // 读到请求体结束
+            while !String::from_utf8_lossy(&request).ends_with('}') {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let webhook =
+            WebhookNotifier::new(url, Some(r#"{"text": "[{event}] {message}"}"#.to_string()))
+                .unwrap();
+        let notification = Notification::new(filled(), Utc::now());
+        webhook.notify(&notification).await.unwrap();
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /hook"), "{}", request);
+        let body = request.split("\r\n\r\n").nth(1).unwrap();
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            json["text"],
+            format!("[OrderFilled] {}", notification.message)
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn slow_channel_should_not_block_others() {
+        // 接受连接但从不响应的 Webhook
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let _server = tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let configs: Vec<NotifyConfig> = vec![
+            toml::from_str(&format!("kind = 'Webhook'\nurl = '{}'", url)).unwrap(),
+            toml::from_str(&format!(
+                "kind = 'Exec'\ncommand = 'sh'\nargs = ['-c', 'cat > {}']",
+                out.display()
+            ))
+            .unwrap(),
+        ];
+        let events = EventBus::default();
+        let handles = spawn(&configs, &events, Arc::new(SystemClock)).unwrap();
+        assert_eq!(handles.len(), 2);
+
+        events.publish(filled());
+        let delivered = async {
+            while std::fs::read_to_string(&out).map_or(true, |s| s.is_empty()) {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), delivered)
+            .await
+            .unwrap();
+        handles.iter().for_each(|h| h.abort());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exec_should_receive_event_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let exec = ExecNotifier::new(
+            "sh",
+            vec![
+                "-c".to_string(),
+                format!("echo $TGS_EVENT > {0}; cat >> {0}", out.display()),
+            ],
+        );
+        exec.notify(&Notification::new(filled(), Utc::now()))
+            .await
+            .unwrap();
+        let output = std::fs::read_to_string(out).unwrap();
+        assert!(output.starts_with("OrderFilled\n"), "{}", output);
+        assert!(output.contains(r#""profit":10.0"#), "{}", output);
+
+        let failing = ExecNotifier::new("sh", vec!["-c".to_string(), "exit 3".to_string()]);
+        assert!(failing
+            .notify(&Notification::new(filled(), Utc::now()))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,502 @@
+//! 参数优化: 在历史 k 线上用网格搜索或随机搜索回测 `profit_ratio`、`double_throw_ratio`
+//! 及 `quantity` 的组合，按收益、回撤或夏普比率排序，可选滚动(walk-forward)验证
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::backtest::{Backtest, BacktestReport};
+use crate::grid::{GridRegistry, FIXED_STRATEGY, REVERSE_STRATEGY};
+use crate::trade::Kline;
+use crate::{Coin, Symbol, TgError};
+
+/// 参数的取值范围，写作 `start:end:step`，只有一个数时为固定值
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Range {
+    pub start: f64,
+    pub end: f64,
+    /// 网格搜索的步长，随机搜索时忽略
+    pub step: f64,
+}
+
+impl Range {
+    /// 网格搜索的取值，包含 `end`
+    pub fn values(&self) -> Vec<f64> {
+        if self.step <= 0.0 || self.end <= self.start {
+            return vec![self.start];
+        }
+        let n = ((self.end - self.start) / self.step + 1e-9).floor() as usize;
+        (0..=n).map(|i| self.start + self.step * i as f64).collect()
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        if self.end <= self.start {
+            return self.start;
+        }
+        rng.gen_range(self.start..=self.end)
+    }
+}
+
+impl FromStr for Range {
+    type Err = TgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || TgError::ConfError(format!("Invalid range: {}, expect start:end:step", s));
+        let parts = s
+            .split(':')
+            .map(|p| p.trim().parse::<f64>().map_err(|_| invalid()))
+            .collect::<Result<Vec<f64>, TgError>>()?;
+        match parts[..] {
+            [value] => Ok(Self {
+                start: value,
+                end: value,
+                step: 0.0,
+            }),
+            [start, end] => Ok(Self {
+                start,
+                end,
+                step: end - start,
+            }),
+            [start, end, step] if end >= start && step >= 0.0 => Ok(Self { start, end, step }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// 需要搜索的参数，未指定的参数使用币种配置中的值
+#[derive(Clone, Debug, Default)]
+pub struct SearchSpace {
+    pub profit_ratio: Option<Range>,
+    pub double_throw_ratio: Option<Range>,
+    pub quantity: Option<Range>,
+}
+
+/// 一组参数
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+pub struct Params {
+    pub profit_ratio: f64,
+    pub double_throw_ratio: f64,
+    pub quantity: f64,
+}
+
+impl Params {
+    pub fn of(coin: &Coin) -> Self {
+        Self {
+            profit_ratio: coin.profit_ratio,
+            double_throw_ratio: coin.double_throw_ratio,
+            quantity: coin.quantity,
+        }
+    }
+
+    /// 使用本组参数的币种配置
+    pub fn apply(&self, coin: &Coin) -> Coin {
+        Coin {
+            profit_ratio: self.profit_ratio,
+            double_throw_ratio: self.double_throw_ratio,
+            quantity: self.quantity,
+            ..coin.clone()
+        }
+    }
+}
+
+/// 搜索方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Search {
+    /// 全部取值的组合
+    Grid,
+    /// 在范围内随机取 `samples` 组，`seed` 相同时结果相同
+    Random { samples: usize, seed: u64 },
+}
+
+impl SearchSpace {
+    /// 是否搜索 `profit_ratio` 或 `double_throw_ratio`
+    pub fn sweeps_ratio(&self) -> bool {
+        self.profit_ratio.is_some() || self.double_throw_ratio.is_some()
+    }
+
+    /// 以 `coin` 的参数为默认值生成候选参数
+    pub fn candidates(&self, coin: &Coin, search: Search) -> Vec<Params> {
+        let base = Params::of(coin);
+        let fixed = |value: f64| Range {
+            start: value,
+            end: value,
+            step: 0.0,
+        };
+        let profit = self.profit_ratio.unwrap_or(fixed(base.profit_ratio));
+        let double = self
+            .double_throw_ratio
+            .unwrap_or(fixed(base.double_throw_ratio));
+        let quantity = self.quantity.unwrap_or(fixed(base.quantity));
+        match search {
+            Search::Grid => {
+                let mut candidates = Vec::new();
+                for profit_ratio in profit.values() {
+                    for double_throw_ratio in double.values() {
+                        for quantity in quantity.values() {
+                            candidates.push(Params {
+                                profit_ratio,
+                                double_throw_ratio,
+                                quantity,
+                            });
+                        }
+                    }
+                }
+                candidates
+            }
+            Search::Random { samples, seed } => {
+                let mut rng = StdRng::seed_from_u64(seed);
+                (0..samples)
+                    .map(|_| Params {
+                        profit_ratio: profit.sample(&mut rng),
+                        double_throw_ratio: double.sample(&mut rng),
+                        quantity: quantity.sample(&mut rng),
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// 排序依据
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rank {
+    /// 盈亏从高到低
+    #[default]
+    Pnl,
+    /// 最大回撤从低到高，相同时按盈亏
+    Drawdown,
+    /// 夏普比率从高到低
+    Sharpe,
+}
+
+impl FromStr for Rank {
+    type Err = TgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pnl" => Ok(Rank::Pnl),
+            "drawdown" => Ok(Rank::Drawdown),
+            "sharpe" => Ok(Rank::Sharpe),
+            _ => Err(TgError::ConfError(format!("Unknown rank: {}", s))),
+        }
+    }
+}
+
+impl Rank {
+    /// 更好的结果排在前面
+    fn compare(&self, a: &BacktestReport, b: &BacktestReport) -> Ordering {
+        match self {
+            Rank::Pnl => b.pnl.total_cmp(&a.pnl),
+            Rank::Drawdown => a
+                .max_drawdown
+                .total_cmp(&b.max_drawdown)
+                .then(b.pnl.total_cmp(&a.pnl)),
+            Rank::Sharpe => b.sharpe.total_cmp(&a.sharpe),
+        }
+    }
+}
+
+/// 一组参数的回测结果
+#[derive(Clone, Debug, Serialize)]
+pub struct Trial {
+    pub params: Params,
+    pub report: BacktestReport,
+}
+
+/// 一次滚动验证: 在训练区间选出最优参数，再在紧随其后的测试区间回测
+#[derive(Clone, Debug, Serialize)]
+pub struct Fold {
+    /// 训练区间第一根 k 线的开盘时间
+    pub train_start: i64,
+    /// 测试区间第一根 k 线的开盘时间
+    pub test_start: i64,
+    pub params: Params,
+    pub train: BacktestReport,
+    pub test: BacktestReport,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OptimizeReport {
+    pub symbol: Symbol,
+    /// 全部 k 线上的结果，按 `rank` 排序
+    pub trials: Vec<Trial>,
+    pub folds: Vec<Fold>,
+}
+
+/// 以 `backtest` 为模板，替换币种参数后回测
+pub struct Optimizer {
+    pub backtest: Backtest,
+    pub space: SearchSpace,
+    pub search: Search,
+    pub rank: Rank,
+    /// 并行回测的线程数
+    pub jobs: usize,
+    /// 滚动验证的次数，k 线被等分为 `splits + 1` 段，为0时不验证
+    pub splits: usize,
+}
+
+impl Optimizer {
+    pub fn run(&self, registry: &GridRegistry, bars: &[Kline]) -> Result<OptimizeReport> {
+        let template = self.template()?;
+        let candidates = self.space.candidates(&template.coin, self.search);
+        if candidates.is_empty() {
+            return Err(TgError::ConfError("No parameters to optimize".to_string()).into());
+        }
+        let trials = self.evaluate(registry, &template, &candidates, bars);
+
+        let mut folds = Vec::new();
+        if self.splits > 0 {
+            let size = bars.len() / (self.splits + 1);
+            if size < 2 {
+                return Err(TgError::ConfError(format!(
+                    "Not enough k lines for {} splits: {}",
+                    self.splits,
+                    bars.len()
+                ))
+                .into());
+            }
+            for i in 0..self.splits {
+                let train = &bars[i * size..(i + 1) * size];
+                let test = &bars[(i + 1) * size..(i + 2) * size];
+                let best = match self
+                    .evaluate(registry, &template, &candidates, train)
+                    .into_iter()
+                    .next()
+                {
+                    Some(best) => best,
+                    None => continue,
+                };
+                let backtest = Backtest {
+                    coin: best.params.apply(&template.coin),
+                    ..template.clone()
+                };
+                folds.push(Fold {
+                    train_start: train[0].open_time,
+                    test_start: test[0].open_time,
+                    params: best.params,
+                    train: best.report,
+                    test: backtest.run(registry, test)?,
+                });
+            }
+        }
+        Ok(OptimizeReport {
+            symbol: self.backtest.symbol.clone(),
+            trials,
+            folds,
+        })
+    }
+
+    /// 回测使用的模板，搜索比例时关闭按 k 线重新计算比例，否则配置的比例不起作用
+    fn template(&self) -> Result<Backtest> {
+        let mut backtest = self.backtest.clone();
+        if self.space.sweeps_ratio() {
+            if !matches!(
+                backtest.coin.strategy.as_str(),
+                FIXED_STRATEGY | REVERSE_STRATEGY
+            ) {
+                return Err(TgError::ConfError(format!(
+                    "Strategy {} does not use profit_ratio or double_throw_ratio",
+                    backtest.coin.strategy
+                ))
+                .into());
+            }
+            backtest.coin.fixed_ratio = true;
+        }
+        Ok(backtest)
+    }
+
+    /// 用 `jobs` 个线程回测全部候选参数，失败的组合记录日志后跳过，结果按 `rank` 排序
+    fn evaluate(
+        &self,
+        registry: &GridRegistry,
+        template: &Backtest,
+        candidates: &[Params],
+        bars: &[Kline],
+    ) -> Vec<Trial> {
+        let next = AtomicUsize::new(0);
+        let trials = Mutex::new(Vec::with_capacity(candidates.len()));
+        std::thread::scope(|scope| {
+            for _ in 0..self.jobs.clamp(1, candidates.len()) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, AtomicOrdering::Relaxed);
+                    let Some(params) = candidates.get(i) else {
+                        break;
+                    };
+                    let backtest = Backtest {
+                        coin: params.apply(&template.coin),
+                        ..template.clone()
+                    };
+                    match backtest.run(registry, bars) {
+                        Ok(report) => trials.lock().unwrap().push((
+                            i,
+                            Trial {
+                                params: *params,
+                                report,
+                            },
+                        )),
+                        Err(e) => warn!("参数{:?}回测失败: {}", params, e),
+                    }
+                });
+            }
+        });
+        let mut trials = trials.into_inner().unwrap();
+        // 先按候选顺序排列，保证结果相同时的顺序与线程数无关
+        trials.sort_by_key(|(i, _)| *i);
+        let mut trials: Vec<Trial> = trials.into_iter().map(|(_, t)| t).collect();
+        trials.sort_by(|a, b| self.rank.compare(&a.report, &b.report));
+        trials
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trade::Interval;
+    use crate::RiskConfig;
+
+    use super::*;
+
+    fn coin() -> Coin {
+        toml::from_str(
+            r#"
+            strategy = 'level'
+            quantity = 1
+            [params]
+            lower_price = 90
+            upper_price = 110
+            levels = 3
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn candidates_should_cover_space() {
+        let space = SearchSpace {
+            profit_ratio: Some("0.01:0.03:0.01".parse().unwrap()),
+            quantity: Some("1:2".parse().unwrap()),
+            ..SearchSpace::default()
+        };
+        let grid = space.candidates(&coin(), Search::Grid);
+        assert_eq!(grid.len(), 6);
+        assert!(grid.iter().all(|p| p.double_throw_ratio == 0.0));
+        assert!((grid[4].profit_ratio - 0.03).abs() < 1e-9);
+        assert_eq!(grid[4].quantity, 1.0);
+
+        let search = Search::Random {
+            samples: 20,
+            seed: 7,
+        };
+        let random = space.candidates(&coin(), search);
+        assert_eq!(random.len(), 20);
+        assert!(random
+            .iter()
+            .all(|p| (0.01..=0.03).contains(&p.profit_ratio) && (1.0..=2.0).contains(&p.quantity)));
+        assert_eq!(random, space.candidates(&coin(), search));
+        assert!("0.03:0.01:0.01".parse::<Range>().is_err());
+    }
+
+    #[test]
+    fn trials_should_be_ranked_and_validated() {
+        let optimizer = Optimizer {
+            backtest: Backtest {
+                symbol: Symbol::Eth,
+                coin: coin(),
+                risk: RiskConfig::default(),
+                quote: 1000.0,
+                base: 0.0,
+                fee_rate: 0.0,
+            },
+            space: SearchSpace {
+                quantity: Some("1:3:1".parse().unwrap()),
+                ..SearchSpace::default()
+            },
+            search: Search::Grid,
+            rank: Rank::Pnl,
+            jobs: 2,
+            splits: 2,
+        };
+        let millis = Interval::Hour1.millis();
+        let bars: Vec<Kline> = (0..12)
+            .map(|h| Kline {
+                open_time: h * millis,
+                open: 105.0,
+                high: 111.0,
+                low: 89.0,
+                close: 105.0,
+                close_time: (h + 1) * millis - 1,
+                count: 1,
+            })
+            .collect();
+        let report = optimizer.run(&GridRegistry::default(), &bars).unwrap();
+        let quantities: Vec<f64> = report.trials.iter().map(|t| t.params.quantity).collect();
+        assert_eq!(quantities, vec![3.0, 2.0, 1.0]);
+        assert!(report.trials[0].report.pnl > report.trials[2].report.pnl);
+
+        assert_eq!(report.folds.len(), 2);
+        assert_eq!(report.folds[0].test_start, 4 * millis);
+        assert_eq!(report.folds[1].params.quantity, 3.0);
+        assert_eq!(report.folds[1].test.bars, 4);
+    }
+
+    #[test]
+    fn swept_ratios_should_change_results() {
+        let coin: Coin = toml::from_str(
+            r#"
+            buy_price = 100
+            sell_price = 101
+            profit_ratio = '1%'
+            double_throw_ratio = '1%'
+            quantity = 1
+            "#,
+        )
+        .unwrap();
+        let mut optimizer = Optimizer {
+            backtest: Backtest {
+                symbol: Symbol::Eth,
+                coin,
+                risk: RiskConfig::default(),
+                quote: 1000.0,
+                base: 0.0,
+                fee_rate: 0.0,
+            },
+            space: SearchSpace {
+                profit_ratio: Some("0.01:0.05:0.04".parse().unwrap()),
+                ..SearchSpace::default()
+            },
+            search: Search::Grid,
+            rank: Rank::Pnl,
+            jobs: 1,
+            splits: 0,
+        };
+        let millis = Interval::Hour1.millis();
+        let bars: Vec<Kline> = (0..24)
+            .map(|h| {
+                let close = if h % 2 == 0 { 99.0 } else { 102.0 };
+                Kline {
+                    open_time: h * millis,
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    close_time: (h + 1) * millis - 1,
+                    count: 1,
+                }
+            })
+            .collect();
+        let report = optimizer.run(&GridRegistry::default(), &bars).unwrap();
+        assert_eq!(report.trials.len(), 2);
+        assert_ne!(report.trials[0].report, report.trials[1].report);
+
+        // level 策略不使用比例
+        optimizer.backtest.coin = self::coin();
+        assert!(optimizer.run(&GridRegistry::default(), &bars).is_err());
+    }
+}
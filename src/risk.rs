@@ -0,0 +1,365 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::clock::Clock;
+use crate::event::{EventBus, GridEvent};
+use crate::{RiskConfig, Symbol, TradeSide};
+
+/// 保留的最近否决记录数量
+const MAX_VETOES: usize = 100;
+
+/// 风控拒绝下单的原因
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub enum VetoReason {
+    /// 买入后占用的资金(U)超过上限
+    MaxCapital { deployed: f64, limit: f64 },
+    /// 单个币种的持仓份数达到上限
+    MaxLots { lots: usize, limit: usize },
+    /// 当日已实现亏损(U)达到上限
+    MaxDailyLoss { loss: f64, limit: f64 },
+    /// 最近一小时的下单次数达到上限
+    MaxOrdersPerHour { orders: usize, limit: usize },
+}
+
+impl fmt::Display for VetoReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VetoReason::MaxCapital { deployed, limit } => {
+                write!(f, "占用资金{}U超过上限{}U", deployed, limit)
+            }
+            VetoReason::MaxLots { lots, limit } => write!(f, "持仓{}份达到上限{}份", lots, limit),
+            VetoReason::MaxDailyLoss { loss, limit } => {
+                write!(f, "当日亏损{}U达到上限{}U", loss, limit)
+            }
+            VetoReason::MaxOrdersPerHour { orders, limit } => {
+                write!(f, "一小时内下单{}次达到上限{}次", orders, limit)
+            }
+        }
+    }
+}
+
+/// 一次被否决的下单
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Veto {
+    pub symbol: Symbol,
+    pub side: TradeSide,
+    pub reason: VetoReason,
+    pub time: DateTime<Utc>,
+}
+
+/// 所有币种共享的风控，下单前检查，成交后记录
+pub struct RiskManager {
+    config: RiskConfig,
+    events: EventBus,
+    clock: Arc<dyn Clock>,
+    state: Mutex<RiskState>,
+}
+
+#[derive(Default)]
+struct RiskState {
+    /// 每个币种持仓占用的资金
+    deployed: HashMap<Symbol, f64>,
+    lots: HashMap<Symbol, usize>,
+    day: Option<NaiveDate>,
+    daily_pnl: f64,
+    orders: VecDeque<DateTime<Utc>>,
+    vetoes: VecDeque<Veto>,
+    /// 各币种、方向当前的否决原因，原因不变时不重复记录及通知
+    vetoing: HashMap<(Symbol, TradeSide), VetoReason>,
+}
+
+impl RiskManager {
+    pub fn new(config: RiskConfig, events: EventBus, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            config,
+            events,
+            clock,
+            state: Mutex::new(RiskState::default()),
+        }
+    }
+
+    pub fn config(&self) -> &RiskConfig {
+        &self.config
+    }
+
+    /// 下单前检查，`quote` 为买入所需的资金(U)，卖出减少风险，总是允许
+    pub fn check(&self, symbol: &Symbol, side: TradeSide, quote: f64) -> Result<(), Veto> {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        state.roll(now);
+        let key = (symbol.clone(), side);
+        let reason = match self.breach(&state, symbol, side, quote) {
+            None => {
+                state.vetoing.remove(&key);
+                return Ok(());
+            }
+            Some(reason) => reason,
+        };
+        let veto = Veto {
+            symbol: symbol.clone(),
+            side,
+            reason,
+            time: now,
+        };
+        // 限制持续期间每次行情都会检查，只在原因变化时记录
+        if state.vetoing.get(&key) != Some(&veto.reason) {
+            warn!("风控拒绝{}的{:?}订单: {}", symbol, side, veto.reason);
+            if state.vetoes.len() >= MAX_VETOES {
+                state.vetoes.pop_front();
+            }
+            state.vetoes.push_back(veto.clone());
+            state.vetoing.insert(key, veto.reason.clone());
+            self.events.publish(GridEvent::Vetoed(veto.clone()));
+        }
+        Err(veto)
+    }
+
+    fn breach(
+        &self,
+        state: &RiskState,
+        symbol: &Symbol,
+        side: TradeSide,
+        quote: f64,
+    ) -> Option<VetoReason> {
+        // 卖出(包括止损清仓)不受任何限制
+        if side == TradeSide::Sell {
+            return None;
+        }
+        let c = &self.config;
+        if let Some(limit) = c.max_orders_per_hour {
+            if state.orders.len() >= limit {
+                let orders = state.orders.len();
+                return Some(VetoReason::MaxOrdersPerHour { orders, limit });
+            }
+        }
+        if let Some(limit) = c.max_daily_loss {
+            if -state.daily_pnl >= limit {
+                let loss = -state.daily_pnl;
+                return Some(VetoReason::MaxDailyLoss { loss, limit });
+            }
+        }
+        if let Some(limit) = c.max_lots_per_coin {
+            let lots = state.lots.get(symbol).cloned().unwrap_or(0);
+            if lots >= limit {
+                return Some(VetoReason::MaxLots { lots, limit });
+            }
+        }
+        if let Some(limit) = c.max_capital {
+            let deployed = state.deployed.values().sum::<f64>() + quote;
+            if deployed > limit {
+                return Some(VetoReason::MaxCapital { deployed, limit });
+            }
+        }
+        None
+    }
+
+    /// 记录买入成交，`cost` 为花费的资金(U)
+    pub fn record_buy(&self, symbol: &Symbol, lots: usize, cost: f64) {
+        let mut state = self.state.lock().unwrap();
+        let now = self.clock.now();
+        state.roll(now);
+        state.orders.push_back(now);
+        *state.deployed.entry(symbol.clone()).or_default() += cost;
+        *state.lots.entry(symbol.clone()).or_default() += lots;
+    }
+
+    /// 记录卖出成交，`cost` 为卖出部分的买入成本，`pnl` 为台账中扣除手续费后的已实现盈亏
+    pub fn record_sell(&self, symbol: &Symbol, lots: usize, cost: f64, pnl: f64) {
+        let mut state = self.state.lock().unwrap();
+        let now = self.clock.now();
+        state.roll(now);
+        state.orders.push_back(now);
+        state.daily_pnl += pnl;
+        let deployed = state.deployed.entry(symbol.clone()).or_default();
+        *deployed = (*deployed - cost).max(0.0);
+        let held = state.lots.entry(symbol.clone()).or_default();
+        *held = held.saturating_sub(lots);
+    }
+
+    /// 只记录下单次数，用于不占用计价资产的成交
+    pub fn record_order(&self) {
+        let mut state = self.state.lock().unwrap();
+        let now = self.clock.now();
+        state.roll(now);
+        state.orders.push_back(now);
+    }
+
+    /// 网格停止或重建时释放该币种占用的资金及持仓份数，新网格从空仓开始
+    pub fn release(&self, symbol: &Symbol) {
+        let mut state = self.state.lock().unwrap();
+        state.deployed.remove(symbol);
+        state.lots.remove(symbol);
+        state.vetoing.retain(|(s, _), _| s != symbol);
+    }
+
+    /// 当日已实现盈亏(U)，已扣除手续费
+    pub fn daily_pnl(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        state.roll(self.clock.now());
+        state.daily_pnl
+    }
+
+    /// 最近被否决的下单，按时间先后排列
+    pub fn vetoes(&self) -> Vec<Veto> {
+        self.state.lock().unwrap().vetoes.iter().cloned().collect()
+    }
+}
+
+impl RiskState {
+    /// 清理一小时前的下单记录，跨天时重置当日盈亏
+    fn roll(&mut self, now: DateTime<Utc>) {
+        let hour_ago = now - Duration::hours(1);
+        while self.orders.front().is_some_and(|t| *t <= hour_ago) {
+            self.orders.pop_front();
+        }
+        let today = now.date_naive();
+        if self.day != Some(today) {
+            self.day = Some(today);
+            self.daily_pnl = 0.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use crate::clock::{ManualClock, SystemClock};
+
+    use super::*;
+
+    fn manager(config: RiskConfig) -> RiskManager {
+        RiskManager::new(config, EventBus::default(), Arc::new(SystemClock))
+    }
+
+    #[test]
+    fn capital_and_lots_should_be_limited() {
+        let risk = manager(RiskConfig {
+            max_capital: Some(100.0),
+            max_lots_per_coin: Some(2),
+            ..RiskConfig::default()
+        });
+        assert!(risk.check(&Symbol::Eth, TradeSide::Buy, 60.0).is_ok());
+        risk.record_buy(&Symbol::Eth, 1, 60.0);
+
+        let veto = risk.check(&Symbol::Btc, TradeSide::Buy, 60.0).unwrap_err();
+        assert_eq!(
+            veto.reason,
+            VetoReason::MaxCapital {
+                deployed: 120.0,
+                limit: 100.0
+            }
+        );
+        assert!(risk.check(&Symbol::Eth, TradeSide::Sell, 0.0).is_ok());
+
+        risk.record_buy(&Symbol::Eth, 1, 10.0);
+        let veto = risk.check(&Symbol::Eth, TradeSide::Buy, 10.0).unwrap_err();
+        assert_eq!(veto.reason, VetoReason::MaxLots { lots: 2, limit: 2 });
+
+        risk.record_sell(&Symbol::Eth, 2, 70.0, 10.0);
+        assert!(risk.check(&Symbol::Eth, TradeSide::Buy, 60.0).is_ok());
+        assert_eq!(risk.vetoes().len(), 2);
+    }
+
+    #[test]
+    fn daily_loss_and_order_rate_should_be_limited() {
+        let risk = manager(RiskConfig {
+            max_daily_loss: Some(5.0),
+            ..RiskConfig::default()
+        });
+        risk.record_buy(&Symbol::Eth, 1, 100.0);
+        risk.record_sell(&Symbol::Eth, 1, 100.0, -6.0);
+        let veto = risk.check(&Symbol::Btc, TradeSide::Buy, 1.0).unwrap_err();
+        assert_eq!(
+            veto.reason,
+            VetoReason::MaxDailyLoss {
+                loss: 6.0,
+                limit: 5.0
+            }
+        );
+
+        let risk = manager(RiskConfig {
+            max_orders_per_hour: Some(1),
+            ..RiskConfig::default()
+        });
+        risk.record_order();
+        assert!(risk.check(&Symbol::Eth, TradeSide::Buy, 1.0).is_err());
+        assert!(risk.state.lock().unwrap().deployed.is_empty());
+    }
+
+    #[test]
+    fn sells_should_pass_at_order_cap_and_vetoes_should_not_repeat() {
+        let events = EventBus::default();
+        let mut rx = events.subscribe();
+        let risk = RiskManager::new(
+            RiskConfig {
+                max_orders_per_hour: Some(1),
+                max_daily_loss: Some(1.0),
+                ..RiskConfig::default()
+            },
+            events,
+            Arc::new(SystemClock),
+        );
+        risk.record_buy(&Symbol::Eth, 1, 100.0);
+        risk.record_sell(&Symbol::Eth, 1, 100.0, -10.0);
+        assert!(risk.check(&Symbol::Eth, TradeSide::Sell, 0.0).is_ok());
+
+        for _ in 0..3 {
+            assert!(risk.check(&Symbol::Eth, TradeSide::Buy, 1.0).is_err());
+        }
+        assert_eq!(risk.vetoes().len(), 1);
+        assert!(matches!(rx.try_recv(), Ok(GridEvent::Vetoed(_))));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn released_symbol_should_free_its_limits() {
+        let risk = manager(RiskConfig {
+            max_capital: Some(100.0),
+            max_lots_per_coin: Some(1),
+            ..RiskConfig::default()
+        });
+        risk.record_buy(&Symbol::Eth, 1, 60.0);
+        risk.record_buy(&Symbol::Btc, 1, 30.0);
+        assert!(risk.check(&Symbol::Eth, TradeSide::Buy, 10.0).is_err());
+        assert!(risk.check(&Symbol::Bnb, TradeSide::Buy, 20.0).is_err());
+
+        risk.release(&Symbol::Eth);
+        assert!(risk.check(&Symbol::Eth, TradeSide::Buy, 10.0).is_ok());
+        assert!(risk.check(&Symbol::Bnb, TradeSide::Buy, 20.0).is_ok());
+        // 其他币种的占用不受影响
+        assert!(risk.check(&Symbol::Btc, TradeSide::Buy, 10.0).is_err());
+    }
+
+    #[test]
+    fn limits_should_reset_as_clock_advances() {
+        let clock = Arc::new(ManualClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 23, 30, 0).unwrap(),
+        ));
+        let risk = RiskManager::new(
+            RiskConfig {
+                max_orders_per_hour: Some(1),
+                max_daily_loss: Some(10.0),
+                ..RiskConfig::default()
+            },
+            EventBus::default(),
+            clock.clone(),
+        );
+        risk.record_buy(&Symbol::Eth, 1, 100.0);
+        risk.record_sell(&Symbol::Eth, 1, 100.0, -20.0);
+        assert!(risk.check(&Symbol::Eth, TradeSide::Buy, 10.0).is_err());
+
+        clock.advance(Duration::minutes(20).to_std().unwrap());
+        assert!(risk.check(&Symbol::Eth, TradeSide::Buy, 10.0).is_err());
+
+        // 一小时后下单次数清零，跨过零点后当日亏损清零
+        clock.advance(Duration::minutes(40).to_std().unwrap());
+        assert!(risk.check(&Symbol::Eth, TradeSide::Sell, 0.0).is_ok());
+        assert!(risk.check(&Symbol::Eth, TradeSide::Buy, 10.0).is_ok());
+    }
+}
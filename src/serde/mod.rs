@@ -25,6 +25,16 @@ where
                 })
             }
         }
+        /// 自身序列化的结果为数字
+        fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+        fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(v as f64)
+        }
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(v as f64)
+        }
     }
-    deserializer.deserialize_str(F64Visitor)
+    deserializer.deserialize_any(F64Visitor)
 }
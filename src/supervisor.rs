@@ -0,0 +1,623 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{error, info, span, warn};
+
+use crate::event::GridEvent;
+use crate::grid::{self, GridContext, GridRegistry};
+use crate::risk::RiskManager;
+use crate::trade::{DryRunTrade, DRY_RUN_JOURNAL};
+use crate::{Coin, CoinConfig, ServerConfig, Symbol, TgError};
+
+/// 检查配置文件是否修改的间隔
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 运行中的网格，通过 `tx` 下发新参数，发送 `None` 时在下一次行情前停止
+struct Running {
+    coin: Coin,
+    tx: watch::Sender<Option<Coin>>,
+    handle: JoinHandle<()>,
+}
+
+/// 运行中的网格与新配置的差异
+#[derive(Debug, PartialEq)]
+enum Change {
+    Start(Symbol, Coin),
+    /// 策略及其参数未变，在运行中的网格上更新
+    Update(Symbol, Coin),
+    /// 策略、策略参数、模拟下单或账户变化，停止后重新创建
+    Restart(Symbol, Coin),
+    Stop(Symbol),
+}
+
+/// 比较运行中的币种与新配置，先停止被移除的币种
+fn diff(running: &HashMap<Symbol, &Coin>, config: &CoinConfig) -> Vec<Change> {
+    let coins = config.coins();
+    let mut changes: Vec<Change> = running
+        .keys()
+        .filter(|s| coins.iter().all(|(symbol, _)| symbol != *s))
+        .map(|s| Change::Stop(s.clone()))
+        .collect();
+    for (symbol, coin) in coins {
+        let change = match running.get(&symbol) {
+            None => Change::Start(symbol, coin.clone()),
+            Some(old) if *old == coin => continue,
+            Some(old)
+                if old.strategy == coin.strategy
+                    && old.params == coin.params
+                    && old.dry_run == coin.dry_run
+                    && old.account == coin.account =>
+            {
+                Change::Update(symbol, coin.clone())
+            }
+            Some(_) => Change::Restart(symbol, coin.clone()),
+        };
+        changes.push(change);
+    }
+    changes
+}
+
+/// 同一账户下各币种共用的依赖
+pub(crate) struct Account {
+    /// `symbol` 在启动时替换
+    pub ctx: GridContext,
+    /// 账户的模拟下单开关
+    pub dry_run: bool,
+}
+
+/// 管理各币种的网格任务，配置变化时更新参数、启动新币种并停止被移除的币种
+pub(crate) struct Supervisor {
+    registry: Arc<GridRegistry>,
+    /// 按账户名索引，`None` 为 `[trade]`
+    accounts: HashMap<Option<String>, Account>,
+    /// 所有模拟下单币种共用的风控，首个模拟币种启动时创建
+    dry_run_risk: Option<Arc<RiskManager>>,
+    grids: HashMap<Symbol, Running>,
+}
+
+impl Supervisor {
+    pub fn new(registry: GridRegistry, accounts: HashMap<Option<String>, Account>) -> Self {
+        Self {
+            registry: Arc::new(registry),
+            accounts,
+            dry_run_risk: None,
+            grids: HashMap::new(),
+        }
+    }
+
+    /// 使运行中的网格与 `config` 一致，有币种使用未连接的账户时不做任何修改
+    pub async fn apply(&mut self, config: &CoinConfig) -> Result<()> {
+        for (symbol, coin) in config.coins() {
+            if !self.accounts.contains_key(&coin.account) {
+                return Err(TgError::ConfError(format!(
+                    "Account {} for {} is not connected, restart to use it",
+                    coin.account.as_deref().unwrap_or("trade"),
+                    symbol
+                ))
+                .into());
+            }
+        }
+        let running: HashMap<Symbol, &Coin> = self
+            .grids
+            .iter()
+            .map(|(s, r)| (s.clone(), &r.coin))
+            .collect();
+        let changes = diff(&running, config);
+        for change in changes {
+            match change {
+                Change::Start(symbol, coin) => self.start(symbol, &coin)?,
+                Change::Update(symbol, coin) => {
+                    info!("币种:{},更新网格参数", symbol);
+                    if let Some(running) = self.grids.get_mut(&symbol) {
+                        running.coin = coin.clone();
+                        let _ = running.tx.send(Some(coin));
+                    }
+                }
+                Change::Restart(symbol, coin) => {
+                    warn!("币种:{},策略、策略参数或账户已修改，重建网格", symbol);
+                    // 等待原网格执行完当前一轮，避免两个网格同时交易
+                    if let Some(handle) = self.stop(&symbol) {
+                        if let Err(e) = handle.await {
+                            error!("Processor error: {}", e);
+                        }
+                    }
+                    self.start(symbol, &coin)?;
+                }
+                Change::Stop(symbol) => {
+                    info!("币种:{},已从配置中移除，停止网格", symbol);
+                    self.stop(&symbol);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grids.is_empty()
+    }
+
+    /// 等待全部网格任务结束
+    pub async fn join(self) {
+        for (_, running) in self.grids {
+            if let Err(e) = running.handle.await {
+                error!("Processor error: {}", e);
+            }
+        }
+    }
+
+    /// 通知网格在下一次行情前停止，返回其任务
+    fn stop(&mut self, symbol: &Symbol) -> Option<JoinHandle<()>> {
+        let running = self.grids.remove(symbol)?;
+        let _ = running.tx.send(None);
+        Some(running.handle)
+    }
+
+    fn start(&mut self, symbol: Symbol, coin: &Coin) -> Result<()> {
+        let account = self.accounts.get(&coin.account).ok_or_else(|| {
+            TgError::ConfError(format!(
+                "Unknown account for {}: {}",
+                symbol,
+                coin.account.as_deref().unwrap_or_default()
+            ))
+        })?;
+        let mut ctx = GridContext {
+            symbol: symbol.clone(),
+            ..account.ctx.clone()
+        };
+        if coin.is_dry_run(account.dry_run) {
+            warn!("币种:{},模拟下单模式，订单不会发送到交易所", symbol);
+            // 模拟成交只计入自己的余额及风控，不占用实盘币种的额度
+            let trade = Arc::new(DryRunTrade::new(
+                ctx.trade.clone(),
+                ctx.market.clone(),
+                ctx.store.clone(),
+                ctx.clock.clone(),
+            ));
+            ctx.balance = Arc::new(ctx.balance.for_trade(trade.clone()));
+            // 模拟的止损等状态不能影响关闭模拟后的实盘网格
+            ctx.store = ctx.store.child(DRY_RUN_JOURNAL);
+            ctx.risk = self
+                .dry_run_risk
+                .get_or_insert_with(|| {
+                    Arc::new(RiskManager::new(
+                        ctx.risk.config().clone(),
+                        ctx.events.clone(),
+                        ctx.clock.clone(),
+                    ))
+                })
+                .clone();
+            ctx.trade = trade;
+        }
+        let market = ctx.market.clone();
+        let clock = ctx.clock.clone();
+        let events = ctx.events.clone();
+        let risk = ctx.risk.clone();
+        let registry = self.registry.clone();
+        let mut grid = grid::factory(&registry, ctx.clone(), coin)?;
+        let (tx, mut rx) = watch::channel(Some(coin.clone()));
+        rx.mark_unchanged();
+
+        let root = span!(tracing::Level::INFO, "Grid");
+        let _enter = root.enter();
+
+        let mut current = coin.clone();
+        let task_symbol = symbol.clone();
+        let handle = tokio::spawn(async move {
+            let symbol = task_symbol;
+            let failed = |message: String| {
+                events.publish(GridEvent::Error {
+                    symbol: symbol.clone(),
+                    message,
+                })
+            };
+            loop {
+                // 发送端被丢弃时同样停止
+                let changed = match rx.has_changed() {
+                    Ok(changed) => changed,
+                    Err(_) => {
+                        info!("币种:{},网格已停止", symbol);
+                        break;
+                    }
+                };
+                if changed {
+                    let next = rx.borrow_and_update().clone();
+                    let next = match next {
+                        Some(next) => next,
+                        None => {
+                            info!("币种:{},网格已停止", symbol);
+                            break;
+                        }
+                    };
+                    if let Err(e) = grid.update(&current, &next) {
+                        // 无法在运行中更新时按新参数重建，保证运行的参数与配置一致
+                        warn!("币种:{},更新网格参数失败: {}，重建网格", symbol, e);
+                        match grid::factory(&registry, ctx.clone(), &next) {
+                            Ok(rebuilt) => {
+                                grid = rebuilt;
+                                risk.release(&symbol);
+                            }
+                            Err(e) => {
+                                error!("币种:{},重建网格失败: {}", symbol, e);
+                                failed(format!("重建网格失败: {}", e));
+                                // 保留未生效的参数，下一轮重试
+                                rx.mark_changed();
+                                clock.sleep(Duration::from_secs(1)).await;
+                                continue;
+                            }
+                        }
+                    }
+                    current = next;
+                }
+                match market.ticker_price(&symbol).await {
+                    Ok(price) => {
+                        events.publish(GridEvent::PriceTick {
+                            symbol: symbol.clone(),
+                            price,
+                        });
+                        if let Err(e) = grid.execute(price).await {
+                            error!("Grid execute error: {}", e);
+                            failed(format!("网格执行失败: {}", e));
+                        }
+                    }
+                    Err(e) => {
+                        error!("Get ticker price error: {}.", e);
+                        failed(format!("获取价格失败: {}", e));
+                        clock.sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+            // 持仓随网格一起丢弃，释放其占用的风控额度
+            risk.release(&symbol);
+        });
+        self.grids.insert(
+            symbol,
+            Running {
+                coin: coin.clone(),
+                tx,
+                handle,
+            },
+        );
+        Ok(())
+    }
+}
+
+/// 监听配置文件，文件修改或收到 SIGHUP 时重新加载并应用币种配置
+pub(crate) async fn watch(path: PathBuf, mut current: ServerConfig, mut supervisor: Supervisor) {
+    let mut modified = modified_time(&path);
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    let mut hangup = hangup();
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let m = modified_time(&path);
+                if m == modified {
+                    continue;
+                }
+                modified = m;
+                info!("配置文件已修改: {:?}", path);
+            }
+            _ = hangup.recv() => info!("收到 SIGHUP，重新加载配置: {:?}", path),
+        }
+        match reload(&path, &current, &supervisor.registry) {
+            Ok(config) => match supervisor.apply(&config.coin).await {
+                Ok(()) => current = config,
+                Err(e) => error!("应用配置失败: {}", e),
+            },
+            Err(e) => error!("重新加载配置失败，继续使用原配置: {}", e),
+        }
+    }
+}
+
+/// 读取并检查配置，`coin` 以外的修改需要重启才能生效
+fn reload(path: &Path, current: &ServerConfig, registry: &GridRegistry) -> Result<ServerConfig> {
+    let config = ServerConfig::load(path)?;
+    config.check(registry)?;
+    if config.trade != current.trade
+        || config.accounts != current.accounts
+        || config.risk != current.risk
+        || config.log != current.log
+        || config.state != current.state
+        || config.notify != current.notify
+    {
+        warn!("仅币种配置支持热更新，其他配置的修改需要重启后生效");
+    }
+    Ok(config)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(unix)]
+fn hangup() -> Hangup {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::hangup()) {
+        Ok(signal) => Hangup(Some(signal)),
+        Err(e) => {
+            warn!("无法监听 SIGHUP: {}", e);
+            Hangup(None)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn hangup() -> Hangup {
+    Hangup
+}
+
+#[cfg(unix)]
+struct Hangup(Option<tokio::signal::unix::Signal>);
+
+#[cfg(not(unix))]
+struct Hangup;
+
+impl Hangup {
+    /// 等待下一次 SIGHUP，不支持时永远等待
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = self.0.as_mut() {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::clock::{Clock, ManualClock, SystemClock};
+    use crate::event::EventBus;
+    use crate::grid::{StateStore, LEVEL_STRATEGY};
+    use crate::trade::fake::{bar, FakeMarket, FakeTrade};
+    use crate::trade::BalanceGuard;
+    use crate::{RiskConfig, TradeSide};
+
+    use super::*;
+
+    fn config(s: &str) -> CoinConfig {
+        let config = ServerConfig::from_str(&format!(
+            r#"
+            [trade]
+            key = 'x'
+            secret = 'x'
+            url = 'https://testnet.binance.vision/api/v3/'
+            [log]
+            enable_log_file = false
+            log_level = 'info'
+            path = '/tmp/tgs-log'
+            rotation = 'Daily'
+            {}
+            "#,
+            s
+        ))
+        .unwrap();
+        config.coin
+    }
+
+    #[test]
+    fn changes_should_be_diffed() {
+        let old = config(
+            r#"
+            [coin.eth]
+            buy_price = 3000
+            sell_price = 3200
+            quantity = 0.01
+            [coin.btc]
+            strategy = 'level'
+            quantity = 0.001
+            params = { lower_price = 20000, upper_price = 30000, levels = 5 }
+            "#,
+        );
+        let new = config(
+            r#"
+            [coin.eth]
+            buy_price = 3000
+            sell_price = 3200
+            quantity = 0.02
+            [coin.bnb]
+            buy_price = 300
+            sell_price = 320
+            quantity = 1
+            "#,
+        );
+        let running: HashMap<Symbol, &Coin> = old.coins().into_iter().collect();
+        let changes = diff(&running, &new);
+        assert_eq!(
+            changes,
+            vec![
+                Change::Stop(Symbol::Btc),
+                Change::Update(Symbol::Eth, new.eth.clone().unwrap()),
+                Change::Start(Symbol::Bnb, new.bnb.clone().unwrap()),
+            ]
+        );
+
+        let running: HashMap<Symbol, &Coin> = new.coins().into_iter().collect();
+        assert!(diff(&running, &new).is_empty());
+
+        let mut level = new.clone();
+        level.eth.as_mut().unwrap().strategy = LEVEL_STRATEGY.into();
+        assert!(matches!(
+            diff(&running, &level)[0],
+            Change::Restart(Symbol::Eth, _)
+        ));
+
+        let mut dry_run = new.clone();
+        dry_run.eth.as_mut().unwrap().dry_run = Some(true);
+        assert!(matches!(
+            diff(&running, &dry_run)[0],
+            Change::Restart(Symbol::Eth, _)
+        ));
+
+        let mut account = new.clone();
+        account.eth.as_mut().unwrap().account = Some("sub1".into());
+        assert!(matches!(
+            diff(&running, &account)[0],
+            Change::Restart(Symbol::Eth, _)
+        ));
+    }
+
+    /// 使用模拟行情及交易的 `[trade]` 账户
+    fn account(
+        market: Arc<FakeMarket>,
+        risk: RiskConfig,
+        clock: Arc<dyn Clock>,
+        dir: &Path,
+    ) -> Account {
+        let trade = Arc::new(FakeTrade::new(market.clone()).with_balance("USDT", 1000.0));
+        let events = EventBus::default();
+        let ctx = GridContext {
+            symbol: Symbol::Eth,
+            market,
+            trade: trade.clone(),
+            events: events.clone(),
+            store: StateStore::new(dir),
+            risk: Arc::new(RiskManager::new(risk, events, clock.clone())),
+            balance: Arc::new(BalanceGuard::new(trade, BTreeMap::new(), clock.clone())),
+            clock,
+        };
+        Account {
+            ctx,
+            dry_run: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_account_should_reject_whole_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let market = Arc::new(FakeMarket::default());
+        let trade = account(
+            market,
+            RiskConfig::default(),
+            Arc::new(SystemClock),
+            dir.path(),
+        );
+        let accounts = HashMap::from([(None, trade)]);
+        let mut supervisor = Supervisor::new(GridRegistry::default(), accounts);
+
+        // eth 使用 [trade]，bnb 的账户未连接，eth 也不启动
+        let coins = config(
+            r#"
+            [accounts.sub1]
+            key = 'x'
+            secret = 'x'
+            url = 'https://testnet.binance.vision/api/v3/'
+            [coin.eth]
+            buy_price = 3000
+            sell_price = 3200
+            quantity = 0.01
+            [coin.bnb]
+            buy_price = 300
+            sell_price = 320
+            quantity = 1
+            account = 'sub1'
+            "#,
+        );
+        let err = supervisor.apply(&coins).await.unwrap_err();
+        assert!(err.to_string().contains("sub1"), "{}", err);
+        assert!(supervisor.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stopped_grid_should_release_risk() {
+        let dir = tempfile::tempdir().unwrap();
+        let market =
+            Arc::new(FakeMarket::new([95.0]).with_k_lines(vec![bar(100.0, 105.0, 100.0, 102.0)]));
+        let clock = Arc::new(ManualClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ));
+        let trade = account(
+            market,
+            RiskConfig {
+                max_lots_per_coin: Some(1),
+                ..RiskConfig::default()
+            },
+            clock,
+            dir.path(),
+        );
+        let risk = trade.ctx.risk.clone();
+        let accounts = HashMap::from([(None, trade)]);
+        let mut supervisor = Supervisor::new(GridRegistry::default(), accounts);
+        let coins = config(
+            r#"
+            [coin.eth]
+            buy_price = 100
+            sell_price = 110
+            quantity = 1
+            "#,
+        );
+        supervisor.apply(&coins).await.unwrap();
+        let vetoed = || risk.check(&Symbol::Eth, TradeSide::Buy, 1.0).is_err();
+        let wait = |expected: bool| async move {
+            while vetoed() != expected {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        // 买入一份后达到持仓上限
+        tokio::time::timeout(Duration::from_secs(5), wait(true))
+            .await
+            .unwrap();
+
+        supervisor.apply(&config("[coin]")).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), wait(false))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dry_run_coins_should_share_risk() {
+        let dir = tempfile::tempdir().unwrap();
+        let market =
+            Arc::new(FakeMarket::new([95.0]).with_k_lines(vec![bar(100.0, 105.0, 100.0, 102.0)]));
+        let clock = Arc::new(ManualClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ));
+        let trade = account(
+            market,
+            RiskConfig {
+                max_lots_per_coin: Some(1),
+                ..RiskConfig::default()
+            },
+            clock,
+            dir.path(),
+        );
+        let risk = trade.ctx.risk.clone();
+        let accounts = HashMap::from([(None, trade)]);
+        let mut supervisor = Supervisor::new(GridRegistry::default(), accounts);
+        let coins = config(
+            r#"
+            [coin.eth]
+            buy_price = 100
+            sell_price = 110
+            quantity = 1
+            dry_run = true
+            [coin.bnb]
+            buy_price = 100
+            sell_price = 110
+            quantity = 1
+            dry_run = true
+            "#,
+        );
+        supervisor.apply(&coins).await.unwrap();
+        let dry_run = supervisor.dry_run_risk.clone().unwrap();
+        let vetoed = |symbol: Symbol| dry_run.check(&symbol, TradeSide::Buy, 1.0).is_err();
+        // 两个币种的模拟买入都记在同一个风控上
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !(vetoed(Symbol::Eth) && vetoed(Symbol::Bnb)) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(risk.check(&Symbol::Eth, TradeSide::Buy, 1.0).is_ok());
+        supervisor.apply(&config("[coin]")).await.unwrap();
+    }
+}
@@ -1,22 +1,460 @@
-use std::env;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use anyhow::Result;
-use tokio::fs;
+use chrono::{NaiveDate, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use tokio::runtime::Runtime;
 
-use trend_grid::{init_log, start_server_with_config, ServerConfig, TgError};
+use trend_grid::backtest::{Backtest, BacktestReport};
+use trend_grid::clock::{Clock, SystemClock};
+use trend_grid::event::EventBus;
+use trend_grid::grid::{GridRegistry, Halt, StateStore, HALT};
+use trend_grid::history::{self, KlineStore};
+use trend_grid::optimize::{Optimizer, Range, Rank, Search, SearchSpace};
+use trend_grid::trade::{self, Interval, KlineQuery, DRY_RUN_JOURNAL};
+use trend_grid::{init_log, start_server_with_reload, ServerConfig, Symbol, TgError};
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let config = match env::var("TGS_CONFIG") {
-        Ok(path) => fs::read_to_string(&path)
-            .await
-            .map_err(|e| TgError::IoError(e)),
-        Err(_) => Err(TgError::ConfNotFound),
-    }?;
-    let config = ServerConfig::from_str(&config)?;
-    init_log(&config.log);
+/// 趋势网格服务
+#[derive(Parser)]
+#[command(name = "tgs", version)]
+struct Cli {
+    /// 配置文件路径
+    #[arg(short, long, env = "TGS_CONFIG", global = true)]
+    config: Option<PathBuf>,
+    /// 使用的账户，对应 `[accounts.xxx]`，未设置时使用 `[trade]`
+    #[arg(long, global = true)]
+    account: Option<String>,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = Format::Human, global = true)]
+    format: Format,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Human,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 运行网格(默认)
+    Run,
+    /// 检查配置文件
+    CheckConfig,
+    /// 账户余额
+    Balance,
+    /// 最新价格
+    Price { symbol: Symbol },
+    /// 当前挂单
+    Orders { symbol: Symbol },
+    /// 撤销挂单，不指定订单号时撤销该币种的全部挂单
+    Cancel {
+        symbol: Symbol,
+        order: Option<usize>,
+    },
+    /// 下载历史 k 线到本地，已下载的部分不会重复下载
+    Download {
+        symbol: Symbol,
+        /// k 线周期
+        #[arg(long, default_value = "1h")]
+        interval: Interval,
+        /// 开始日期(UTC)，如 2023-01-01
+        #[arg(long)]
+        start: NaiveDate,
+        /// 结束日期(UTC，不含)，默认为当前时间
+        #[arg(long)]
+        end: Option<NaiveDate>,
+        /// 保存目录
+        #[arg(long, default_value = "data")]
+        dir: PathBuf,
+    },
+    /// 用最近的 k 线回测配置中的币种，不指定币种时回测全部
+    Backtest {
+        symbol: Option<Symbol>,
+        /// k 线周期
+        #[arg(long, default_value = "1h")]
+        interval: Interval,
+        /// k 线数量
+        #[arg(long, default_value_t = 1000)]
+        limit: usize,
+        /// 使用 download 保存的 k 线目录，不指定时从交易所获取
+        #[arg(long)]
+        data: Option<PathBuf>,
+        /// 初始计价资产(U)
+        #[arg(long, default_value_t = 1000.0)]
+        quote: f64,
+        /// 初始基础资产
+        #[arg(long, default_value_t = 0.0)]
+        base: f64,
+        /// 手续费率
+        #[arg(long, default_value_t = 0.001)]
+        fee_rate: f64,
+    },
+    /// 在历史 k 线上搜索币种的 profit_ratio、double_throw_ratio 及 quantity
+    Optimize {
+        symbol: Symbol,
+        /// 止盈比例范围，如 0.01:0.05:0.005
+        #[arg(long)]
+        profit_ratio: Option<Range>,
+        /// 补仓比例范围
+        #[arg(long)]
+        double_throw_ratio: Option<Range>,
+        /// 每次交易数量范围
+        #[arg(long)]
+        quantity: Option<Range>,
+        /// 随机搜索的组数，不指定时搜索全部组合
+        #[arg(long)]
+        random: Option<usize>,
+        /// 随机搜索的种子
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// 排序依据: pnl/drawdown/sharpe
+        #[arg(long, default_value = "pnl")]
+        rank: Rank,
+        /// 滚动验证的次数，0为不验证
+        #[arg(long, default_value_t = 0)]
+        splits: usize,
+        /// 并行回测的线程数，默认为 CPU 数量
+        #[arg(long)]
+        jobs: Option<usize>,
+        /// 输出前几名
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// k 线周期
+        #[arg(long, default_value = "1h")]
+        interval: Interval,
+        /// k 线数量
+        #[arg(long, default_value_t = 1000)]
+        limit: usize,
+        /// 使用 download 保存的 k 线目录，不指定时从交易所获取
+        #[arg(long)]
+        data: Option<PathBuf>,
+        /// 初始计价资产(U)
+        #[arg(long, default_value_t = 1000.0)]
+        quote: f64,
+        /// 初始基础资产
+        #[arg(long, default_value_t = 0.0)]
+        base: f64,
+        /// 手续费率
+        #[arg(long, default_value_t = 0.001)]
+        fee_rate: f64,
+    },
+    /// 各币种的价格、持仓及停止状态
+    Report,
+}
+
+#[derive(Serialize)]
+struct CoinReport {
+    symbol: Symbol,
+    strategy: String,
+    price: f64,
+    change_percent: f64,
+    /// 可用的基础资产
+    free: f64,
+    /// 可用基础资产的价值(U)
+    value: f64,
+    halt: Option<Halt>,
+}
 
-    start_server_with_config(&config).await?;
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = load_config(cli.config.as_ref())?;
+    let format = cli.format;
+    let account = cli.account.as_deref();
+    let rt = Runtime::new()?;
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => {
+            init_log(&config.log);
+            let path = cli.config.ok_or(TgError::ConfNotFound)?;
+            rt.block_on(start_server_with_reload(
+                path,
+                GridRegistry::default(),
+                EventBus::default(),
+            ))?;
+        }
+        Command::CheckConfig => {
+            let issues = config.validate();
+            output(format, &issues, |issues| {
+                for issue in issues {
+                    println!("{}", issue);
+                }
+                if issues.is_empty() {
+                    println!("配置正确");
+                }
+            })?;
+            if !issues.is_empty() {
+                return Err(
+                    TgError::ConfError(format!("{} problem(s) found", issues.len())).into(),
+                );
+            }
+        }
+        Command::Balance => {
+            let (_, trade) = trade::factory(config.account(account)?, clock.clone())?;
+            let mut account = rt.block_on(trade.account())?;
+            account.balances.retain(|b| b.free > 0.0 || b.locked > 0.0);
+            output(format, &account.balances, |balances| {
+                for b in balances {
+                    println!("{}\t可用: {}\t冻结: {}", b.asset, b.free, b.locked);
+                }
+            })?;
+        }
+        Command::Price { symbol } => {
+            let (market, _) = trade::factory(config.account(account)?, clock.clone())?;
+            let price = rt.block_on(market.ticker_price(&symbol))?;
+            output(format, &price, |price| println!("{}\t{}", symbol, price))?;
+        }
+        Command::Orders { symbol } => {
+            let (_, trade) = trade::factory(config.account(account)?, clock.clone())?;
+            let orders = rt.block_on(trade.open_orders(&symbol))?;
+            output(format, &orders, |orders| {
+                for o in orders {
+                    println!(
+                        "{}\t{:?}\t{:?}\t价格: {}\t数量: {}\t已成交: {}",
+                        o.order_id, o.side, o.order_type, o.price, o.quantity, o.executed_quantity
+                    );
+                }
+            })?;
+        }
+        Command::Cancel { symbol, order } => {
+            let (_, trade) = trade::factory(config.account(account)?, clock.clone())?;
+            rt.block_on(trade.cancel_order(&symbol, order))?;
+            output(format, &order, |order| match order {
+                Some(id) => println!("已撤销订单{}", id),
+                None => println!("已撤销{}的全部挂单", symbol),
+            })?;
+        }
+        Command::Download {
+            symbol,
+            interval,
+            start,
+            end,
+            dir,
+        } => {
+            let (market, _) = trade::factory(config.account(account)?, clock.clone())?;
+            let store = KlineStore::new(dir);
+            let millis = |date: NaiveDate| {
+                date.and_time(Default::default())
+                    .and_utc()
+                    .timestamp_millis()
+            };
+            let end = end.map_or_else(|| Utc::now().timestamp_millis(), millis);
+            let n = rt.block_on(history::download(
+                market.as_ref(),
+                &store,
+                &symbol,
+                interval,
+                millis(start),
+                end,
+            ))?;
+            let path = store.path(&symbol, interval);
+            output(format, &n, |n| {
+                println!("新增{}根k线: {}", n, path.display())
+            })?;
+        }
+        Command::Backtest {
+            symbol,
+            interval,
+            limit,
+            data,
+            quote,
+            base,
+            fee_rate,
+        } => {
+            let (market, _) = trade::factory(config.account(account)?, clock.clone())?;
+            let registry = GridRegistry::default();
+            let mut reports: Vec<BacktestReport> = Vec::new();
+            for (s, coin) in config.coin.coins() {
+                if symbol.as_ref().is_some_and(|symbol| *symbol != s) {
+                    continue;
+                }
+                let query = KlineQuery::latest(interval, limit);
+                let bars = match data.as_ref() {
+                    Some(dir) => KlineStore::new(dir).query(&s, &query)?,
+                    None => rt.block_on(market.k_lines(&s, &query))?,
+                };
+                let backtest = Backtest {
+                    symbol: s,
+                    coin: coin.clone(),
+                    risk: config.risk.clone(),
+                    quote,
+                    base,
+                    fee_rate,
+                };
+                reports.push(backtest.run(&registry, &bars)?);
+            }
+            output(format, &reports, |reports| {
+                for r in reports {
+                    println!(
+                        "{}\tk线: {}\t买入: {}\t卖出: {}\t手续费: {:.4}\t盈亏: {:.4}U({:.2}%)\t最大回撤: {:.2}%\t夏普: {:.2}",
+                        r.symbol,
+                        r.bars,
+                        r.buys,
+                        r.sells,
+                        r.fees,
+                        r.pnl,
+                        r.return_ratio * 100.0,
+                        r.max_drawdown * 100.0,
+                        r.sharpe
+                    );
+                }
+            })?;
+        }
+        Command::Optimize {
+            symbol,
+            profit_ratio,
+            double_throw_ratio,
+            quantity,
+            random,
+            seed,
+            rank,
+            splits,
+            jobs,
+            top,
+            interval,
+            limit,
+            data,
+            quote,
+            base,
+            fee_rate,
+        } => {
+            let coin = config
+                .coin
+                .coins()
+                .into_iter()
+                .find(|(s, _)| *s == symbol)
+                .map(|(_, coin)| coin.clone())
+                .ok_or_else(|| TgError::ConfError(format!("{} is not configured", symbol)))?;
+            let query = KlineQuery::latest(interval, limit);
+            let bars = match data.as_ref() {
+                Some(dir) => KlineStore::new(dir).query(&symbol, &query)?,
+                None => {
+                    let (market, _) = trade::factory(config.account(account)?, clock.clone())?;
+                    rt.block_on(market.k_lines(&symbol, &query))?
+                }
+            };
+            let optimizer = Optimizer {
+                backtest: Backtest {
+                    symbol,
+                    coin,
+                    risk: config.risk.clone(),
+                    quote,
+                    base,
+                    fee_rate,
+                },
+                space: SearchSpace {
+                    profit_ratio,
+                    double_throw_ratio,
+                    quantity,
+                },
+                search: match random {
+                    Some(samples) => Search::Random { samples, seed },
+                    None => Search::Grid,
+                },
+                rank,
+                jobs: jobs
+                    .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())),
+                splits,
+            };
+            let mut report = optimizer.run(&GridRegistry::default(), &bars)?;
+            report.trials.truncate(top);
+            output(format, &report, |report| {
+                for (i, t) in report.trials.iter().enumerate() {
+                    println!(
+                        "{}\t止盈: {:.4}\t补仓: {:.4}\t数量: {}\t盈亏: {:.4}U({:.2}%)\t最大回撤: {:.2}%\t夏普: {:.2}",
+                        i + 1,
+                        t.params.profit_ratio,
+                        t.params.double_throw_ratio,
+                        t.params.quantity,
+                        t.report.pnl,
+                        t.report.return_ratio * 100.0,
+                        t.report.max_drawdown * 100.0,
+                        t.report.sharpe
+                    );
+                }
+                for f in report.folds.iter() {
+                    println!(
+                        "验证 {}\t止盈: {:.4}\t补仓: {:.4}\t数量: {}\t训练盈亏: {:.4}U\t测试盈亏: {:.4}U\t测试回撤: {:.2}%",
+                        f.test_start,
+                        f.params.profit_ratio,
+                        f.params.double_throw_ratio,
+                        f.params.quantity,
+                        f.train.pnl,
+                        f.test.pnl,
+                        f.test.max_drawdown * 100.0
+                    );
+                }
+            })?;
+        }
+        Command::Report => {
+            let store = StateStore::new(&config.state.path);
+            // 按币种所在账户查询，同一账户只查询一次余额
+            let mut accounts = HashMap::new();
+            let mut reports = Vec::new();
+            for (symbol, coin) in config.coin.coins() {
+                if !accounts.contains_key(&coin.account) {
+                    let (market, trade) =
+                        trade::factory(config.account(coin.account.as_deref())?, clock.clone())?;
+                    let account = rt.block_on(trade.account())?;
+                    accounts.insert(coin.account.clone(), (market, account));
+                }
+                let (market, account) = &accounts[&coin.account];
+                let ticker = rt.block_on(market.ticker_24hr(&symbol))?;
+                let free = account
+                    .balances
+                    .iter()
+                    .find(|b| b.asset == symbol.base_asset())
+                    .map_or(0.0, |b| b.free);
+                // 模拟下单的网格状态保存在单独的目录
+                let dry_run = coin.is_dry_run(config.account(coin.account.as_deref())?.dry_run);
+                let halt = match dry_run {
+                    true => store.child(DRY_RUN_JOURNAL).load(&symbol, HALT)?,
+                    false => store.load(&symbol, HALT)?,
+                };
+                reports.push(CoinReport {
+                    halt,
+                    symbol,
+                    strategy: coin.strategy.clone(),
+                    price: ticker.last_price,
+                    change_percent: ticker.price_change_percent,
+                    free,
+                    value: free * ticker.last_price,
+                });
+            }
+            output(format, &reports, |reports| {
+                for r in reports {
+                    let state = match r.halt.as_ref() {
+                        Some(halt) => format!("已停止: {:?}", halt.reason),
+                        None => "运行中".to_string(),
+                    };
+                    println!(
+                        "{}\t{}\t价格: {}({:+}%)\t持有: {}({:.2}U)\t{}",
+                        r.symbol, r.strategy, r.price, r.change_percent, r.free, r.value, state
+                    );
+                }
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+fn load_config(path: Option<&PathBuf>) -> Result<ServerConfig> {
+    let path = path.ok_or(TgError::ConfNotFound)?;
+    Ok(ServerConfig::load(path)?)
+}
 
+/// 按 `format` 输出 JSON 或调用 `human` 输出文本
+fn output<T: Serialize>(format: Format, value: &T, human: impl FnOnce(&T)) -> Result<()> {
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        Format::Human => human(value),
+    }
     Ok(())
 }
@@ -0,0 +1,160 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::clock::{self, Clock};
+use crate::trade::TradeService;
+use crate::Symbol;
+
+/// 余额缓存的有效期，超过后重新查询以发现手工操作
+const REFRESH: Duration = Duration::from_secs(60);
+
+/// 查询时间及各资产的可用数量
+type Balances = (DateTime<Utc>, HashMap<String, f64>);
+
+/// 下单前检查账户余额，`reserve` 中的数量永远不会被使用
+pub struct BalanceGuard {
+    trade: Arc<dyn TradeService>,
+    reserve: BTreeMap<String, f64>,
+    clock: Arc<dyn Clock>,
+    cache: Mutex<Option<Balances>>,
+}
+
+impl BalanceGuard {
+    pub fn new(
+        trade: Arc<dyn TradeService>,
+        reserve: BTreeMap<String, f64>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            trade,
+            reserve,
+            clock,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// 保留数量及时钟相同、余额从 `trade` 查询的检查
+    pub fn for_trade(&self, trade: Arc<dyn TradeService>) -> Self {
+        Self::new(trade, self.reserve.clone(), self.clock.clone())
+    }
+
+    /// 扣除保留数量后的可用余额
+    pub async fn available(&self, asset: &str) -> Result<f64> {
+        let mut cache = self.cache.lock().await;
+        let stale = match cache.as_ref() {
+            Some((at, _)) => clock::elapsed(self.clock.as_ref(), *at, REFRESH),
+            None => true,
+        };
+        if stale {
+            let account = self.trade.account().await?;
+            let free = account
+                .balances
+                .into_iter()
+                .map(|b| (b.asset, b.free))
+                .collect();
+            *cache = Some((self.clock.now(), free));
+        }
+        let free = cache
+            .as_ref()
+            .and_then(|(_, free)| free.get(asset).cloned())
+            .unwrap_or(0.0);
+        let reserved = self.reserve.get(asset).cloned().unwrap_or(0.0);
+        Ok((free - reserved).max(0.0))
+    }
+
+    /// 是否有足够的计价资产买入，`quote` 为所需资金
+    pub async fn can_buy(&self, symbol: &Symbol, quote: f64) -> bool {
+        self.enough(symbol, symbol.quote_asset(), quote).await
+    }
+
+    /// 是否有足够的基础资产卖出
+    pub async fn can_sell(&self, symbol: &Symbol, quantity: f64) -> bool {
+        self.enough(symbol, symbol.base_asset(), quantity).await
+    }
+
+    async fn enough(&self, symbol: &Symbol, asset: &str, amount: f64) -> bool {
+        match self.available(asset).await {
+            Ok(available) if available >= amount => true,
+            Ok(available) => {
+                warn!(
+                    "币种:{},{}可用余额{}不足{}，跳过下单",
+                    symbol, asset, available, amount
+                );
+                false
+            }
+            Err(e) => {
+                warn!("币种:{},查询余额失败: {}", symbol, e);
+                false
+            }
+        }
+    }
+
+    /// 成交后调用，下一次检查时重新查询余额
+    pub async fn invalidate(&self) {
+        *self.cache.lock().await = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use crate::clock::ManualClock;
+    use crate::trade::fake::{FakeMarket, FakeTrade};
+
+    use super::*;
+
+    fn guard(reserve: &[(&str, f64)]) -> (Arc<FakeTrade>, Arc<ManualClock>, BalanceGuard) {
+        let clock = Arc::new(ManualClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ));
+        let market = Arc::new(FakeMarket::new([]));
+        let trade = Arc::new(
+            FakeTrade::new(market)
+                .with_balance("USDT", 100.0)
+                .with_balance("ETH", 1.0),
+        );
+        let reserve = reserve.iter().map(|(a, v)| (a.to_string(), *v)).collect();
+        let guard = BalanceGuard::new(trade.clone(), reserve, clock.clone());
+        (trade, clock, guard)
+    }
+
+    #[tokio::test]
+    async fn reserve_should_be_deducted() {
+        let (_, _, guard) = guard(&[("USDT", 30.0), ("ETH", 2.0)]);
+        assert_eq!(guard.available("USDT").await.unwrap(), 70.0);
+        // 保留数量超过余额时可用为 0
+        assert_eq!(guard.available("ETH").await.unwrap(), 0.0);
+        assert_eq!(guard.available("BTC").await.unwrap(), 0.0);
+        assert!(guard.can_buy(&Symbol::Eth, 70.0).await);
+        assert!(!guard.can_buy(&Symbol::Eth, 70.1).await);
+        assert!(!guard.can_sell(&Symbol::Eth, 0.1).await);
+    }
+
+    #[tokio::test]
+    async fn balances_should_be_cached_until_refresh_or_invalidate() {
+        let (trade, clock, guard) = guard(&[]);
+        assert_eq!(guard.available("USDT").await.unwrap(), 100.0);
+
+        trade.buy_limit(&Symbol::Eth, 0.5, 100.0).await.unwrap();
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(guard.available("USDT").await.unwrap(), 100.0);
+
+        // 超过有效期后重新查询
+        clock.advance(Duration::from_secs(31));
+        assert_eq!(guard.available("USDT").await.unwrap(), 50.0);
+
+        // 成交后立即失效
+        trade.sell_limit(&Symbol::Eth, 1.5, 100.0).await.unwrap();
+        assert_eq!(guard.available("ETH").await.unwrap(), 1.5);
+        guard.invalidate().await;
+        assert_eq!(guard.available("ETH").await.unwrap(), 0.0);
+        assert_eq!(guard.available("USDT").await.unwrap(), 200.0);
+    }
+}
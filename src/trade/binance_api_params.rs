@@ -1,6 +1,10 @@
+use std::fmt;
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize};
 
-use crate::Symbol;
+use crate::clock::Clock;
+use crate::{Symbol, TgError};
 
 #[derive(Debug, Serialize)]
 pub struct PEmpty;
@@ -20,7 +24,7 @@ pub struct PKline<'a> {
     pub limit: Option<usize>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Interval {
     #[serde(rename = "1m")]
     Min1,
@@ -54,12 +58,46 @@ pub enum Interval {
     Month1,
 }
 
-impl ToString for Interval {
-    fn to_string(&self) -> String {
-        serde_json::to_string(self)
-            .unwrap()
-            .trim_matches('"')
-            .to_string()
+impl Interval {
+    /// k 线的时长(毫秒)，`Month1` 按30天计
+    pub fn millis(&self) -> i64 {
+        const MINUTE: i64 = 60 * 1000;
+        const HOUR: i64 = 60 * MINUTE;
+        const DAY: i64 = 24 * HOUR;
+        match self {
+            Interval::Min1 => MINUTE,
+            Interval::Min3 => 3 * MINUTE,
+            Interval::Min5 => 5 * MINUTE,
+            Interval::Min15 => 15 * MINUTE,
+            Interval::Min30 => 30 * MINUTE,
+            Interval::Hour1 => HOUR,
+            Interval::Hour2 => 2 * HOUR,
+            Interval::Hour4 => 4 * HOUR,
+            Interval::Hour6 => 6 * HOUR,
+            Interval::Hour8 => 8 * HOUR,
+            Interval::Hour12 => 12 * HOUR,
+            Interval::Day1 => DAY,
+            Interval::Day3 => 3 * DAY,
+            Interval::Week1 => 7 * DAY,
+            Interval::Month1 => 30 * DAY,
+        }
+    }
+}
+
+impl FromStr for Interval {
+    type Err = TgError;
+
+    /// 与币安相同的写法，如 `1h`、`4h`、`1d`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.to_string()))
+            .map_err(|_| TgError::ConfError(format!("Unknown interval: {}", s)))
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(s.trim_matches('"'))
     }
 }
 
@@ -75,13 +113,13 @@ pub struct PQuerySpotOrder<'a> {
 }
 
 impl<'a> PQuerySpotOrder<'a> {
-    pub fn new(symbol: &'a Symbol) -> Self {
+    pub fn new(symbol: &'a Symbol, ts: PTimestamp) -> Self {
         PQuerySpotOrder {
             symbol,
             order_id: None,
             orig_client_order_id: None,
             new_client_order_id: None,
-            ts: PTimestamp::now(),
+            ts,
         }
     }
 }
@@ -96,12 +134,26 @@ pub struct PSpotOrder<'a> {
 }
 
 impl<'a> PSpotOrder<'a> {
-    pub fn new(symbol: &'a Symbol, side: OrderSide, quantity: f64, price: Option<f64>) -> Self {
+    pub fn new(
+        symbol: &'a Symbol,
+        side: OrderSide,
+        quantity: f64,
+        price: Option<f64>,
+        ts: PTimestamp,
+    ) -> Self {
         Self {
             spec: PSpotOrderSpec::new(symbol, side, quantity, price),
-            ts: PTimestamp::now(),
+            ts,
         }
     }
+
+    /// 按计价资产金额下的市价单
+    pub fn quote(symbol: &'a Symbol, side: OrderSide, quote: f64, ts: PTimestamp) -> Self {
+        let mut spec = PSpotOrderSpec::new(symbol, side, 0.0, None);
+        spec.quantity = None;
+        spec.quote_order_qty = Some(quote);
+        Self { spec, ts }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -182,6 +234,7 @@ pub enum SpotOrderType {
     LimitMaker,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Serialize, Deserialize)]
 pub enum TimeInForce {
     GTC,
@@ -198,8 +251,8 @@ pub struct PTimestamp {
 }
 
 impl PTimestamp {
-    pub fn now() -> Self {
-        let now = chrono::Utc::now();
+    pub fn now(clock: &dyn Clock) -> Self {
+        let now = clock.now();
         PTimestamp {
             timestamp: now.timestamp_millis(),
             recv_window: Some(5000),
@@ -1,11 +1,13 @@
 use std::fmt;
 
+use chrono::{TimeZone, Utc};
 use serde::de::{SeqAccess, Unexpected, Visitor};
 use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::serde::string_as_f64;
 use crate::trade::binance_api_params::{OrderSide, SpotOrderType, TimeInForce};
-use crate::Symbol;
+use crate::trade::{Account, Balance, Kline, Order, OrderType, Ticker24h};
+use crate::{Symbol, TradeSide};
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -15,7 +17,7 @@ pub struct RSpotPrice {
     pub price: f64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RH24ticker {
     pub symbol: Symbol,
@@ -46,7 +48,7 @@ pub struct RH24ticker {
     pub count: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RKline {
     pub open_time: i64,
     pub open: f64,
@@ -266,7 +268,7 @@ pub enum OrderStatus {
     Expired,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct QuerySpotOrder {
     /// 交易对
@@ -315,7 +317,7 @@ pub struct QuerySpotOrder {
     pub orig_quote_order_qty: f64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SpotAccount {
     pub maker_commission: usize,
@@ -331,7 +333,7 @@ pub struct SpotAccount {
     pub permissions: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SpotBalance {
     pub asset: String,
@@ -340,3 +342,90 @@ pub struct SpotBalance {
     #[serde(deserialize_with = "string_as_f64")]
     pub locked: f64,
 }
+
+impl From<RH24ticker> for Ticker24h {
+    fn from(t: RH24ticker) -> Self {
+        Self {
+            symbol: t.symbol,
+            last_price: t.last_price,
+            open_price: t.open_price,
+            high_price: t.high_price,
+            low_price: t.low_price,
+            price_change_percent: t.price_change_percent,
+            volume: t.volume,
+            quote_volume: t.quote_volume,
+        }
+    }
+}
+
+impl From<RKline> for Kline {
+    fn from(k: RKline) -> Self {
+        Self {
+            open_time: k.open_time,
+            open: k.open,
+            high: k.high,
+            low: k.low,
+            close: k.close,
+            close_time: k.close_time,
+            count: k.count,
+        }
+    }
+}
+
+impl From<OrderStatus> for crate::trade::OrderStatus {
+    fn from(s: OrderStatus) -> Self {
+        match s {
+            OrderStatus::New => Self::New,
+            OrderStatus::PartiallyFilled => Self::PartiallyFilled,
+            OrderStatus::Filled => Self::Filled,
+            OrderStatus::Canceled => Self::Canceled,
+            OrderStatus::Rejected => Self::Rejected,
+            OrderStatus::Expired => Self::Expired,
+        }
+    }
+}
+
+impl QuerySpotOrder {
+    /// 转换为通用的订单，`symbol` 为查询时的币种
+    pub fn into_order(self, symbol: &Symbol) -> Order {
+        Order {
+            order_id: self.order_id,
+            symbol: symbol.clone(),
+            side: match self.side {
+                OrderSide::Buy => TradeSide::Buy,
+                OrderSide::Sell => TradeSide::Sell,
+            },
+            order_type: match self.order_type {
+                SpotOrderType::Limit | SpotOrderType::LimitMaker => OrderType::Limit,
+                SpotOrderType::Market => OrderType::Market,
+                _ => OrderType::Other,
+            },
+            status: self.status.into(),
+            price: self.price,
+            quantity: self.orig_qty,
+            executed_quantity: self.executed_qty,
+            quote_quantity: self.cummulative_quote_qty,
+            time: Utc
+                .timestamp_millis_opt(self.time)
+                .single()
+                .unwrap_or_else(Utc::now),
+        }
+    }
+}
+
+impl From<SpotAccount> for Account {
+    fn from(a: SpotAccount) -> Self {
+        Self {
+            can_trade: a.can_trade,
+            balances: a
+                .balances
+                .into_iter()
+                .map(|b| Balance {
+                    asset: b.asset,
+                    free: b.free,
+                    locked: b.locked,
+                })
+                .collect(),
+        }
+    }
+}
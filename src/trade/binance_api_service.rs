@@ -1,82 +1,123 @@
 use std::borrow::{Borrow, BorrowMut};
+use std::sync::Arc;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use reqwest::{Client, Url};
+use chrono::{TimeZone, Utc};
+use reqwest::Url;
 use ring::hmac;
 use serde::Serialize;
 
+use crate::clock::Clock;
 use crate::trade::binance_api_params::{
-    Interval, OrderSide, PEmpty, PKline, PQuerySpotOrder, PSpotOrder, PSymbol, PTimestamp,
+    OrderSide, PEmpty, PKline, PQuerySpotOrder, PSpotOrder, PSymbol, PTimestamp,
 };
 use crate::trade::binance_api_response::{
-    QuerySpotOrder, RH24ticker, RKline, RSpotPrice, SpotAccount, SpotOrder,
+    OrderFill, QuerySpotOrder, RH24ticker, RKline, RSpotPrice, SpotAccount, SpotOrder,
 };
-use crate::trade::{MarketService, TradeService};
-use crate::{Symbol, TgError, TradeConfig};
+use crate::trade::{
+    build_client, Account, Fill, Kline, KlineQuery, MarketService, Order, Ticker24h, TradeService,
+};
+use crate::{Secret, Symbol, TgError, TradeConfig};
+
+/// Binance 单次请求最多返回的 k 线数量
+const MAX_KLINE_LIMIT: usize = 1000;
 
 pub struct BinanceTradeService {
     http_client: reqwest::Client,
     hmac_key: hmac::Key,
-    api_key: String,
+    api_key: Secret,
     base_url: reqwest::Url,
+    clock: Arc<dyn Clock>,
 }
 
 #[async_trait]
 impl TradeService for BinanceTradeService {
-    async fn get_order(&self, symbol: &Symbol) -> Result<QuerySpotOrder> {
-        let param = PQuerySpotOrder::new(symbol);
+    async fn get_order(&self, symbol: &Symbol, order_id: usize) -> Result<Order> {
+        let mut param = PQuerySpotOrder::new(symbol, self.timestamp());
+        param.order_id = Some(order_id);
         let json_str = self
             .send_request("order", reqwest::Method::GET, &param)
             .await?;
         let order: QuerySpotOrder = serde_json::from_str(json_str.as_str())?;
-        Ok(order)
+        Ok(order.into_order(symbol))
     }
 
-    async fn buy_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<f64>> {
+    async fn buy_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
         self.order_ops(symbol, OrderSide::Buy, quantity, Some(price))
             .await
     }
 
-    async fn buy(&self, symbol: &Symbol, quantity: f64) -> Result<Option<f64>> {
+    async fn buy(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
         self.order_ops(symbol, OrderSide::Buy, quantity, None).await
     }
 
-    async fn sell_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<f64>> {
+    async fn buy_quote(&self, symbol: &Symbol, quote: f64) -> Result<Option<Fill>> {
+        let param = PSpotOrder::quote(symbol, OrderSide::Buy, quote, self.timestamp());
+        self.send_order(&param).await
+    }
+
+    async fn sell_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
         self.order_ops(symbol, OrderSide::Sell, quantity, Some(price))
             .await
     }
 
-    async fn sell(&self, symbol: &Symbol, quantity: f64) -> Result<Option<f64>> {
+    async fn sell(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
         self.order_ops(symbol, OrderSide::Sell, quantity, None)
             .await
     }
 
-    async fn account(&self) -> Result<SpotAccount> {
-        let param = PTimestamp::now();
+    async fn account(&self) -> Result<Account> {
+        let param = self.timestamp();
         let json_str = self
             .send_request("account", reqwest::Method::GET, &param)
             .await?;
         let account: SpotAccount = serde_json::from_str(json_str.as_str())?;
-        Ok(account)
+        Ok(account.into())
+    }
+
+    async fn open_orders(&self, symbol: &Symbol) -> Result<Vec<Order>> {
+        let param = PQuerySpotOrder::new(symbol, self.timestamp());
+        let json_str = self
+            .send_request("openOrders", reqwest::Method::GET, &param)
+            .await?;
+        let orders: Vec<QuerySpotOrder> = serde_json::from_str(json_str.as_str())?;
+        Ok(orders.into_iter().map(|o| o.into_order(symbol)).collect())
+    }
+
+    async fn cancel_order(&self, symbol: &Symbol, order_id: Option<usize>) -> Result<()> {
+        let mut param = PQuerySpotOrder::new(symbol, self.timestamp());
+        let path = match order_id {
+            Some(_) => "order",
+            None => "openOrders",
+        };
+        param.order_id = order_id;
+        self.send_request(path, reqwest::Method::DELETE, &param)
+            .await?;
+        Ok(())
     }
 }
 
 impl BinanceTradeService {
-    pub fn new(config: &TradeConfig) -> Result<Self> {
+    pub fn new(config: &TradeConfig, clock: Arc<dyn Clock>) -> Result<Self> {
         let base_url = reqwest::Url::parse(config.url.as_str())
             .map_err(|_| TgError::UrlError(config.url.to_string()))?;
         let http_client = build_client(config)?;
-        let key = hmac::Key::new(hmac::HMAC_SHA256, config.secret.as_bytes());
+        let key = hmac::Key::new(hmac::HMAC_SHA256, config.secret.expose().as_bytes());
 
         Ok(Self {
             http_client,
             hmac_key: key,
-            api_key: config.key.to_string(),
+            api_key: config.key.clone(),
             base_url,
+            clock,
         })
     }
 
+    fn timestamp(&self) -> PTimestamp {
+        PTimestamp::now(self.clock.as_ref())
+    }
+
     fn sign_and_query<P: Serialize>(&self, params: &P) -> Result<String> {
         let qs = serde_qs::to_string(&params)?;
         let signature = hmac::sign(self.hmac_key.borrow(), qs.as_bytes());
@@ -99,7 +140,7 @@ impl BinanceTradeService {
             .http_client
             .request(method, url)
             .header("Content-Type", "application/json")
-            .header("X-MBX-APIKEY", self.api_key.as_str())
+            .header("X-MBX-APIKEY", self.api_key.expose())
             .send()
             .await?;
         let resp = TgError::bina_resp(res).await?;
@@ -113,35 +154,46 @@ impl BinanceTradeService {
         side: OrderSide,
         quantity: f64,
         price: Option<f64>,
-    ) -> Result<Option<f64>> {
-        let param = PSpotOrder::new(symbol, side, quantity, price);
+    ) -> Result<Option<Fill>> {
+        let param = PSpotOrder::new(symbol, side, quantity, price, self.timestamp());
+        self.send_order(&param).await
+    }
+
+    /// 下单并汇总成交明细
+    async fn send_order(&self, param: &PSpotOrder<'_>) -> Result<Option<Fill>> {
         let json_str = self
-            .send_request("order", reqwest::Method::POST, &param)
+            .send_request("order", reqwest::Method::POST, param)
             .await?;
         let order: SpotOrder = serde_json::from_str(json_str.as_str())?;
         let fills = match order {
             SpotOrder::Ack(_) => None,
             SpotOrder::Result(_) => None,
             SpotOrder::Full(full) => {
-                if full.fills.is_empty() {
-                    None
-                } else {
-                    Some(full.fills[0].price)
-                }
+                summarize_fills(full.order_id, full.transact_time, &full.fills)
             }
         };
         Ok(fills)
     }
 }
 
-fn build_client(config: &TradeConfig) -> Result<Client> {
-    let http_client = match config.proxy.as_ref() {
-        Some(proxy) => reqwest::Client::builder()
-            .proxy(reqwest::Proxy::all(proxy)?)
-            .build()?,
-        None => reqwest::Client::new(),
-    };
-    Ok(http_client)
+/// 多笔成交的均价、总数量和手续费，`time` 为毫秒时间戳
+fn summarize_fills(order_id: usize, time: i64, fills: &[OrderFill]) -> Option<Fill> {
+    let quantity: f64 = fills.iter().map(|f| f.qty).sum();
+    if quantity <= 0.0 {
+        return None;
+    }
+    let quote: f64 = fills.iter().map(|f| f.price * f.qty).sum();
+    Some(Fill {
+        price: quote / quantity,
+        quantity,
+        order_id: Some(order_id),
+        fee: fills.iter().map(|f| f.commission).sum(),
+        fee_asset: fills.first().map(|f| f.commission_asset.clone()),
+        time: Utc
+            .timestamp_millis_opt(time)
+            .single()
+            .unwrap_or_else(Utc::now),
+    })
 }
 
 pub struct BinanceMarketService {
@@ -163,24 +215,24 @@ impl MarketService for BinanceMarketService {
         Ok(obj.price)
     }
 
-    async fn ticker_24hr(&self, symbol: &Symbol) -> Result<RH24ticker> {
+    async fn ticker_24hr(&self, symbol: &Symbol) -> Result<Ticker24h> {
         let param = PSymbol { symbol };
         let json_str = self.send_request("ticker/24hr", &param).await?;
         let obj: RH24ticker = serde_json::from_str(json_str.as_str())?;
-        Ok(obj)
+        Ok(obj.into())
     }
 
-    async fn k_lines(&self, symbol: &Symbol) -> Result<Vec<RKline>> {
+    async fn k_lines(&self, symbol: &Symbol, query: &KlineQuery) -> Result<Vec<Kline>> {
         let param = PKline {
             symbol,
-            interval: Interval::Hour4,
-            start_time: None,
-            end_time: None,
-            limit: Some(20),
+            interval: query.interval,
+            start_time: query.start_time,
+            end_time: query.end_time,
+            limit: query.limit.map(|limit| limit.min(MAX_KLINE_LIMIT)),
         };
         let json_str = self.send_request("klines", &param).await?;
         let obj: Vec<RKline> = serde_json::from_str(json_str.as_str())?;
-        Ok(obj)
+        Ok(obj.into_iter().map(Kline::from).collect())
     }
 }
 
@@ -196,7 +248,7 @@ impl BinanceMarketService {
     /// send request
     async fn send_request<P: serde::Serialize>(&self, path: &str, params: &P) -> Result<String> {
         let mut url = self.url.join(path)?;
-        let param = serde_qs::to_string(params).map_or(None, |s| Some(s));
+        let param = serde_qs::to_string(params).ok();
         url.borrow_mut().set_query(param.as_deref());
         let res = self
             .http_client
@@ -211,6 +263,8 @@ impl BinanceMarketService {
 
 #[cfg(test)]
 mod tests {
+    use crate::Exchange;
+
     use super::*;
 
     #[test]
@@ -225,6 +279,24 @@ mod tests {
         assert_eq!("", qs.as_str());
     }
 
+    #[test]
+    fn fills_should_be_summarized() {
+        let fills: Vec<OrderFill> = serde_json::from_str(
+            r#"[
+                {"price":"100","qty":"1","commission":"0.1","commissionAsset":"USDT"},
+                {"price":"110","qty":"3","commission":"0.3","commissionAsset":"USDT"}
+            ]"#,
+        )
+        .unwrap();
+        let fill = summarize_fills(7, 1591702613943, &fills).unwrap();
+        assert_eq!(fill.quantity, 4.0);
+        assert_eq!(fill.price, 107.5);
+        assert_eq!(fill.order_id, Some(7));
+        assert!((fill.quote_fee(&Symbol::Eth) - 0.4).abs() < 1e-9);
+        assert_eq!(fill.time.timestamp_millis(), 1591702613943);
+        assert_eq!(summarize_fills(7, 0, &[]), None);
+    }
+
     #[test]
     fn url_join() {
         let base_url = reqwest::Url::parse("https://api.binance.com/api/").unwrap();
@@ -254,8 +326,14 @@ mod tests {
         static ref TC: TradeConfig = TradeConfig {
             url: "https://testnet.binance.vision/api/v3/".to_string(),
             proxy: None,
-            key: "x".to_string(),
-            secret: "x".to_string(),
+            key: "x".into(),
+            secret: "x".into(),
+            key_file: None,
+            secret_file: None,
+            exchange: Exchange::Binance,
+            reserve: Default::default(),
+            dry_run: false,
+            record: None,
         };
     }
 
@@ -0,0 +1,905 @@
+//! Bybit 现货 v5 API，交易对写作 ETHUSDT，k 线按时间倒序返回
+
+use std::borrow::{Borrow, BorrowMut};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use reqwest::{Method, Url};
+use ring::hmac;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::clock::{self, Clock};
+use crate::serde::string_as_f64;
+use crate::trade::{
+    build_client, Account, Balance, Fill, Interval, Kline, KlineQuery, MarketService, Order,
+    OrderStatus, OrderType, Ticker24h, TradeService,
+};
+use crate::{Secret, Symbol, TgError, TradeConfig, TradeSide};
+
+/// Bybit 单次请求最多返回的 k 线数量
+const MAX_KLINE_LIMIT: usize = 1000;
+const CATEGORY: &str = "spot";
+const RECV_WINDOW: i64 = 5000;
+/// 下单后查询订单状态的间隔
+const ORDER_POLL: Duration = Duration::from_millis(500);
+/// 等待订单结束的最长时间，超时后撤销剩余部分
+const ORDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 统一的响应格式，`retCode` 不为0时请求失败
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RResponse {
+    ret_code: i64,
+    ret_msg: String,
+    #[serde(default)]
+    result: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct RList<T> {
+    list: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RTicker {
+    #[serde(deserialize_with = "string_as_f64")]
+    last_price: f64,
+    #[serde(deserialize_with = "string_as_f64")]
+    prev_price24h: f64,
+    #[serde(deserialize_with = "string_as_f64")]
+    high_price24h: f64,
+    #[serde(deserialize_with = "string_as_f64")]
+    low_price24h: f64,
+    /// 涨跌幅，0.01 即 1%
+    #[serde(deserialize_with = "string_as_f64")]
+    price24h_pcnt: f64,
+    #[serde(deserialize_with = "string_as_f64")]
+    volume24h: f64,
+    #[serde(deserialize_with = "string_as_f64")]
+    turnover24h: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ROrderId {
+    order_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ROrder {
+    order_id: String,
+    side: TradeSide,
+    order_type: String,
+    order_status: String,
+    #[serde(deserialize_with = "string_as_f64")]
+    price: f64,
+    #[serde(deserialize_with = "string_as_f64")]
+    qty: f64,
+    #[serde(deserialize_with = "string_as_f64")]
+    avg_price: f64,
+    #[serde(deserialize_with = "string_as_f64")]
+    cum_exec_qty: f64,
+    #[serde(deserialize_with = "string_as_f64")]
+    cum_exec_value: f64,
+    #[serde(deserialize_with = "string_as_f64")]
+    cum_exec_fee: f64,
+    #[serde(deserialize_with = "string_as_f64")]
+    updated_time: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RWallet {
+    coin: Vec<RCoin>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RCoin {
+    coin: String,
+    #[serde(deserialize_with = "string_as_f64")]
+    wallet_balance: f64,
+    #[serde(default, deserialize_with = "string_as_f64")]
+    locked: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct PSymbol {
+    category: &'static str,
+    symbol: String,
+}
+
+impl PSymbol {
+    fn new(symbol: &Symbol) -> Self {
+        Self {
+            category: CATEGORY,
+            symbol: symbol.pair(""),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct PKline {
+    category: &'static str,
+    symbol: String,
+    interval: &'static str,
+    start: Option<i64>,
+    end: Option<i64>,
+    limit: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct POrder {
+    category: &'static str,
+    symbol: String,
+    side: TradeSide,
+    order_type: &'static str,
+    qty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<String>,
+    /// 市价单的 `qty` 是基础资产(baseCoin)还是计价资产(quoteCoin)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    market_unit: Option<&'static str>,
+}
+
+impl POrder {
+    fn new(symbol: &Symbol, side: TradeSide, quantity: f64, price: Option<f64>) -> Self {
+        Self {
+            category: CATEGORY,
+            symbol: symbol.pair(""),
+            side,
+            order_type: match price {
+                Some(_) => "Limit",
+                None => "Market",
+            },
+            qty: quantity.to_string(),
+            price: price.map(|p| p.to_string()),
+            market_unit: price.is_none().then_some("baseCoin"),
+        }
+    }
+
+    /// 按计价资产金额下的市价单
+    fn quote(symbol: &Symbol, side: TradeSide, quote: f64) -> Self {
+        Self {
+            qty: quote.to_string(),
+            market_unit: Some("quoteCoin"),
+            ..Self::new(symbol, side, 0.0, None)
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct POrderId {
+    category: &'static str,
+    symbol: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    open_only: Option<u8>,
+}
+
+impl POrderId {
+    fn new(symbol: &Symbol, order_id: Option<usize>) -> Self {
+        Self {
+            category: CATEGORY,
+            symbol: symbol.pair(""),
+            order_id: order_id.map(|id| id.to_string()),
+            open_only: None,
+        }
+    }
+}
+
+/// 检查 HTTP 状态及 `retCode`，返回 `result`
+async fn read_result<T: DeserializeOwned>(res: reqwest::Response) -> Result<T> {
+    let status = res.status();
+    let text = res
+        .text()
+        .await
+        .map_err(|e| TgError::DecodeError(e.to_string()))?;
+    debug!("resp {}", &text);
+    if !status.is_success() {
+        warn!("Bybit {}:{}", status, text);
+        return Err(TgError::BybitError(status.as_u16() as i64, text).into());
+    }
+    let resp: RResponse = serde_json::from_str(&text)?;
+    if resp.ret_code != 0 {
+        warn!("Bybit {}:{}", resp.ret_code, resp.ret_msg);
+        return Err(TgError::BybitError(resp.ret_code, resp.ret_msg).into());
+    }
+    Ok(serde_json::from_value(resp.result)?)
+}
+
+/// Bybit 的 k 线周期，不支持 8h 和 3d
+fn interval(interval: Interval) -> Result<&'static str, TgError> {
+    Ok(match interval {
+        Interval::Min1 => "1",
+        Interval::Min3 => "3",
+        Interval::Min5 => "5",
+        Interval::Min15 => "15",
+        Interval::Min30 => "30",
+        Interval::Hour1 => "60",
+        Interval::Hour2 => "120",
+        Interval::Hour4 => "240",
+        Interval::Hour6 => "360",
+        Interval::Hour12 => "720",
+        Interval::Day1 => "D",
+        Interval::Week1 => "W",
+        Interval::Month1 => "M",
+        Interval::Hour8 | Interval::Day3 => {
+            return Err(TgError::Internal(format!(
+                "Interval {} is not supported by Bybit",
+                interval
+            )))
+        }
+    })
+}
+
+fn millis(time: f64) -> chrono::DateTime<Utc> {
+    Utc.timestamp_millis_opt(time as i64)
+        .single()
+        .unwrap_or_else(Utc::now)
+}
+
+impl ROrder {
+    fn order_id(&self) -> Result<usize, TgError> {
+        self.order_id
+            .parse()
+            .map_err(|_| TgError::DecodeError(format!("Invalid order id: {}", self.order_id)))
+    }
+
+    /// 订单是否已结束，不会再有新的成交
+    fn is_final(&self) -> bool {
+        matches!(
+            self.order_status.as_str(),
+            "Filled" | "Cancelled" | "PartiallyFilledCanceled" | "Rejected" | "Deactivated"
+        )
+    }
+
+    /// 已成交部分的汇总，手续费以收到的资产计
+    fn fill(&self, symbol: &Symbol) -> Result<Option<Fill>> {
+        if self.cum_exec_qty <= 0.0 {
+            return Ok(None);
+        }
+        let fee_asset = match self.side {
+            TradeSide::Buy => symbol.base_asset(),
+            TradeSide::Sell => symbol.quote_asset(),
+        };
+        let price = match self.avg_price > 0.0 {
+            true => self.avg_price,
+            false => self.cum_exec_value / self.cum_exec_qty,
+        };
+        Ok(Some(Fill {
+            price,
+            quantity: self.cum_exec_qty,
+            order_id: Some(self.order_id()?),
+            fee: self.cum_exec_fee,
+            fee_asset: Some(fee_asset.to_string()),
+            time: millis(self.updated_time),
+        }))
+    }
+
+    fn into_order(self, symbol: &Symbol) -> Result<Order> {
+        Ok(Order {
+            order_id: self.order_id()?,
+            symbol: symbol.clone(),
+            side: self.side,
+            order_type: match self.order_type.as_str() {
+                "Limit" => OrderType::Limit,
+                "Market" => OrderType::Market,
+                _ => OrderType::Other,
+            },
+            status: match self.order_status.as_str() {
+                "New" | "Untriggered" | "Triggered" => OrderStatus::New,
+                "PartiallyFilled" => OrderStatus::PartiallyFilled,
+                "Filled" => OrderStatus::Filled,
+                "Cancelled" | "PartiallyFilledCanceled" | "Deactivated" => OrderStatus::Canceled,
+                "Rejected" => OrderStatus::Rejected,
+                _ => OrderStatus::Expired,
+            },
+            price: self.price,
+            quantity: self.qty,
+            executed_quantity: self.cum_exec_qty,
+            quote_quantity: self.cum_exec_value,
+            time: millis(self.updated_time),
+        })
+    }
+}
+
+pub struct BybitTradeService {
+    http_client: reqwest::Client,
+    hmac_key: hmac::Key,
+    api_key: Secret,
+    base_url: Url,
+    clock: Arc<dyn Clock>,
+}
+
+#[async_trait]
+impl TradeService for BybitTradeService {
+    async fn get_order(&self, symbol: &Symbol, order_id: usize) -> Result<Order> {
+        self.query_order(symbol, order_id).await?.into_order(symbol)
+    }
+
+    async fn buy_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
+        let param = POrder::new(symbol, TradeSide::Buy, quantity, Some(price));
+        self.send_order(symbol, &param).await
+    }
+
+    async fn buy(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        let param = POrder::new(symbol, TradeSide::Buy, quantity, None);
+        self.send_order(symbol, &param).await
+    }
+
+    async fn buy_quote(&self, symbol: &Symbol, quote: f64) -> Result<Option<Fill>> {
+        let param = POrder::quote(symbol, TradeSide::Buy, quote);
+        self.send_order(symbol, &param).await
+    }
+
+    async fn sell_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
+        let param = POrder::new(symbol, TradeSide::Sell, quantity, Some(price));
+        self.send_order(symbol, &param).await
+    }
+
+    async fn sell(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        let param = POrder::new(symbol, TradeSide::Sell, quantity, None);
+        self.send_order(symbol, &param).await
+    }
+
+    async fn account(&self) -> Result<Account> {
+        let param = [("accountType", "UNIFIED")];
+        let wallets: RList<RWallet> = self
+            .send_request(Method::GET, "v5/account/wallet-balance", &param)
+            .await?;
+        let balances = wallets
+            .list
+            .into_iter()
+            .flat_map(|w| w.coin)
+            .map(|c| Balance {
+                free: c.wallet_balance - c.locked,
+                locked: c.locked,
+                asset: c.coin,
+            })
+            .collect();
+        Ok(Account {
+            can_trade: true,
+            balances,
+        })
+    }
+
+    async fn open_orders(&self, symbol: &Symbol) -> Result<Vec<Order>> {
+        let mut param = POrderId::new(symbol, None);
+        param.open_only = Some(0);
+        let orders: RList<ROrder> = self
+            .send_request(Method::GET, "v5/order/realtime", &param)
+            .await?;
+        orders
+            .list
+            .into_iter()
+            .map(|o| o.into_order(symbol))
+            .collect()
+    }
+
+    async fn cancel_order(&self, symbol: &Symbol, order_id: Option<usize>) -> Result<()> {
+        let path = match order_id {
+            Some(_) => "v5/order/cancel",
+            None => "v5/order/cancel-all",
+        };
+        let param = POrderId::new(symbol, order_id);
+        self.send_request::<_, Value>(Method::POST, path, &param)
+            .await?;
+        Ok(())
+    }
+}
+
+impl BybitTradeService {
+    pub fn new(config: &TradeConfig, clock: Arc<dyn Clock>) -> Result<Self> {
+        let base_url =
+            Url::parse(config.url.as_str()).map_err(|_| TgError::UrlError(config.url.clone()))?;
+        let http_client = build_client(config)?;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, config.secret.expose().as_bytes());
+
+        Ok(Self {
+            http_client,
+            hmac_key: key,
+            api_key: config.key.clone(),
+            base_url,
+            clock,
+        })
+    }
+
+    /// 签名内容为 时间戳 + key + recv_window + 查询串(GET)或请求体(POST)
+    fn sign(&self, timestamp: i64, payload: &str) -> String {
+        let content = format!(
+            "{}{}{}{}",
+            timestamp,
+            self.api_key.expose(),
+            RECV_WINDOW,
+            payload
+        );
+        let signature = hmac::sign(self.hmac_key.borrow(), content.as_bytes());
+        hex::encode(signature.as_ref())
+    }
+
+    /// send request，GET 的参数放在查询串，POST 的参数为 JSON 请求体
+    async fn send_request<P: Serialize, T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        params: &P,
+    ) -> Result<T> {
+        let mut url = self.base_url.join(path)?;
+        let payload = match method {
+            Method::GET => {
+                let query = serde_qs::to_string(params)?;
+                url.borrow_mut().set_query(Some(query.as_str()));
+                query
+            }
+            _ => serde_json::to_string(params)?,
+        };
+        let timestamp = self.clock.now().timestamp_millis();
+        let mut request = self
+            .http_client
+            .request(method.clone(), url)
+            .header("Content-Type", "application/json")
+            .header("X-BAPI-API-KEY", self.api_key.expose())
+            .header("X-BAPI-TIMESTAMP", timestamp)
+            .header("X-BAPI-RECV-WINDOW", RECV_WINDOW)
+            .header("X-BAPI-SIGN", self.sign(timestamp, &payload));
+        if method != Method::GET {
+            request = request.body(payload);
+        }
+        read_result(request.send().await?).await
+    }
+
+    /// 刚创建的订单可能暂时查询不到
+    async fn find_order(&self, symbol: &Symbol, order_id: usize) -> Result<Option<ROrder>> {
+        let param = POrderId::new(symbol, Some(order_id));
+        let orders: RList<ROrder> = self
+            .send_request(Method::GET, "v5/order/realtime", &param)
+            .await?;
+        Ok(orders.list.into_iter().next())
+    }
+
+    async fn query_order(&self, symbol: &Symbol, order_id: usize) -> Result<ROrder> {
+        self.find_order(symbol, order_id)
+            .await?
+            .ok_or_else(|| TgError::DecodeError(format!("Order {} not found", order_id)).into())
+    }
+
+    /// 下单后每隔 `ORDER_POLL` 查询订单直到结束，超过 `ORDER_TIMEOUT` 时撤销剩余部分，返回已成交的部分
+    async fn send_order(&self, symbol: &Symbol, param: &POrder) -> Result<Option<Fill>> {
+        let created: ROrderId = self
+            .send_request(Method::POST, "v5/order/create", param)
+            .await?;
+        let order_id = created
+            .order_id
+            .parse()
+            .map_err(|_| TgError::DecodeError(format!("Invalid order id: {}", created.order_id)))?;
+        let start = self.clock.now();
+        loop {
+            if let Some(order) = self.find_order(symbol, order_id).await? {
+                if order.is_final() {
+                    return order.fill(symbol);
+                }
+            }
+            if clock::elapsed(self.clock.as_ref(), start, ORDER_TIMEOUT) {
+                break;
+            }
+            self.clock.sleep(ORDER_POLL).await;
+        }
+        warn!("币种:{},订单{}超时未结束，撤销剩余部分", symbol, order_id);
+        // 撤单失败时订单可能刚好结束，仍以查询结果为准
+        if let Err(e) = self.cancel_order(symbol, Some(order_id)).await {
+            warn!("币种:{},撤销订单{}失败: {}", symbol, order_id, e);
+        }
+        self.query_order(symbol, order_id).await?.fill(symbol)
+    }
+}
+
+pub struct BybitMarketService {
+    http_client: reqwest::Client,
+    url: Url,
+}
+
+#[async_trait]
+impl MarketService for BybitMarketService {
+    async fn ping(&self) -> Result<bool> {
+        let _: Value = self.send_request("v5/market/time", &()).await?;
+        Ok(true)
+    }
+
+    async fn ticker_price(&self, symbol: &Symbol) -> Result<f64> {
+        Ok(self.ticker(symbol).await?.last_price)
+    }
+
+    async fn ticker_24hr(&self, symbol: &Symbol) -> Result<Ticker24h> {
+        let ticker = self.ticker(symbol).await?;
+        Ok(Ticker24h {
+            symbol: symbol.clone(),
+            last_price: ticker.last_price,
+            open_price: ticker.prev_price24h,
+            high_price: ticker.high_price24h,
+            low_price: ticker.low_price24h,
+            price_change_percent: ticker.price24h_pcnt * 100.0,
+            volume: ticker.volume24h,
+            quote_volume: ticker.turnover24h,
+        })
+    }
+
+    /// Bybit 返回区间内最近的 `limit` 根，指定 `start_time` 时收窄结束时间，
+    /// 使其与币安一样返回最早的 `limit` 根
+    async fn k_lines(&self, symbol: &Symbol, query: &KlineQuery) -> Result<Vec<Kline>> {
+        let limit = query.limit.unwrap_or(MAX_KLINE_LIMIT).min(MAX_KLINE_LIMIT);
+        let millis = query.interval.millis();
+        let end = match query.start_time {
+            Some(start) => {
+                let last = start + limit as i64 * millis - 1;
+                Some(query.end_time.map_or(last, |end| end.min(last)))
+            }
+            None => query.end_time,
+        };
+        let param = PKline {
+            category: CATEGORY,
+            symbol: symbol.pair(""),
+            interval: interval(query.interval)?,
+            start: query.start_time,
+            end,
+            limit,
+        };
+        let rows: RList<[String; 7]> = self.send_request("v5/market/kline", &param).await?;
+        let mut bars = Vec::with_capacity(rows.list.len());
+        for row in rows.list.iter().rev() {
+            let field = |i: usize| {
+                row[i]
+                    .parse::<f64>()
+                    .map_err(|_| TgError::DecodeError(format!("Invalid kline: {:?}", row)))
+            };
+            let open_time = field(0)? as i64;
+            bars.push(Kline {
+                open_time,
+                open: field(1)?,
+                high: field(2)?,
+                low: field(3)?,
+                close: field(4)?,
+                close_time: open_time + millis - 1,
+                count: 0,
+            });
+        }
+        Ok(query.select(&bars))
+    }
+}
+
+impl BybitMarketService {
+    pub fn new(config: &TradeConfig) -> Result<Self> {
+        let url_str = config.url.as_str();
+        let url = Url::parse(url_str).map_err(|_| TgError::UrlError(url_str.to_string()))?;
+        let http_client = build_client(config)?;
+
+        Ok(Self { http_client, url })
+    }
+
+    async fn ticker(&self, symbol: &Symbol) -> Result<RTicker> {
+        let tickers: RList<RTicker> = self
+            .send_request("v5/market/tickers", &PSymbol::new(symbol))
+            .await?;
+        tickers.list.into_iter().next().ok_or_else(|| {
+            TgError::DecodeError(format!("No ticker for {}", symbol.pair(""))).into()
+        })
+    }
+
+    /// send request
+    async fn send_request<P: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        params: &P,
+    ) -> Result<T> {
+        let mut url = self.url.join(path)?;
+        let query = serde_qs::to_string(params).ok().filter(|q| !q.is_empty());
+        url.borrow_mut().set_query(query.as_deref());
+        let res = self.http_client.get(url).send().await?;
+        read_result(res).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use chrono::TimeZone;
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use crate::clock::ManualClock;
+    use crate::Exchange;
+
+    use super::*;
+
+    /// 模拟服务收到的请求
+    #[derive(Clone, Debug)]
+    struct Request {
+        method: String,
+        target: String,
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// 本地的 Bybit 模拟服务，按路径返回 `routes` 中的 `result`，记录收到的请求。
+    /// 同一路径有多个 `result` 时依次返回，最后一个重复使用
+    async fn serve(
+        mut routes: Vec<(&'static str, Value)>,
+    ) -> (TradeConfig, Arc<Mutex<Vec<Request>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut data = Vec::new();
+                let mut buf = [0; 1024];
+                let request = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    data.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&data).to_string();
+                    let (head, body) = match text.split_once("\r\n\r\n") {
+                        Some(parts) => parts,
+                        None => continue,
+                    };
+                    let mut lines = head.lines();
+                    let mut start = lines.next().unwrap().split(' ');
+                    let headers: HashMap<String, String> = lines
+                        .filter_map(|l| l.split_once(": "))
+                        .map(|(k, v)| (k.to_lowercase(), v.to_string()))
+                        .collect();
+                    let length = headers
+                        .get("content-length")
+                        .map_or(0, |l| l.parse::<usize>().unwrap());
+                    if body.len() >= length {
+                        break Request {
+                            method: start.next().unwrap().to_string(),
+                            target: start.next().unwrap().to_string(),
+                            headers,
+                            body: body.to_string(),
+                        };
+                    }
+                };
+                let path = request.target.split('?').next().unwrap().to_string();
+                let matched: Vec<usize> =
+                    (0..routes.len()).filter(|i| routes[*i].0 == path).collect();
+                let body = match matched.as_slice() {
+                    [] => json!({ "retCode": 10001, "retMsg": "unknown path", "result": {} }),
+                    [i] => json!({ "retCode": 0, "retMsg": "OK", "result": routes[*i].1 }),
+                    [i, ..] => {
+                        let (_, result) = routes.remove(*i);
+                        json!({ "retCode": 0, "retMsg": "OK", "result": result })
+                    }
+                }
+                .to_string();
+                received.lock().unwrap().push(request);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        let config = TradeConfig {
+            exchange: Exchange::Bybit,
+            url,
+            proxy: None,
+            key: "key".into(),
+            secret: "secret".into(),
+            key_file: None,
+            secret_file: None,
+            dry_run: false,
+            record: None,
+            reserve: Default::default(),
+        };
+        (config, requests)
+    }
+
+    fn clock() -> Arc<ManualClock> {
+        Arc::new(ManualClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn market_should_map_symbol_and_k_lines() {
+        let hour = Interval::Hour1.millis();
+        let (config, requests) = serve(vec![
+            (
+                "/v5/market/tickers",
+                json!({ "list": [{
+                    "symbol": "ETHUSDT", "lastPrice": "2000.5", "prevPrice24h": "1900",
+                    "highPrice24h": "2100", "lowPrice24h": "1850", "price24hPcnt": "0.05",
+                    "volume24h": "10", "turnover24h": "20000"
+                }]}),
+            ),
+            (
+                "/v5/market/kline",
+                json!({ "list": [
+                    [(2 * hour).to_string(), "3", "4", "2", "3.5", "1", "3"],
+                    [hour.to_string(), "2", "3", "1", "2.5", "1", "2"],
+                ]}),
+            ),
+        ])
+        .await;
+        let market = BybitMarketService::new(&config).unwrap();
+
+        assert_eq!(market.ticker_price(&Symbol::Eth).await.unwrap(), 2000.5);
+        let ticker = market.ticker_24hr(&Symbol::Eth).await.unwrap();
+        assert_eq!(ticker.open_price, 1900.0);
+        assert!((ticker.price_change_percent - 5.0).abs() < 1e-9);
+
+        let query = KlineQuery::range(Interval::Hour1, hour, None).with_limit(10);
+        let bars = market.k_lines(&Symbol::Eth, &query).await.unwrap();
+        assert_eq!(bars.len(), 2);
+        assert_eq!((bars[0].open_time, bars[0].close), (hour, 2.5));
+        assert_eq!(bars[1].close_time, 3 * hour - 1);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(
+            requests[0].target,
+            "/v5/market/tickers?category=spot&symbol=ETHUSDT"
+        );
+        // 从 start 起最多 limit 根
+        assert_eq!(
+            requests[2].target,
+            format!(
+                "/v5/market/kline?category=spot&symbol=ETHUSDT&interval=60&start={}&end={}&limit=10",
+                hour,
+                11 * hour - 1
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn market_buy_should_be_signed_and_filled() {
+        let (config, requests) = serve(vec![
+            (
+                "/v5/order/create",
+                json!({ "orderId": "1321003749386327552" }),
+            ),
+            (
+                "/v5/order/realtime",
+                json!({ "list": [{
+                    "orderId": "1321003749386327552", "side": "Buy", "orderType": "Market",
+                    "orderStatus": "Filled", "price": "0", "qty": "2", "avgPrice": "",
+                    "cumExecQty": "2", "cumExecValue": "201", "cumExecFee": "0.002",
+                    "updatedTime": "1704067200000"
+                }]}),
+            ),
+        ])
+        .await;
+        let clock = clock();
+        let trade = BybitTradeService::new(&config, clock.clone()).unwrap();
+
+        let fill = trade.buy(&Symbol::Eth, 2.0).await.unwrap().unwrap();
+        assert_eq!((fill.price, fill.quantity), (100.5, 2.0));
+        assert_eq!(fill.order_id, Some(1321003749386327552));
+        // 买入的手续费以基础资产计
+        assert!((fill.quote_fee(&Symbol::Eth) - 0.201).abs() < 1e-9);
+        assert_eq!(fill.time, clock.now());
+
+        let requests = requests.lock().unwrap();
+        let create = &requests[0];
+        assert_eq!(
+            (create.method.as_str(), create.target.as_str()),
+            ("POST", "/v5/order/create")
+        );
+        let body: Value = serde_json::from_str(&create.body).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "category": "spot", "symbol": "ETHUSDT", "side": "Buy",
+                "orderType": "Market", "qty": "2", "marketUnit": "baseCoin"
+            })
+        );
+        let timestamp = clock.now().timestamp_millis();
+        assert_eq!(create.headers["x-bapi-timestamp"], timestamp.to_string());
+        assert_eq!(
+            create.headers["x-bapi-sign"],
+            trade.sign(timestamp, &create.body)
+        );
+        assert_eq!(
+            requests[1].target,
+            "/v5/order/realtime?category=spot&symbol=ETHUSDT&orderId=1321003749386327552"
+        );
+    }
+
+    fn realtime(status: &str, executed: &str) -> Value {
+        json!({ "list": [{
+            "orderId": "7", "side": "Sell", "orderType": "Limit",
+            "orderStatus": status, "price": "100", "qty": "2", "avgPrice": "100",
+            "cumExecQty": executed, "cumExecValue": "100", "cumExecFee": "0.1",
+            "updatedTime": "1704067200000"
+        }]})
+    }
+
+    #[tokio::test]
+    async fn order_should_be_polled_until_final() {
+        let (config, requests) = serve(vec![
+            ("/v5/order/create", json!({ "orderId": "7" })),
+            ("/v5/order/realtime", realtime("New", "0")),
+            ("/v5/order/realtime", realtime("PartiallyFilled", "1")),
+            ("/v5/order/realtime", realtime("Filled", "2")),
+        ])
+        .await;
+        let clock = clock();
+        let start = clock.now();
+        let trade = BybitTradeService::new(&config, clock.clone()).unwrap();
+
+        let fill = trade
+            .sell_limit(&Symbol::Eth, 2.0, 100.0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fill.quantity, 2.0);
+        assert_eq!(requests.lock().unwrap().len(), 4);
+        assert_eq!(clock.now() - start, chrono::Duration::seconds(1));
+    }
+
+    #[tokio::test]
+    async fn unfinished_order_should_be_cancelled_after_timeout() {
+        let (config, requests) = serve(vec![
+            ("/v5/order/create", json!({ "orderId": "7" })),
+            ("/v5/order/realtime", realtime("PartiallyFilled", "1")),
+            ("/v5/order/cancel", json!({ "orderId": "7" })),
+        ])
+        .await;
+        let trade = BybitTradeService::new(&config, clock()).unwrap();
+
+        let fill = trade
+            .sell_limit(&Symbol::Eth, 2.0, 100.0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fill.quantity, 1.0);
+        let requests = requests.lock().unwrap();
+        let cancel = &requests[requests.len() - 2];
+        assert_eq!(cancel.target, "/v5/order/cancel");
+        assert_eq!(requests.last().unwrap().method, "GET");
+    }
+
+    #[tokio::test]
+    async fn account_and_errors_should_be_converted() {
+        let (config, _) = serve(vec![(
+            "/v5/account/wallet-balance",
+            json!({ "list": [{ "accountType": "UNIFIED", "coin": [
+                { "coin": "USDT", "walletBalance": "1000", "locked": "100" },
+                { "coin": "ETH", "walletBalance": "1.5", "locked": "" }
+            ]}]}),
+        )])
+        .await;
+        let trade = BybitTradeService::new(&config, clock()).unwrap();
+
+        let account = trade.account().await.unwrap();
+        assert_eq!(
+            account.balances[0],
+            Balance {
+                asset: "USDT".to_string(),
+                free: 900.0,
+                locked: 100.0,
+            }
+        );
+        assert_eq!(account.balances[1].free, 1.5);
+
+        let err = trade.cancel_order(&Symbol::Eth, None).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TgError>(),
+            Some(TgError::BybitError(10001, _))
+        ));
+    }
+}
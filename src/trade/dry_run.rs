@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::clock::Clock;
+use crate::grid::StateStore;
+use crate::trade::{Account, Balance, Fill, MarketService, Order, TradeService};
+use crate::{Symbol, TradeSide};
+
+/// 模拟下单记录的状态名，每个订单一行 JSON
+pub const DRY_RUN_JOURNAL: &str = "dry_run";
+
+/// 模拟下单时本应发出的订单
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct DryRunOrder {
+    pub time: DateTime<Utc>,
+    pub symbol: Symbol,
+    pub side: TradeSide,
+    /// 下单的基础资产数量，按金额买入时为空
+    pub quantity: Option<f64>,
+    /// 按金额买入时的计价资产金额
+    pub quote: Option<f64>,
+    /// 限价单的价格
+    pub limit: Option<f64>,
+    /// 视为成交的价格及数量
+    pub price: f64,
+    pub filled: f64,
+}
+
+/// 模拟下单: 订单只记录日志及 `dry_run` 文件，并按当前价格视为成交；查询转发给 `inner`，
+/// 账户余额叠加模拟成交的变化，撤单不做任何事
+pub struct DryRunTrade {
+    inner: Arc<dyn TradeService>,
+    market: Arc<dyn MarketService>,
+    store: StateStore,
+    clock: Arc<dyn Clock>,
+    /// 模拟成交引起的余额变化
+    deltas: Mutex<BTreeMap<String, f64>>,
+}
+
+impl DryRunTrade {
+    pub fn new(
+        inner: Arc<dyn TradeService>,
+        market: Arc<dyn MarketService>,
+        store: StateStore,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            inner,
+            market,
+            store,
+            clock,
+            deltas: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    async fn order(
+        &self,
+        symbol: &Symbol,
+        side: TradeSide,
+        quantity: Option<f64>,
+        quote: Option<f64>,
+        limit: Option<f64>,
+    ) -> Result<Option<Fill>> {
+        // 限价单同样以当前价格视为成交，限价只记录
+        let price = self.market.ticker_price(symbol).await?;
+        let filled = quantity.unwrap_or_else(|| quote.unwrap_or(0.0) / price);
+        let order = DryRunOrder {
+            time: self.clock.now(),
+            symbol: symbol.clone(),
+            side,
+            quantity,
+            quote,
+            limit,
+            price,
+            filled,
+        };
+        info!(
+            "模拟下单: 币种为: {},方向: {:?},数量: {:?},金额: {:?},限价: {:?},视为以{}成交{}",
+            symbol, side, quantity, quote, limit, price, filled
+        );
+        if let Err(e) = self.store.append(symbol, DRY_RUN_JOURNAL, &order) {
+            error!("币种:{},记录模拟订单失败: {}", symbol, e);
+        }
+
+        let (quote_delta, base_delta) = match side {
+            TradeSide::Buy => (-filled * price, filled),
+            TradeSide::Sell => (filled * price, -filled),
+        };
+        let mut deltas = self.deltas.lock().unwrap();
+        *deltas.entry(symbol.quote_asset().to_string()).or_default() += quote_delta;
+        *deltas.entry(symbol.base_asset().to_string()).or_default() += base_delta;
+
+        Ok(Some(Fill::new(price, filled, order.time)))
+    }
+}
+
+#[async_trait]
+impl TradeService for DryRunTrade {
+    async fn get_order(&self, symbol: &Symbol, order_id: usize) -> Result<Order> {
+        self.inner.get_order(symbol, order_id).await
+    }
+
+    async fn buy_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
+        self.order(symbol, TradeSide::Buy, Some(quantity), None, Some(price))
+            .await
+    }
+
+    async fn buy(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        self.order(symbol, TradeSide::Buy, Some(quantity), None, None)
+            .await
+    }
+
+    async fn buy_quote(&self, symbol: &Symbol, quote: f64) -> Result<Option<Fill>> {
+        self.order(symbol, TradeSide::Buy, None, Some(quote), None)
+            .await
+    }
+
+    async fn sell_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
+        self.order(symbol, TradeSide::Sell, Some(quantity), None, Some(price))
+            .await
+    }
+
+    async fn sell(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        self.order(symbol, TradeSide::Sell, Some(quantity), None, None)
+            .await
+    }
+
+    async fn account(&self) -> Result<Account> {
+        let mut account = self.inner.account().await?;
+        let deltas = self.deltas.lock().unwrap().clone();
+        for (asset, delta) in deltas {
+            match account.balances.iter_mut().find(|b| b.asset == asset) {
+                Some(balance) => balance.free += delta,
+                None => account.balances.push(Balance {
+                    asset,
+                    free: delta,
+                    locked: 0.0,
+                }),
+            }
+        }
+        Ok(account)
+    }
+
+    async fn open_orders(&self, symbol: &Symbol) -> Result<Vec<Order>> {
+        self.inner.open_orders(symbol).await
+    }
+
+    async fn cancel_order(&self, symbol: &Symbol, order_id: Option<usize>) -> Result<()> {
+        info!("模拟下单: 币种为: {},忽略撤单{:?}", symbol, order_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use crate::clock::ManualClock;
+    use crate::trade::fake::{FakeMarket, FakeTrade};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn orders_should_be_journaled_without_sending() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = StateStore::new(dir.path());
+        let market = Arc::new(FakeMarket::new([100.0, 105.0]));
+        let inner = Arc::new(FakeTrade::new(market.clone()).with_balance("USDT", 1000.0));
+        let clock = Arc::new(ManualClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ));
+        let trade = DryRunTrade::new(inner.clone(), market, store.clone(), clock.clone());
+
+        let fill = trade.buy(&Symbol::Eth, 2.0).await.unwrap().unwrap();
+        assert_eq!((fill.price, fill.quantity), (100.0, 2.0));
+        // 成交时间取自网格的时钟
+        assert_eq!(fill.time, clock.now());
+        let fill = trade.sell_limit(&Symbol::Eth, 1.0, 110.0).await.unwrap();
+        assert_eq!(fill.unwrap().price, 105.0);
+        assert!(inner.orders().is_empty());
+
+        let account = trade.account().await.unwrap();
+        let free = |asset: &str| {
+            account
+                .balances
+                .iter()
+                .find(|b| b.asset == asset)
+                .map(|b| b.free)
+        };
+        assert_eq!(free("USDT"), Some(905.0));
+        assert_eq!(free("ETH"), Some(1.0));
+
+        let journal =
+            std::fs::read_to_string(store.journal_path(&Symbol::Eth, DRY_RUN_JOURNAL)).unwrap();
+        let lines: Vec<&str> = journal.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""side":"Buy""#));
+        assert!(lines[1].contains(r#""limit":110.0"#));
+    }
+}
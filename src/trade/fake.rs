@@ -0,0 +1,310 @@
+//! 用于测试的行情及交易服务: 按脚本返回价格，记录每一笔订单，并可注入失败
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::clock::{Clock, SystemClock};
+use crate::trade::{
+    Account, Balance, Fill, Kline, KlineQuery, MarketService, Order, Ticker24h, TradeService,
+};
+use crate::{Symbol, TgError, TradeSide};
+
+/// 开高低收构成的 k 线
+pub fn bar(open: f64, high: f64, low: f64, close: f64) -> Kline {
+    Kline {
+        open_time: 0,
+        open,
+        high,
+        low,
+        close,
+        close_time: 0,
+        count: 1,
+    }
+}
+
+/// 按脚本依次返回价格的行情服务，脚本用完后一直返回最后一个价格
+#[derive(Default)]
+pub struct FakeMarket {
+    state: Mutex<MarketState>,
+}
+
+#[derive(Default)]
+struct MarketState {
+    prices: VecDeque<f64>,
+    last: Option<f64>,
+    k_lines: Vec<Kline>,
+    failures: usize,
+}
+
+impl FakeMarket {
+    pub fn new(prices: impl IntoIterator<Item = f64>) -> Self {
+        let market = Self::default();
+        market.push_prices(prices);
+        market
+    }
+
+    /// `k_lines` 按查询条件从中选出的 k 线
+    pub fn with_k_lines(self, k_lines: Vec<Kline>) -> Self {
+        self.state.lock().unwrap().k_lines = k_lines;
+        self
+    }
+
+    pub fn push_prices(&self, prices: impl IntoIterator<Item = f64>) {
+        self.state.lock().unwrap().prices.extend(prices);
+    }
+
+    /// 最近一次返回的价格
+    pub fn price(&self) -> Option<f64> {
+        self.state.lock().unwrap().last
+    }
+
+    /// 之后的 `n` 次请求返回错误
+    pub fn fail_next(&self, n: usize) {
+        self.state.lock().unwrap().failures += n;
+    }
+
+    fn check(state: &mut MarketState) -> Result<()> {
+        if state.failures > 0 {
+            state.failures -= 1;
+            return Err(TgError::Internal("Injected market failure".to_string()).into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MarketService for FakeMarket {
+    async fn ping(&self) -> Result<bool> {
+        Self::check(&mut self.state.lock().unwrap())?;
+        Ok(true)
+    }
+
+    async fn ticker_price(&self, _symbol: &Symbol) -> Result<f64> {
+        let mut state = self.state.lock().unwrap();
+        Self::check(&mut state)?;
+        if let Some(price) = state.prices.pop_front() {
+            state.last = Some(price);
+        }
+        state
+            .last
+            .ok_or_else(|| TgError::Internal("No scripted price".to_string()).into())
+    }
+
+    async fn ticker_24hr(&self, _symbol: &Symbol) -> Result<Ticker24h> {
+        Err(TgError::Internal("ticker_24hr is not supported by FakeMarket".to_string()).into())
+    }
+
+    async fn k_lines(&self, _symbol: &Symbol, query: &KlineQuery) -> Result<Vec<Kline>> {
+        let mut state = self.state.lock().unwrap();
+        Self::check(&mut state)?;
+        Ok(query.select(&state.k_lines))
+    }
+}
+
+/// 提交给 `FakeTrade` 的订单
+#[derive(Clone, Debug, PartialEq)]
+pub struct FakeOrder {
+    pub side: TradeSide,
+    /// 基础资产数量，按金额买入时为空
+    pub quantity: Option<f64>,
+    /// 按金额买入时的计价资产金额
+    pub quote: Option<f64>,
+    /// 限价单的价格
+    pub limit: Option<f64>,
+    /// 成交结果，失败或未成交时为空
+    pub fill: Option<Fill>,
+}
+
+/// 以行情的最近价格立即成交的交易服务，成交后更新余额
+pub struct FakeTrade {
+    market: Arc<FakeMarket>,
+    clock: Arc<dyn Clock>,
+    state: Mutex<TradeState>,
+}
+
+#[derive(Default)]
+struct TradeState {
+    balances: BTreeMap<String, f64>,
+    fee_rate: f64,
+    orders: Vec<FakeOrder>,
+    failures: usize,
+    rejections: usize,
+}
+
+impl FakeTrade {
+    pub fn new(market: Arc<FakeMarket>) -> Self {
+        Self {
+            market,
+            clock: Arc::new(SystemClock),
+            state: Mutex::new(TradeState::default()),
+        }
+    }
+
+    /// 成交时间取自 `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_balance(self, asset: &str, free: f64) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .balances
+            .insert(asset.to_string(), free);
+        self
+    }
+
+    /// 手续费率，以计价资产收取
+    pub fn with_fee_rate(self, fee_rate: f64) -> Self {
+        self.state.lock().unwrap().fee_rate = fee_rate;
+        self
+    }
+
+    /// 已提交的全部订单
+    pub fn orders(&self) -> Vec<FakeOrder> {
+        self.state.lock().unwrap().orders.clone()
+    }
+
+    pub fn balance(&self, asset: &str) -> f64 {
+        let state = self.state.lock().unwrap();
+        state.balances.get(asset).cloned().unwrap_or(0.0)
+    }
+
+    /// 之后的 `n` 笔订单返回错误
+    pub fn fail_next(&self, n: usize) {
+        self.state.lock().unwrap().failures += n;
+    }
+
+    /// 之后的 `n` 笔订单未成交
+    pub fn reject_next(&self, n: usize) {
+        self.state.lock().unwrap().rejections += n;
+    }
+
+    fn order(
+        &self,
+        symbol: &Symbol,
+        side: TradeSide,
+        quantity: Option<f64>,
+        quote: Option<f64>,
+        limit: Option<f64>,
+    ) -> Result<Option<Fill>> {
+        let price = limit.or_else(|| self.market.price());
+        let time = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        let result = if state.failures > 0 {
+            state.failures -= 1;
+            Err(TgError::Internal("Injected order failure".to_string()))
+        } else if state.rejections > 0 {
+            state.rejections -= 1;
+            Ok(None)
+        } else {
+            let order_id = state.orders.len() + 1;
+            price
+                .map(|price| {
+                    let base = quantity.unwrap_or_else(|| quote.unwrap_or(0.0) / price);
+                    Some(state.fill(symbol, side, price, base, order_id, time))
+                })
+                .ok_or_else(|| TgError::Internal("No market price to fill".to_string()))
+        };
+        state.orders.push(FakeOrder {
+            side,
+            quantity,
+            quote,
+            limit,
+            fill: result.as_ref().ok().cloned().flatten(),
+        });
+        Ok(result?)
+    }
+}
+
+impl TradeState {
+    fn fill(
+        &mut self,
+        symbol: &Symbol,
+        side: TradeSide,
+        price: f64,
+        quantity: f64,
+        order_id: usize,
+        time: DateTime<Utc>,
+    ) -> Fill {
+        let amount = quantity * price;
+        let fee = amount * self.fee_rate;
+        let (quote_delta, base_delta) = match side {
+            TradeSide::Buy => (-amount - fee, quantity),
+            TradeSide::Sell => (amount - fee, -quantity),
+        };
+        *self
+            .balances
+            .entry(symbol.quote_asset().to_string())
+            .or_default() += quote_delta;
+        *self
+            .balances
+            .entry(symbol.base_asset().to_string())
+            .or_default() += base_delta;
+        Fill {
+            price,
+            quantity,
+            order_id: Some(order_id),
+            fee,
+            fee_asset: Some(symbol.quote_asset().to_string()),
+            time,
+        }
+    }
+}
+
+#[async_trait]
+impl TradeService for FakeTrade {
+    async fn get_order(&self, _symbol: &Symbol, _order_id: usize) -> Result<Order> {
+        Err(TgError::Internal("get_order is not supported by FakeTrade".to_string()).into())
+    }
+
+    async fn buy_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
+        self.order(symbol, TradeSide::Buy, Some(quantity), None, Some(price))
+    }
+
+    async fn buy(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        self.order(symbol, TradeSide::Buy, Some(quantity), None, None)
+    }
+
+    async fn buy_quote(&self, symbol: &Symbol, quote: f64) -> Result<Option<Fill>> {
+        self.order(symbol, TradeSide::Buy, None, Some(quote), None)
+    }
+
+    async fn sell_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
+        self.order(symbol, TradeSide::Sell, Some(quantity), None, Some(price))
+    }
+
+    async fn sell(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        self.order(symbol, TradeSide::Sell, Some(quantity), None, None)
+    }
+
+    async fn account(&self) -> Result<Account> {
+        let state = self.state.lock().unwrap();
+        let balances = state
+            .balances
+            .iter()
+            .map(|(asset, free)| Balance {
+                asset: asset.clone(),
+                free: *free,
+                locked: 0.0,
+            })
+            .collect();
+        Ok(Account {
+            can_trade: true,
+            balances,
+        })
+    }
+
+    async fn open_orders(&self, _symbol: &Symbol) -> Result<Vec<Order>> {
+        Ok(Vec::new())
+    }
+
+    async fn cancel_order(&self, _symbol: &Symbol, _order_id: Option<usize>) -> Result<()> {
+        Ok(())
+    }
+}
@@ -2,16 +2,32 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use tracing::info;
 
-use crate::trade::binance_api_response::{QuerySpotOrder, RH24ticker, RKline, SpotAccount};
+use crate::clock::Clock;
 use crate::trade::binance_api_service::{BinanceMarketService, BinanceTradeService};
-use crate::{Symbol, TradeConfig};
+use crate::trade::bybit_api_service::{BybitMarketService, BybitTradeService};
+use crate::{Exchange, Symbol, TradeConfig, TradeSide};
 
-mod binance_api_params;
-mod binance_api_response;
+pub use self::balance::BalanceGuard;
+pub use self::binance_api_params::Interval;
+pub use self::dry_run::{DryRunOrder, DryRunTrade, DRY_RUN_JOURNAL};
+pub use self::recording::{
+    read_records, Record, Recorder, RecordingMarket, RecordingTrade, Replay,
+};
+
+mod balance;
+pub mod binance_api_params;
+pub mod binance_api_response;
 mod binance_api_service;
 mod binance_api_ws;
+mod bybit_api_service;
+mod dry_run;
+#[cfg(any(test, feature = "fake"))]
+pub mod fake;
+mod recording;
 
 /// Abstraction of Market Service
 #[async_trait]
@@ -23,28 +39,234 @@ pub trait MarketService: Send + Sync + 'static {
     async fn ticker_price(&self, symbol: &Symbol) -> Result<f64>;
 
     /// 24 hour rolling window price change statistics. Careful when accessing this with no symbol.
-    async fn ticker_24hr(&self, symbol: &Symbol) -> Result<RH24ticker>;
+    async fn ticker_24hr(&self, symbol: &Symbol) -> Result<Ticker24h>;
+
+    /// Kline/candlestick bars for a symbol, oldest first.
+    /// Klines are uniquely identified by their open time.
+    async fn k_lines(&self, symbol: &Symbol, query: &KlineQuery) -> Result<Vec<Kline>>;
+}
+
+/// 24 小时滚动窗口的行情统计
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ticker24h {
+    pub symbol: Symbol,
+    pub last_price: f64,
+    pub open_price: f64,
+    pub high_price: f64,
+    pub low_price: f64,
+    /// 涨跌幅，单位为 %
+    pub price_change_percent: f64,
+    /// 基础资产成交量
+    pub volume: f64,
+    /// 计价资产成交额
+    pub quote_volume: f64,
+}
 
-    /// Kline/candlestick bars for a symbol. Klines are uniquely identified by their open time.
-    async fn k_lines(&self, symbol: &Symbol) -> Result<Vec<RKline>>;
+/// k 线，时间为毫秒时间戳
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Kline {
+    pub open_time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub close_time: i64,
+    /// 成交笔数，交易所不提供时为0
+    pub count: usize,
+}
+
+/// k 线的查询条件，时间为毫秒时间戳
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct KlineQuery {
+    pub interval: Interval,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+impl KlineQuery {
+    /// 最近的 `limit` 根 k 线
+    pub fn latest(interval: Interval, limit: usize) -> Self {
+        Self {
+            interval,
+            start_time: None,
+            end_time: None,
+            limit: Some(limit),
+        }
+    }
+
+    /// 开盘时间在 `[start_time, end_time]` 内的 k 线，从 `start_time` 起最多返回 `limit` 根
+    pub fn range(interval: Interval, start_time: i64, end_time: Option<i64>) -> Self {
+        Self {
+            interval,
+            start_time: Some(start_time),
+            end_time,
+            limit: None,
+        }
+    }
+
+    pub fn with_limit(self, limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// 按与币安相同的规则从按时间排序的 `bars` 中选出 k 线:
+    /// 指定 `start_time` 时取最早的 `limit` 根，否则取最近的 `limit` 根
+    pub fn select(&self, bars: &[Kline]) -> Vec<Kline> {
+        let mut bars: Vec<Kline> = bars
+            .iter()
+            .filter(|b| self.start_time.is_none_or(|t| b.open_time >= t))
+            .filter(|b| self.end_time.is_none_or(|t| b.open_time <= t))
+            .cloned()
+            .collect();
+        if let Some(limit) = self.limit {
+            if self.start_time.is_some() {
+                bars.truncate(limit);
+            } else {
+                bars = bars.split_off(bars.len().saturating_sub(limit));
+            }
+        }
+        bars
+    }
+}
+
+/// 订单的成交结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fill {
+    /// 成交均价
+    pub price: f64,
+    /// 成交的基础资产数量
+    pub quantity: f64,
+    pub order_id: Option<usize>,
+    /// 手续费总额，币种为 `fee_asset`
+    pub fee: f64,
+    pub fee_asset: Option<String>,
+    pub time: DateTime<Utc>,
+}
+
+impl Fill {
+    /// `time` 成交、无手续费的成交，时间应取自网格使用的 `Clock`
+    pub fn new(price: f64, quantity: f64, time: DateTime<Utc>) -> Self {
+        Self {
+            price,
+            quantity,
+            order_id: None,
+            fee: 0.0,
+            fee_asset: None,
+            time,
+        }
+    }
+
+    /// 扣除以基础资产收取的手续费后实际到账的数量
+    pub fn net_quantity(&self, symbol: &Symbol) -> f64 {
+        match self.fee_asset.as_deref() {
+            Some(asset) if asset == symbol.base_asset() => self.quantity - self.fee,
+            _ => self.quantity,
+        }
+    }
+
+    /// 以计价资产计的手续费，其它币种(如BNB)的手续费无法换算，按0计
+    pub fn quote_fee(&self, symbol: &Symbol) -> f64 {
+        match self.fee_asset.as_deref() {
+            Some(asset) if asset == symbol.quote_asset() => self.fee,
+            Some(asset) if asset == symbol.base_asset() => self.fee * self.price,
+            _ => 0.0,
+        }
+    }
+}
+
+/// 订单类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderType {
+    Limit,
+    Market,
+    /// 止损单等网格不使用的类型
+    Other,
+}
+
+/// 订单状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Rejected,
+    Expired,
+}
+
+/// 交易所的订单
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Order {
+    pub order_id: usize,
+    pub symbol: Symbol,
+    pub side: TradeSide,
+    pub order_type: OrderType,
+    pub status: OrderStatus,
+    /// 委托价格，市价单为0
+    pub price: f64,
+    /// 委托数量
+    pub quantity: f64,
+    /// 已成交数量
+    pub executed_quantity: f64,
+    /// 已成交金额
+    pub quote_quantity: f64,
+    pub time: DateTime<Utc>,
+}
+
+/// 现货账户
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    pub can_trade: bool,
+    pub balances: Vec<Balance>,
+}
+
+/// 一种资产的余额
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Balance {
+    pub asset: String,
+    pub free: f64,
+    pub locked: f64,
 }
 
 /// Abstraction of transaction services
 #[async_trait]
 pub trait TradeService: Send + Sync + 'static {
-    async fn get_order(&self, symbol: &Symbol) -> Result<QuerySpotOrder>;
+    async fn get_order(&self, symbol: &Symbol, order_id: usize) -> Result<Order>;
 
     /// Send in a new order.
-    async fn buy_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<f64>>;
+    async fn buy_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>>;
+
+    async fn buy(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>>;
 
-    async fn buy(&self, symbol: &Symbol, quantity: f64) -> Result<Option<f64>>;
+    /// Market buy spending `quote` of the quote asset.
+    async fn buy_quote(&self, symbol: &Symbol, quote: f64) -> Result<Option<Fill>>;
 
     /// Send in a new order.
-    async fn sell_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<f64>>;
+    async fn sell_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>>;
 
-    async fn sell(&self, symbol: &Symbol, quantity: f64) -> Result<Option<f64>>;
+    async fn sell(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>>;
+
+    async fn account(&self) -> Result<Account>;
+
+    /// 当前挂单
+    async fn open_orders(&self, symbol: &Symbol) -> Result<Vec<Order>>;
+
+    /// 撤销订单，`order_id` 为空时撤销该币种的全部挂单
+    async fn cancel_order(&self, symbol: &Symbol, order_id: Option<usize>) -> Result<()>;
+}
 
-    async fn account(&self) -> Result<SpotAccount>;
+/// 按 `proxy` 配置创建 HTTP 客户端
+fn build_client(config: &TradeConfig) -> Result<reqwest::Client> {
+    let http_client = match config.proxy.as_ref() {
+        Some(proxy) => reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all(proxy)?)
+            .build()?,
+        None => reqwest::Client::new(),
+    };
+    Ok(http_client)
 }
 
 pub trait WebsocketResponse<R: serde::de::DeserializeOwned> {
@@ -53,9 +275,34 @@ pub trait WebsocketResponse<R: serde::de::DeserializeOwned> {
     fn close_stream(&mut self);
 }
 
-pub fn factory(config: &TradeConfig) -> Result<(Arc<dyn MarketService>, Arc<dyn TradeService>)> {
-    info!("Initialize Market&Trade Service: {}", config.url.as_str());
-    let m = Arc::new(BinanceMarketService::new(config)?);
-    let t = Arc::new(BinanceTradeService::new(config)?);
-    Ok((m, t))
+/// 创建 `exchange` 对应的行情及交易服务，`clock` 用于生成请求时间戳；配置了 `record` 时录制全部请求
+pub fn factory(
+    config: &TradeConfig,
+    clock: Arc<dyn Clock>,
+) -> Result<(Arc<dyn MarketService>, Arc<dyn TradeService>)> {
+    info!(
+        "Initialize Market&Trade Service: {:?} {}",
+        config.exchange,
+        config.url.as_str()
+    );
+    let (m, t): (Arc<dyn MarketService>, Arc<dyn TradeService>) = match config.exchange {
+        Exchange::Binance => (
+            Arc::new(BinanceMarketService::new(config)?),
+            Arc::new(BinanceTradeService::new(config, clock.clone())?),
+        ),
+        Exchange::Bybit => (
+            Arc::new(BybitMarketService::new(config)?),
+            Arc::new(BybitTradeService::new(config, clock.clone())?),
+        ),
+    };
+    match config.record.as_ref() {
+        Some(path) => {
+            info!("录制行情及交易请求到: {}", path);
+            let recorder = Arc::new(Recorder::create(path, clock)?);
+            let m = Arc::new(RecordingMarket::new(m, recorder.clone()));
+            let t = Arc::new(RecordingTrade::new(t, recorder));
+            Ok((m, t))
+        }
+        None => Ok((m, t)),
+    }
 }
@@ -0,0 +1,469 @@
+//! 录制与回放: 把行情及交易服务的每次请求和返回按时间写入文件，
+//! 回放时按相同顺序返回录制的结果，用于在本地重现线上网格的决策
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::error;
+
+use crate::clock::{Clock, ManualClock};
+use crate::trade::{
+    Account, Fill, Kline, KlineQuery, MarketService, Order, Ticker24h, TradeService,
+};
+use crate::{Symbol, TgError};
+
+/// 一次请求及其结果，录制文件中每行一个
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Record {
+    pub time: DateTime<Utc>,
+    pub method: String,
+    pub args: Value,
+    /// 成功时的返回值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ok: Option<Value>,
+    /// 失败时的错误信息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub err: Option<String>,
+}
+
+/// 读取录制文件
+pub fn read_records(path: impl AsRef<Path>) -> Result<Vec<Record>, TgError> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).map_err(|e| TgError::DecodeError(e.to_string())))
+        .collect()
+}
+
+/// 以 JSON 行追加写入请求记录
+pub struct Recorder {
+    file: Mutex<File>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>, clock: Arc<dyn Clock>) -> Result<Self, TgError> {
+        if let Some(dir) = path.as_ref().parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            clock,
+        })
+    }
+
+    /// 写入失败只记录日志，不影响请求本身
+    fn record<T: Serialize>(&self, method: &str, args: Value, result: &Result<T>) {
+        let (ok, err) = match result {
+            Ok(v) => (serde_json::to_value(v).ok(), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let record = Record {
+            time: self.clock.now(),
+            method: method.to_string(),
+            args,
+            ok,
+            err,
+        };
+        let written = serde_json::to_string(&record)
+            .map_err(|e| TgError::Internal(e.to_string()))
+            .and_then(|s| Ok(writeln!(self.file.lock().unwrap(), "{}", s)?));
+        if let Err(e) = written {
+            error!("录制请求{}失败: {}", method, e);
+        }
+    }
+}
+
+/// 录制请求的行情服务
+pub struct RecordingMarket {
+    inner: Arc<dyn MarketService>,
+    recorder: Arc<Recorder>,
+}
+
+impl RecordingMarket {
+    pub fn new(inner: Arc<dyn MarketService>, recorder: Arc<Recorder>) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+#[async_trait]
+impl MarketService for RecordingMarket {
+    async fn ping(&self) -> Result<bool> {
+        let result = self.inner.ping().await;
+        self.recorder.record("ping", json!({}), &result);
+        result
+    }
+
+    async fn ticker_price(&self, symbol: &Symbol) -> Result<f64> {
+        let result = self.inner.ticker_price(symbol).await;
+        self.recorder
+            .record("ticker_price", json!({ "symbol": symbol }), &result);
+        result
+    }
+
+    async fn ticker_24hr(&self, symbol: &Symbol) -> Result<Ticker24h> {
+        let result = self.inner.ticker_24hr(symbol).await;
+        self.recorder
+            .record("ticker_24hr", json!({ "symbol": symbol }), &result);
+        result
+    }
+
+    async fn k_lines(&self, symbol: &Symbol, query: &KlineQuery) -> Result<Vec<Kline>> {
+        let result = self.inner.k_lines(symbol, query).await;
+        let args = json!({ "symbol": symbol, "query": query });
+        self.recorder.record("k_lines", args, &result);
+        result
+    }
+}
+
+/// 录制请求的交易服务
+pub struct RecordingTrade {
+    inner: Arc<dyn TradeService>,
+    recorder: Arc<Recorder>,
+}
+
+impl RecordingTrade {
+    pub fn new(inner: Arc<dyn TradeService>, recorder: Arc<Recorder>) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+#[async_trait]
+impl TradeService for RecordingTrade {
+    async fn get_order(&self, symbol: &Symbol, order_id: usize) -> Result<Order> {
+        let result = self.inner.get_order(symbol, order_id).await;
+        let args = json!({ "symbol": symbol, "order_id": order_id });
+        self.recorder.record("get_order", args, &result);
+        result
+    }
+
+    async fn buy_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
+        let result = self.inner.buy_limit(symbol, quantity, price).await;
+        let args = json!({ "symbol": symbol, "quantity": quantity, "price": price });
+        self.recorder.record("buy_limit", args, &result);
+        result
+    }
+
+    async fn buy(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        let result = self.inner.buy(symbol, quantity).await;
+        let args = json!({ "symbol": symbol, "quantity": quantity });
+        self.recorder.record("buy", args, &result);
+        result
+    }
+
+    async fn buy_quote(&self, symbol: &Symbol, quote: f64) -> Result<Option<Fill>> {
+        let result = self.inner.buy_quote(symbol, quote).await;
+        let args = json!({ "symbol": symbol, "quote": quote });
+        self.recorder.record("buy_quote", args, &result);
+        result
+    }
+
+    async fn sell_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
+        let result = self.inner.sell_limit(symbol, quantity, price).await;
+        let args = json!({ "symbol": symbol, "quantity": quantity, "price": price });
+        self.recorder.record("sell_limit", args, &result);
+        result
+    }
+
+    async fn sell(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        let result = self.inner.sell(symbol, quantity).await;
+        let args = json!({ "symbol": symbol, "quantity": quantity });
+        self.recorder.record("sell", args, &result);
+        result
+    }
+
+    async fn account(&self) -> Result<Account> {
+        let result = self.inner.account().await;
+        self.recorder.record("account", json!({}), &result);
+        result
+    }
+
+    async fn open_orders(&self, symbol: &Symbol) -> Result<Vec<Order>> {
+        let result = self.inner.open_orders(symbol).await;
+        self.recorder
+            .record("open_orders", json!({ "symbol": symbol }), &result);
+        result
+    }
+
+    async fn cancel_order(&self, symbol: &Symbol, order_id: Option<usize>) -> Result<()> {
+        let result = self.inner.cancel_order(symbol, order_id).await;
+        let args = json!({ "symbol": symbol, "order_id": order_id });
+        self.recorder.record("cancel_order", args, &result);
+        result
+    }
+}
+
+/// 按录制顺序返回结果的行情及交易服务。每次请求必须与同一币种的下一条记录的方法及参数相同，
+/// 否则说明决策与录制时不同，返回错误。多个币种的网格并发请求，先后顺序不固定，
+/// 因此每个币种单独排队，不带币种的请求(如 `account`)共用一个队列；
+/// `clock` 会被设置为记录的时间，只向前推进
+pub struct Replay {
+    records: Mutex<HashMap<Option<Value>, VecDeque<Record>>>,
+    clock: Arc<ManualClock>,
+}
+
+impl Replay {
+    pub fn new(records: Vec<Record>, clock: Arc<ManualClock>) -> Self {
+        let mut queues: HashMap<Option<Value>, VecDeque<Record>> = HashMap::new();
+        for record in records {
+            queues
+                .entry(record.args.get("symbol").cloned())
+                .or_default()
+                .push_back(record);
+        }
+        Self {
+            records: Mutex::new(queues),
+            clock,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>, clock: Arc<ManualClock>) -> Result<Self, TgError> {
+        Ok(Self::new(read_records(path)?, clock))
+    }
+
+    /// 尚未回放的记录数
+    pub fn remaining(&self) -> usize {
+        self.records.lock().unwrap().values().map(|q| q.len()).sum()
+    }
+
+    fn next<T: DeserializeOwned>(&self, method: &str, args: Value) -> Result<T> {
+        let record = self
+            .records
+            .lock()
+            .unwrap()
+            .get_mut(&args.get("symbol").cloned())
+            .and_then(|q| q.pop_front())
+            .ok_or_else(|| TgError::Internal(format!("Replay exhausted at {} {}", method, args)))?;
+        if record.method != method || record.args != args {
+            return Err(TgError::Internal(format!(
+                "Replay diverged: recorded {} {}, requested {} {}",
+                record.method, record.args, method, args
+            ))
+            .into());
+        }
+        if record.time > self.clock.now() {
+            self.clock.set(record.time);
+        }
+        match (record.ok, record.err) {
+            // Kline 反序列化时借用字符串，不能直接从 Value 转换
+            (Some(ok), _) => Ok(serde_json::from_str(&ok.to_string())?),
+            (None, Some(err)) => Err(TgError::Internal(err).into()),
+            (None, None) => Ok(serde_json::from_value(Value::Null)?),
+        }
+    }
+}
+
+#[async_trait]
+impl MarketService for Replay {
+    async fn ping(&self) -> Result<bool> {
+        self.next("ping", json!({}))
+    }
+
+    async fn ticker_price(&self, symbol: &Symbol) -> Result<f64> {
+        self.next("ticker_price", json!({ "symbol": symbol }))
+    }
+
+    async fn ticker_24hr(&self, symbol: &Symbol) -> Result<Ticker24h> {
+        self.next("ticker_24hr", json!({ "symbol": symbol }))
+    }
+
+    async fn k_lines(&self, symbol: &Symbol, query: &KlineQuery) -> Result<Vec<Kline>> {
+        self.next("k_lines", json!({ "symbol": symbol, "query": query }))
+    }
+}
+
+#[async_trait]
+impl TradeService for Replay {
+    async fn get_order(&self, symbol: &Symbol, order_id: usize) -> Result<Order> {
+        self.next(
+            "get_order",
+            json!({ "symbol": symbol, "order_id": order_id }),
+        )
+    }
+
+    async fn buy_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
+        let args = json!({ "symbol": symbol, "quantity": quantity, "price": price });
+        self.next("buy_limit", args)
+    }
+
+    async fn buy(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        self.next("buy", json!({ "symbol": symbol, "quantity": quantity }))
+    }
+
+    async fn buy_quote(&self, symbol: &Symbol, quote: f64) -> Result<Option<Fill>> {
+        self.next("buy_quote", json!({ "symbol": symbol, "quote": quote }))
+    }
+
+    async fn sell_limit(&self, symbol: &Symbol, quantity: f64, price: f64) -> Result<Option<Fill>> {
+        let args = json!({ "symbol": symbol, "quantity": quantity, "price": price });
+        self.next("sell_limit", args)
+    }
+
+    async fn sell(&self, symbol: &Symbol, quantity: f64) -> Result<Option<Fill>> {
+        self.next("sell", json!({ "symbol": symbol, "quantity": quantity }))
+    }
+
+    async fn account(&self) -> Result<Account> {
+        self.next("account", json!({}))
+    }
+
+    async fn open_orders(&self, symbol: &Symbol) -> Result<Vec<Order>> {
+        self.next("open_orders", json!({ "symbol": symbol }))
+    }
+
+    async fn cancel_order(&self, symbol: &Symbol, order_id: Option<usize>) -> Result<()> {
+        self.next(
+            "cancel_order",
+            json!({ "symbol": symbol, "order_id": order_id }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::TimeZone;
+
+    use crate::event::EventBus;
+    use crate::grid::{self, GridContext, GridRegistry, StateStore};
+    use crate::risk::RiskManager;
+    use crate::trade::fake::{bar, FakeMarket, FakeTrade};
+    use crate::trade::BalanceGuard;
+    use crate::{Coin, RiskConfig};
+
+    use super::*;
+
+    const PRICES: [f64; 6] = [102.0, 95.0, 97.0, 106.0, 99.0, 112.0];
+
+    fn coin(quantity: f64) -> Coin {
+        toml::from_str(&format!(
+            r#"
+            buy_price = 100
+            sell_price = 110
+            quantity = {}
+            "#,
+            quantity
+        ))
+        .unwrap()
+    }
+
+    /// 用 `market`/`trade` 运行固定网格，请求录制到 `path`
+    async fn run(
+        market: Arc<dyn MarketService>,
+        trade: Arc<dyn TradeService>,
+        clock: Arc<dyn Clock>,
+        coin: &Coin,
+        path: &Path,
+    ) -> Result<()> {
+        let recorder = Arc::new(Recorder::create(path, clock.clone())?);
+        let market: Arc<dyn MarketService> =
+            Arc::new(RecordingMarket::new(market, recorder.clone()));
+        let trade: Arc<dyn TradeService> = Arc::new(RecordingTrade::new(trade, recorder));
+        let state = tempfile::tempdir()?;
+        let events = EventBus::default();
+        let ctx = GridContext {
+            symbol: Symbol::Eth,
+            market: market.clone(),
+            trade: trade.clone(),
+            events: events.clone(),
+            store: StateStore::new(state.path()),
+            risk: Arc::new(RiskManager::new(
+                RiskConfig::default(),
+                events,
+                clock.clone(),
+            )),
+            balance: Arc::new(BalanceGuard::new(trade, BTreeMap::new(), clock.clone())),
+            clock,
+        };
+        let mut grid = grid::factory(&GridRegistry::default(), ctx, coin)?;
+        for _ in PRICES {
+            let price = market.ticker_price(&Symbol::Eth).await?;
+            grid.execute(price).await?;
+        }
+        Ok(())
+    }
+
+    fn start_clock() -> Arc<ManualClock> {
+        Arc::new(ManualClock::new(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn replay_should_repeat_recorded_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorded = dir.path().join("recorded.jsonl");
+        let replayed = dir.path().join("replayed.jsonl");
+
+        let clock = start_clock();
+        let market =
+            Arc::new(FakeMarket::new(PRICES).with_k_lines(vec![bar(100.0, 105.0, 100.0, 102.0)]));
+        let trade = Arc::new(
+            FakeTrade::new(market.clone())
+                .with_clock(clock.clone())
+                .with_balance("USDT", 1000.0),
+        );
+        run(market, trade.clone(), clock, &coin(1.0), &recorded)
+            .await
+            .unwrap();
+        assert!(!trade.orders().is_empty());
+
+        let clock = start_clock();
+        let replay = Arc::new(Replay::load(&recorded, clock.clone()).unwrap());
+        run(replay.clone(), replay.clone(), clock, &coin(1.0), &replayed)
+            .await
+            .unwrap();
+        assert_eq!(replay.remaining(), 0);
+        assert_eq!(
+            read_records(&recorded).unwrap(),
+            read_records(&replayed).unwrap()
+        );
+
+        // 参数不同时决策不同，回放报错
+        let clock = start_clock();
+        let replay = Arc::new(Replay::load(&recorded, clock.clone()).unwrap());
+        let diverged = dir.path().join("diverged.jsonl");
+        let err = run(replay.clone(), replay, clock, &coin(2.0), &diverged)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("diverged"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn symbols_should_be_replayed_independently() {
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let record = |symbol: Symbol, price: f64, minutes: i64| Record {
+            time: time + chrono::Duration::minutes(minutes),
+            method: "ticker_price".to_string(),
+            args: json!({ "symbol": symbol }),
+            ok: Some(json!(price)),
+            err: None,
+        };
+        let records = vec![
+            record(Symbol::Eth, 100.0, 1),
+            record(Symbol::Btc, 20000.0, 2),
+            record(Symbol::Eth, 101.0, 3),
+        ];
+        let clock = start_clock();
+        let replay = Replay::new(records, clock.clone());
+
+        // 与录制时的先后顺序不同
+        assert_eq!(replay.ticker_price(&Symbol::Eth).await.unwrap(), 100.0);
+        assert_eq!(replay.ticker_price(&Symbol::Eth).await.unwrap(), 101.0);
+        assert_eq!(replay.ticker_price(&Symbol::Btc).await.unwrap(), 20000.0);
+        assert_eq!(replay.remaining(), 0);
+        assert_eq!(clock.now(), time + chrono::Duration::minutes(3));
+        assert!(replay.ticker_price(&Symbol::Btc).await.is_err());
+    }
+}
@@ -0,0 +1,503 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+use tracing_subscriber::filter::LevelFilter;
+
+use crate::event::EVENT_KINDS;
+use crate::grid::{GridRegistry, FIXED_STRATEGY, LEVEL_STRATEGY, REVERSE_STRATEGY};
+use crate::{
+    Coin, Compare, Indicator, LevelGrid, Matching, NotifyKind, ReverseGrid, ServerConfig, Sizing,
+    StopConfig, TgError, TradeConfig,
+};
+
+/// 配置中的一个问题，`path` 为 TOML 中的位置，如 `coin.eth.quantity`
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct ConfigIssue {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// 收集配置问题
+#[derive(Default)]
+struct Issues(Vec<ConfigIssue>);
+
+impl Issues {
+    fn push(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(ConfigIssue {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    /// `ok` 为 false 时记录问题
+    fn check(&mut self, ok: bool, path: impl Into<String>, message: impl Into<String>) {
+        if !ok {
+            self.push(path, message);
+        }
+    }
+
+    fn positive(&mut self, value: f64, path: impl Into<String>) {
+        self.check(value > 0.0, path, format!("must be > 0, got {}", value));
+    }
+
+    /// 比例须在 (0, 100%] 之间
+    fn ratio(&mut self, value: f64, path: impl Into<String>) {
+        self.check(
+            value > 0.0 && value <= 1.0,
+            path,
+            format!("must be within (0%, 100%], got {}%", value * 100.0),
+        );
+    }
+}
+
+impl ServerConfig {
+    /// 检查配置的取值，返回发现的全部问题，`strategy` 只能为内置策略
+    pub fn validate(&self) -> Vec<ConfigIssue> {
+        self.validate_with(&GridRegistry::default())
+    }
+
+    /// 检查配置的取值，`strategy` 必须已在 `registry` 中注册
+    pub fn validate_with(&self, registry: &GridRegistry) -> Vec<ConfigIssue> {
+        let mut issues = Issues::default();
+
+        validate_trade(&mut issues, "trade", &self.trade);
+        for (name, account) in self.accounts.iter() {
+            validate_trade(&mut issues, &format!("accounts.{}", name), account);
+        }
+
+        for (symbol, coin) in self.coin.coins() {
+            let path = format!("coin.{}", symbol.base_asset().to_lowercase());
+            issues.check(
+                registry.contains(&coin.strategy),
+                format!("{}.strategy", path),
+                format!("unknown grid strategy '{}'", coin.strategy),
+            );
+            validate_coin(&mut issues, &path, coin);
+            if let Some(account) = coin.account.as_ref() {
+                issues.check(
+                    self.accounts.contains_key(account),
+                    format!("{}.account", path),
+                    format!("unknown account: {}", account),
+                );
+            }
+        }
+
+        let log = &self.log;
+        if LevelFilter::from_str(&log.log_level).is_err() {
+            issues.push(
+                "log.log_level",
+                format!(
+                    "unknown level '{}', expected trace/debug/info/warn/error/off",
+                    log.log_level
+                ),
+            );
+        }
+        issues.check(
+            !log.enable_log_file || !log.path.trim().is_empty(),
+            "log.path",
+            "must not be empty when enable_log_file = true",
+        );
+
+        let risk = &self.risk;
+        if let Some(v) = risk.max_capital {
+            issues.positive(v, "risk.max_capital");
+        }
+        if let Some(v) = risk.max_daily_loss {
+            issues.positive(v, "risk.max_daily_loss");
+        }
+        issues.check(
+            risk.max_lots_per_coin != Some(0),
+            "risk.max_lots_per_coin",
+            "must be > 0",
+        );
+        issues.check(
+            risk.max_orders_per_hour != Some(0),
+            "risk.max_orders_per_hour",
+            "must be > 0",
+        );
+        issues.check(
+            !self.state.path.trim().is_empty(),
+            "state.path",
+            "must not be empty",
+        );
+
+        for (i, notify) in self.notify.iter().enumerate() {
+            let path = format!("notify[{}]", i);
+            match notify.kind {
+                NotifyKind::Webhook => match notify.url.as_deref() {
+                    Some(url) => {
+                        if let Err(e) = reqwest::Url::parse(url) {
+                            issues.push(format!("{}.url", path), format!("invalid url: {}", e));
+                        }
+                    }
+                    None => issues.push(format!("{}.url", path), "required for Webhook"),
+                },
+                NotifyKind::Exec => issues.check(
+                    notify
+                        .command
+                        .as_deref()
+                        .is_some_and(|c| !c.trim().is_empty()),
+                    format!("{}.command", path),
+                    "required for Exec",
+                ),
+            }
+            for event in notify.events.iter() {
+                issues.check(
+                    EVENT_KINDS.contains(&event.as_str()),
+                    format!("{}.events", path),
+                    format!("unknown event '{}'", event),
+                );
+            }
+            issues.check(
+                notify.rate_limit > 0,
+                format!("{}.rate_limit", path),
+                "must be > 0",
+            );
+        }
+
+        issues.0
+    }
+
+    /// 按 `registry` 检查配置，有问题时以 `TgError::InvalidConfig` 返回全部问题
+    pub fn check(&self, registry: &GridRegistry) -> Result<(), TgError> {
+        let issues = self.validate_with(registry);
+        if issues.is_empty() {
+            return Ok(());
+        }
+        Err(TgError::InvalidConfig(issues))
+    }
+}
+
+fn validate_trade(issues: &mut Issues, path: &str, trade: &TradeConfig) {
+    if let Err(e) = reqwest::Url::parse(&trade.url) {
+        issues.push(format!("{}.url", path), format!("invalid url: {}", e));
+    }
+    issues.check(
+        !trade.key.expose().trim().is_empty(),
+        format!("{}.key", path),
+        "must not be empty",
+    );
+    issues.check(
+        !trade.secret.expose().trim().is_empty(),
+        format!("{}.secret", path),
+        "must not be empty",
+    );
+    for (asset, amount) in trade.reserve.iter() {
+        issues.check(
+            *amount >= 0.0,
+            format!("{}.reserve.{}", path, asset),
+            format!("must be >= 0, got {}", amount),
+        );
+    }
+}
+
+fn validate_coin(issues: &mut Issues, path: &str, coin: &Coin) {
+    match coin.sizing {
+        Sizing::Base | Sizing::Scaled { .. } => {
+            issues.positive(coin.quantity, format!("{}.quantity", path))
+        }
+        _ => issues.check(
+            coin.quantity >= 0.0,
+            format!("{}.quantity", path),
+            format!("must be >= 0, got {}", coin.quantity),
+        ),
+    }
+    match coin.sizing {
+        Sizing::Base => {}
+        Sizing::Quote { amount } => issues.positive(amount, format!("{}.sizing.amount", path)),
+        Sizing::Percent { ratio } => issues.ratio(ratio, format!("{}.sizing.ratio", path)),
+        Sizing::Scaled { multiplier } => {
+            issues.positive(multiplier, format!("{}.sizing.multiplier", path))
+        }
+    }
+
+    match coin.strategy.as_str() {
+        FIXED_STRATEGY | REVERSE_STRATEGY => {
+            if coin.strategy == FIXED_STRATEGY {
+                issues.positive(coin.buy_price, format!("{}.buy_price", path));
+            }
+            issues.positive(coin.sell_price, format!("{}.sell_price", path));
+            issues.check(
+                coin.buy_price < coin.sell_price,
+                format!("{}.buy_price", path),
+                format!(
+                    "must be lower than sell_price ({} >= {})",
+                    coin.buy_price, coin.sell_price
+                ),
+            );
+            issues.ratio(coin.profit_ratio, format!("{}.profit_ratio", path));
+            issues.ratio(
+                coin.double_throw_ratio,
+                format!("{}.double_throw_ratio", path),
+            );
+        }
+        _ => {}
+    }
+    let params = format!("{}.params", path);
+    match coin.strategy.as_str() {
+        LEVEL_STRATEGY => match coin.params::<LevelGrid>() {
+            Ok(level) => {
+                issues.check(
+                    level.levels >= 2,
+                    format!("{}.levels", params),
+                    "must be >= 2",
+                );
+                issues.positive(level.lower_price, format!("{}.lower_price", params));
+                issues.check(
+                    level.lower_price < level.upper_price,
+                    format!("{}.upper_price", params),
+                    "must be higher than lower_price",
+                );
+            }
+            Err(e) => issues.push(params, e.to_string()),
+        },
+        REVERSE_STRATEGY => match coin.params::<ReverseGrid>() {
+            Ok(reverse) => issues.check(
+                reverse.inventory >= coin.quantity,
+                format!("{}.inventory", params),
+                "must be >= quantity",
+            ),
+            Err(e) => issues.push(params, e.to_string()),
+        },
+        _ => {}
+    }
+    // 反向网格每次卖出固定数量，并总是买回最近卖出的一份
+    if coin.strategy == REVERSE_STRATEGY {
+        issues.check(
+            coin.sizing == Sizing::Base,
+            format!("{}.sizing", path),
+            format!("is not supported by strategy '{}'", REVERSE_STRATEGY),
+        );
+        issues.check(
+            matches!(coin.matching, None | Some(Matching::Lifo)),
+            format!("{}.matching", path),
+            format!("is not supported by strategy '{}'", REVERSE_STRATEGY),
+        );
+    }
+    // 经典网格的每一份都在其上一个价位卖出
+    if coin.strategy == LEVEL_STRATEGY {
+        issues.check(
+            matches!(coin.matching, None | Some(Matching::Pair)),
+            format!("{}.matching", path),
+            format!("is not supported by strategy '{}'", LEVEL_STRATEGY),
+        );
+    }
+
+    if let Some(lower) = coin.lower_limit {
+        issues.positive(lower, format!("{}.lower_limit", path));
+    }
+    if let (Some(lower), Some(upper)) = (coin.lower_limit, coin.upper_limit) {
+        issues.check(
+            lower < upper,
+            format!("{}.upper_limit", path),
+            "must be higher than lower_limit",
+        );
+    }
+    // 价格区间目前只在固定网格中实现，其它策略配置时报错而不是忽略
+    if matches!(coin.strategy.as_str(), LEVEL_STRATEGY | REVERSE_STRATEGY) {
+        for (limit, field) in [
+            (coin.lower_limit, "lower_limit"),
+            (coin.upper_limit, "upper_limit"),
+        ] {
+            issues.check(
+                limit.is_none(),
+                format!("{}.{}", path, field),
+                format!("is not supported by strategy '{}'", coin.strategy),
+            );
+        }
+    }
+    if let Some(stop) = coin.stop.as_ref() {
+        // 止损/止盈目前只在固定网格中实现，其它策略配置时报错而不是忽略
+        issues.check(
+            !matches!(coin.strategy.as_str(), LEVEL_STRATEGY | REVERSE_STRATEGY),
+            format!("{}.stop", path),
+            format!("is not supported by strategy '{}'", coin.strategy),
+        );
+        validate_stop(issues, &format!("{}.stop", path), stop);
+    }
+
+    for (i, rule) in coin.filters.iter().enumerate() {
+        // 空条件恒成立，会一直阻止该方向的下单
+        issues.check(
+            !rule.when.is_empty(),
+            format!("{}.filters[{}].when", path, i),
+            "must not be empty",
+        );
+        for (j, condition) in rule.when.iter().enumerate() {
+            let path = format!("{}.filters[{}].when[{}]", path, i, j);
+            match condition.indicator {
+                Indicator::Sma { period }
+                | Indicator::Ema { period }
+                | Indicator::Rsi { period } => {
+                    issues.check(period > 0, format!("{}.period", path), "must be > 0")
+                }
+                Indicator::MacdHistogram { fast, slow, signal } => {
+                    issues.check(
+                        fast > 0 && fast < slow,
+                        format!("{}.fast", path),
+                        "must be > 0 and lower than slow",
+                    );
+                    issues.check(signal > 0, format!("{}.signal", path), "must be > 0");
+                }
+            }
+            if matches!(condition.compare, Compare::Above | Compare::Below) {
+                issues.check(
+                    condition.value.is_some(),
+                    format!("{}.value", path),
+                    format!("is required by {:?}", condition.compare),
+                );
+            }
+        }
+    }
+}
+
+fn validate_stop(issues: &mut Issues, path: &str, stop: &StopConfig) {
+    if let Some(ratio) = stop.stop_loss_ratio {
+        issues.check(
+            ratio > 0.0 && ratio < 1.0,
+            format!("{}.stop_loss_ratio", path),
+            format!("must be within (0%, 100%), got {}%", ratio * 100.0),
+        );
+    }
+    if let Some(price) = stop.stop_loss_price {
+        issues.positive(price, format!("{}.stop_loss_price", path));
+    }
+    if let Some(profit) = stop.take_profit {
+        issues.positive(profit, format!("{}.take_profit", path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixture_should_be_valid() {
+        let config = ServerConfig::from_str(include_str!("../fixtures/tgs.conf")).unwrap();
+        assert_eq!(config.validate(), vec![]);
+        assert!(config.check(&GridRegistry::default()).is_ok());
+    }
+
+    #[test]
+    fn strategy_should_be_registered() {
+        let mut config = ServerConfig::from_str(include_str!("../fixtures/tgs.conf")).unwrap();
+        config.coin.eth.as_mut().unwrap().strategy = "martingale".into();
+        let paths: Vec<String> = config.validate().into_iter().map(|i| i.path).collect();
+        assert_eq!(paths, vec!["coin.eth.strategy"]);
+
+        let mut registry = GridRegistry::default();
+        registry.register("martingale", |_, _| unimplemented!());
+        assert!(config.check(&registry).is_ok());
+    }
+
+    #[test]
+    fn all_issues_should_be_collected() {
+        let config = ServerConfig::from_str(
+            r#"
+            [trade]
+            key = ''
+            secret = 'x'
+            url = 'https://testnet.binance.vision/api/v3/'
+
+            [accounts.sub1]
+            key = 'x'
+            secret = ''
+            url = 'https://testnet.binance.vision/api/v3/'
+
+            [coin.eth]
+            account = 'sub2'
+            buy_price = 4000
+            sell_price = 3800
+            profit_ratio = '0%'
+            double_throw_ratio = '120%'
+            quantity = -1
+            [[coin.eth.filters]]
+            side = 'Buy'
+            when = [{ indicator = 'Rsi', compare = 'Below' }]
+            [[coin.eth.filters]]
+            side = 'Sell'
+            when = []
+
+            [coin.bnb]
+            strategy = 'reverse'
+            buy_price = 0
+            sell_price = 300
+            profit_ratio = '2%'
+            double_throw_ratio = '2%'
+            quantity = 1
+            sizing = { mode = 'Quote', amount = 20 }
+            matching = 'Fifo'
+            params = { inventory = 5 }
+
+            [coin.btc]
+            strategy = 'level'
+            quantity = 0.001
+            matching = 'Lifo'
+            lower_limit = 10000
+            [coin.btc.params]
+            lower_price = 30000
+            upper_price = 20000
+            levels = 1
+            [coin.btc.stop]
+            stop_loss_ratio = '5%'
+
+            [log]
+            enable_log_file = false
+            log_level = 'verbose'
+            path = '/tmp/tgs-log'
+            rotation = 'Daily'
+
+            [[notify]]
+            kind = 'Webhook'
+            events = ['Filled']
+            [[notify]]
+            kind = 'Exec'
+            command = 'notify.sh'
+            rate_limit = 0
+            "#,
+        )
+        .unwrap();
+        let paths: Vec<String> = config.validate().into_iter().map(|i| i.path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "trade.key",
+                "accounts.sub1.secret",
+                "coin.eth.quantity",
+                "coin.eth.buy_price",
+                "coin.eth.profit_ratio",
+                "coin.eth.double_throw_ratio",
+                "coin.eth.filters[0].when[0].value",
+                "coin.eth.filters[1].when",
+                "coin.eth.account",
+                "coin.bnb.sizing",
+                "coin.bnb.matching",
+                "coin.btc.params.levels",
+                "coin.btc.params.upper_price",
+                "coin.btc.matching",
+                "coin.btc.lower_limit",
+                "coin.btc.stop",
+                "log.log_level",
+                "notify[0].url",
+                "notify[0].events",
+                "notify[1].rate_limit",
+            ]
+        );
+        match config.check(&GridRegistry::default()) {
+            Err(TgError::InvalidConfig(issues)) => assert_eq!(issues, config.validate()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let err = config
+            .check(&GridRegistry::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("coin.eth.buy_price: must be lower than sell_price"));
+    }
+}